@@ -0,0 +1,302 @@
+use proc_macro::TokenStream;
+use proc_macro_crate::{FoundCrate, crate_name};
+use proc_macro2::{Span, TokenStream as TokenStream2};
+use quote::{format_ident, quote};
+use syn::{Data, DeriveInput, Expr, ExprLit, Fields, Ident, Lit, LitInt, Type, parse_macro_input, spanned::Spanned};
+
+/// Implements `AttributeDescriptions` and `BindingDescriptions` for a vertex struct.
+///
+/// Every field becomes one attribute, locations are assigned in declaration order.
+/// Field types map to formats: `f32`, `i32`, `u32`, `i16`, `u16`, `i8`, `u8` and arrays
+/// of up to 4 of them. Arrays of those arrays, e.g. `[[f32; 4]; 4]`, take one location per row.
+///
+/// Struct attributes:
+/// - `#[vertex(binding = 1)]` vertex buffer binding, 0 by default
+/// - `#[vertex(instance)]` advance per instance instead of per vertex
+/// - `#[vertex(location = 4)]` first location, 0 by default
+///
+/// Field attributes:
+/// - `#[vertex(normalized)]` read integers as `UNORM`/`SNORM` floats
+/// - `#[vertex(format = R16G16_SFLOAT)]` explicit `vk::Format` for any other type
+/// - `#[vertex(location = 2)]` location of this field, following fields continue from it
+///
+/// The generated code refers to the engine crate by the name it has in the user's manifest,
+/// or `crate` inside the engine itself.
+#[proc_macro_derive(VertexAttributes, attributes(vertex))]
+pub fn derive_vertex_attributes(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    expand(input)
+        .unwrap_or_else(|e| e.to_compile_error())
+        .into()
+}
+
+struct StructAttrs {
+    binding: u32,
+    instance: bool,
+    location: u32,
+}
+
+#[derive(Default)]
+struct FieldAttrs {
+    normalized: bool,
+    format: Option<Ident>,
+    location: Option<u32>,
+}
+
+fn expand(input: DeriveInput) -> syn::Result<TokenStream2> {
+
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+
+    let Data::Struct(data) = &input.data else {
+        return Err(syn::Error::new(input.span(), "VertexAttributes can only be derived for structs"));
+    };
+
+    let Fields::Named(fields) = &data.fields else {
+        return Err(syn::Error::new(data.fields.span(), "VertexAttributes needs named fields"));
+    };
+
+    let attrs = struct_attrs(&input)?;
+    let binding = attrs.binding;
+    let mut location = attrs.location;
+
+    let mut attributes = vec![];
+
+    for field in &fields.named {
+        let ident = field.ident.as_ref().unwrap();
+        let field_attrs = field_attrs(field)?;
+
+        if let Some(first) = field_attrs.location {
+            location = first;
+        }
+
+        let (rows, row_size, format) = match &field_attrs.format {
+            Some(format) => (1, 0, format.clone()),
+            None => field_format(&field.ty, field_attrs.normalized)?,
+        };
+
+        for row in 0..rows {
+            // Rows of a matrix are tightly packed, one location each
+            let row_offset = row * row_size;
+
+            attributes.push(quote! {
+                ::ash::vk::VertexInputAttributeDescription {
+                    location: #location,
+                    binding: #binding,
+                    format: ::ash::vk::Format::#format,
+                    offset: (::std::mem::offset_of!(Self, #ident) + #row_offset) as u32,
+                }
+            });
+
+            location += 1;
+        }
+    }
+
+    let engine = engine_path()?;
+
+    let input_rate = if attrs.instance {
+        format_ident!("INSTANCE")
+    } else {
+        format_ident!("VERTEX")
+    };
+
+    Ok(quote! {
+        impl #impl_generics #engine::AttributeDescriptions for #name #ty_generics #where_clause {
+            fn attr_desc() -> Vec<::ash::vk::VertexInputAttributeDescription> {
+                vec![#(#attributes),*]
+            }
+        }
+
+        impl #impl_generics #engine::BindingDescriptions for #name #ty_generics #where_clause {
+            fn bind_desc() -> Vec<::ash::vk::VertexInputBindingDescription> {
+                vec![::ash::vk::VertexInputBindingDescription {
+                    binding: #binding,
+                    stride: ::std::mem::size_of::<Self>() as u32,
+                    input_rate: ::ash::vk::VertexInputRate::#input_rate,
+                }]
+            }
+        }
+    })
+}
+
+/// Path of the engine crate as seen from the deriving crate
+fn engine_path() -> syn::Result<TokenStream2> {
+    match crate_name("banan") {
+        Ok(FoundCrate::Itself) => Ok(quote!(crate)),
+        Ok(FoundCrate::Name(name)) => {
+            let name = Ident::new(&name, Span::call_site());
+            Ok(quote!(::#name))
+        }
+        Err(e) => Err(syn::Error::new(Span::call_site(), format!("VertexAttributes needs the `banan` crate: {}", e))),
+    }
+}
+
+fn struct_attrs(input: &DeriveInput) -> syn::Result<StructAttrs> {
+    let mut attrs = StructAttrs { binding: 0, instance: false, location: 0 };
+
+    for attr in input.attrs.iter().filter(|a| a.path().is_ident("vertex")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("binding") {
+                attrs.binding = meta.value()?.parse::<LitInt>()?.base10_parse()?;
+            } else if meta.path.is_ident("instance") {
+                attrs.instance = true;
+            } else if meta.path.is_ident("location") {
+                attrs.location = meta.value()?.parse::<LitInt>()?.base10_parse()?;
+            } else {
+                return Err(meta.error("expected `binding`, `instance` or `location`"));
+            }
+            Ok(())
+        })?;
+    }
+
+    Ok(attrs)
+}
+
+fn field_attrs(field: &syn::Field) -> syn::Result<FieldAttrs> {
+    let mut attrs = FieldAttrs::default();
+
+    for attr in field.attrs.iter().filter(|a| a.path().is_ident("vertex")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("normalized") {
+                attrs.normalized = true;
+            } else if meta.path.is_ident("format") {
+                attrs.format = Some(meta.value()?.parse::<Ident>()?);
+            } else if meta.path.is_ident("location") {
+                attrs.location = Some(meta.value()?.parse::<LitInt>()?.base10_parse()?);
+            } else {
+                return Err(meta.error("expected `normalized`, `format` or `location`"));
+            }
+            Ok(())
+        })?;
+    }
+
+    if attrs.normalized && attrs.format.is_some() {
+        return Err(syn::Error::new(field.span(), "`normalized` has no effect with an explicit `format`"));
+    }
+
+    Ok(attrs)
+}
+
+/// Returns the number of locations, the size of one location in bytes and the format
+fn field_format(ty: &Type, normalized: bool) -> syn::Result<(usize, usize, Ident)> {
+
+    if let Type::Array(array) = ty {
+        if let Type::Array(_) = &*array.elem {
+            let rows = array_len(&array.len)?;
+            let (inner_rows, size, format) = field_format(&array.elem, normalized)?;
+
+            if inner_rows != 1 {
+                return Err(syn::Error::new(ty.span(), "only two levels of arrays are supported"));
+            }
+
+            return Ok((rows, size, format));
+        }
+
+        let count = array_len(&array.len)?;
+        let (size, format) = scalar_format(&array.elem, count, normalized)?;
+        return Ok((1, size, format));
+    }
+
+    let (size, format) = scalar_format(ty, 1, normalized)?;
+    Ok((1, size, format))
+}
+
+fn array_len(len: &Expr) -> syn::Result<usize> {
+    match len {
+        Expr::Lit(ExprLit { lit: Lit::Int(lit), .. }) => lit.base10_parse(),
+        _ => Err(syn::Error::new(len.span(), "array length must be a literal")),
+    }
+}
+
+fn scalar_format(ty: &Type, count: usize, normalized: bool) -> syn::Result<(usize, Ident)> {
+
+    let unsupported = || syn::Error::new(
+        ty.span(),
+        "unsupported vertex field type, use `#[vertex(format = ...)]`"
+    );
+
+    let Type::Path(path) = ty else {
+        return Err(unsupported());
+    };
+
+    let Some(scalar) = path.path.get_ident().map(|i| i.to_string()) else {
+        return Err(unsupported());
+    };
+
+    let (bits, kind) = match (scalar.as_str(), normalized) {
+        ("f32", false) => (32, "SFLOAT"),
+        ("i32", false) => (32, "SINT"),
+        ("u32", false) => (32, "UINT"),
+        ("i16", false) => (16, "SINT"),
+        ("u16", false) => (16, "UINT"),
+        ("i16", true) => (16, "SNORM"),
+        ("u16", true) => (16, "UNORM"),
+        ("i8", false) => (8, "SINT"),
+        ("u8", false) => (8, "UINT"),
+        ("i8", true) => (8, "SNORM"),
+        ("u8", true) => (8, "UNORM"),
+        ("f32" | "i32" | "u32", true) => {
+            return Err(syn::Error::new(ty.span(), "only 8 and 16 bit integers can be normalized"));
+        }
+        _ => return Err(unsupported()),
+    };
+
+    if !(1..=4).contains(&count) {
+        return Err(syn::Error::new(ty.span(), "vertex attributes have 1 to 4 components"));
+    }
+
+    let format = ["R", "G", "B", "A"][..count].iter()
+        .map(|channel| format!("{}{}", channel, bits))
+        .collect::<String>();
+
+    Ok((count * bits / 8, Ident::new(&format!("{}_{}", format, kind), Span::call_site())))
+}
+
+#[cfg(test)]
+mod tests {
+    use syn::parse_quote;
+    use super::*;
+
+    fn scalar(ty: Type, count: usize, normalized: bool) -> syn::Result<(usize, String)> {
+        scalar_format(&ty, count, normalized).map(|(size, format)| (size, format.to_string()))
+    }
+
+    fn field(ty: Type, normalized: bool) -> syn::Result<(usize, usize, String)> {
+        field_format(&ty, normalized).map(|(rows, size, format)| (rows, size, format.to_string()))
+    }
+
+    #[test]
+    fn scalar_formats() {
+        assert_eq!(scalar(parse_quote!(f32), 1, false).unwrap(), (4, "R32_SFLOAT".into()));
+        assert_eq!(scalar(parse_quote!(f32), 3, false).unwrap(), (12, "R32G32B32_SFLOAT".into()));
+        assert_eq!(scalar(parse_quote!(u32), 2, false).unwrap(), (8, "R32G32_UINT".into()));
+        assert_eq!(scalar(parse_quote!(i16), 2, true).unwrap(), (4, "R16G16_SNORM".into()));
+        assert_eq!(scalar(parse_quote!(u8), 4, true).unwrap(), (4, "R8G8B8A8_UNORM".into()));
+        assert_eq!(scalar(parse_quote!(i8), 4, false).unwrap(), (4, "R8G8B8A8_SINT".into()));
+    }
+
+    #[test]
+    fn scalar_format_errors() {
+        assert!(scalar(parse_quote!(f32), 1, true).is_err());
+        assert!(scalar(parse_quote!(u8), 5, false).is_err());
+        assert!(scalar(parse_quote!(u8), 0, false).is_err());
+        assert!(scalar(parse_quote!(f64), 1, false).is_err());
+        assert!(scalar(parse_quote!(glam::Vec3), 1, false).is_err());
+    }
+
+    #[test]
+    fn field_formats() {
+        assert_eq!(field(parse_quote!(u16), false).unwrap(), (1, 2, "R16_UINT".into()));
+        assert_eq!(field(parse_quote!([f32; 2]), false).unwrap(), (1, 8, "R32G32_SFLOAT".into()));
+        assert_eq!(field(parse_quote!([[f32; 4]; 4]), false).unwrap(), (4, 16, "R32G32B32A32_SFLOAT".into()));
+        assert_eq!(field(parse_quote!([[u8; 2]; 3]), true).unwrap(), (3, 2, "R8G8_UNORM".into()));
+    }
+
+    #[test]
+    fn field_format_errors() {
+        assert!(field(parse_quote!([[[f32; 2]; 2]; 2]), false).is_err());
+        assert!(field(parse_quote!([f32; N]), false).is_err());
+        assert!(field(parse_quote!([f32; 5]), false).is_err());
+    }
+}
@@ -0,0 +1,8 @@
+use banana_derive::VertexAttributes;
+
+#[derive(VertexAttributes)]
+struct Vertex {
+    weights: [f32; 5],
+}
+
+fn main() {}
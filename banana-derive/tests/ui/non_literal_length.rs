@@ -0,0 +1,10 @@
+use banana_derive::VertexAttributes;
+
+const N: usize = 3;
+
+#[derive(VertexAttributes)]
+struct Vertex {
+    position: [f32; N],
+}
+
+fn main() {}
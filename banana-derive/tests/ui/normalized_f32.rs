@@ -0,0 +1,9 @@
+use banana_derive::VertexAttributes;
+
+#[derive(VertexAttributes)]
+struct Vertex {
+    #[vertex(normalized)]
+    position: [f32; 3],
+}
+
+fn main() {}
@@ -1,6 +1,328 @@
+use std::mem;
 
+use ash::vk;
+use log::debug;
 
+use crate::{CommandRecorder, DescriptorError, DescriptorPool, DescriptorPoolBuilder, DescriptorSetLayout, DescriptorSetLayoutBuilder, Device, VulkanError, VulkanResult};
+
+/// Bindings of the global set, must match `common.glsl`
+pub const TEXTURES_BINDING: u32 = 0;
+pub const STORAGE_IMAGES_BINDING: u32 = 1;
+pub const TRANSFORMS_BINDING: u32 = 2;
+pub const CAMERA_BINDING: u32 = 3;
+pub const BUFFERS_BINDING: u32 = 4;
+pub const AABB_LIST_BINDING: u32 = 5;
+
+pub const MAX_TEXTURES: u32 = 512;
+pub const MAX_STORAGE_IMAGES: u32 = 128;
+pub const MAX_BUFFERS: u32 = 256;
+
+/// Index of a sampled texture in `textures[]`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct TextureIndex(pub u32);
+
+/// Index of a storage image in `storageImages[]`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct StorageImageIndex(pub u32);
+
+/// Index of a storage buffer in `ssbos[]`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct BufferIndex(pub u32);
+
+enum Write {
+    Image { binding: u32, index: u32, info: vk::DescriptorImageInfo },
+    Buffer { binding: u32, index: u32, ty: vk::DescriptorType, info: vk::DescriptorBufferInfo },
+}
+
+/// Free list of one array binding
+struct Slots {
+    binding: u32,
+    capacity: u32,
+    next: u32,
+    free: Vec<u32>,
+    /// Indices freed while a frame slot was recorded, reusable once it comes around again
+    retired: Vec<Vec<u32>>,
+}
+
+impl Slots {
+
+    fn new(binding: u32, capacity: u32, frames: usize) -> Self {
+        Self {
+            binding,
+            capacity,
+            next: 0,
+            free: vec![],
+            retired: vec![vec![]; frames],
+        }
+    }
+
+    fn alloc(&mut self) -> VulkanResult<u32> {
+        if let Some(index) = self.free.pop() {
+            return Ok(index);
+        }
+
+        if self.next == self.capacity {
+            return Err(VulkanError::Descriptor(DescriptorError::BindlessFull {
+                binding: self.binding,
+                capacity: self.capacity,
+            }));
+        }
+
+        self.next += 1;
+        Ok(self.next - 1)
+    }
+
+    fn retire(&mut self, slot: usize, index: u32) {
+        self.retired[slot].push(index);
+    }
+
+    fn begin_frame(&mut self, slot: usize) {
+        let retired = mem::take(&mut self.retired[slot]);
+        self.free.extend(retired);
+    }
+
+    fn used(&self) -> u32 {
+        self.next - self.free.len() as u32 - self.retired.iter().map(|r| r.len() as u32).sum::<u32>()
+    }
+}
+
+/// Global descriptor set at set 0 holding every texture, storage image and storage buffer.
+///
+/// Each frame slot has its own copy of the set. Writes are queued and applied to a copy when its
+/// slot begins, after the slot's fence signaled, so no copy is updated while the GPU reads it
 pub struct Bindless {
-    
+    layout: DescriptorSetLayout,
+    /// Keeps the sets alive
+    _pool: DescriptorPool,
+    sets: Vec<vk::DescriptorSet>,
+    /// Writes not yet applied to every copy
+    writes: Vec<Write>,
+    /// Number of `writes` each copy already has
+    applied: Vec<usize>,
+    textures: Slots,
+    storage_images: Slots,
+    buffers: Slots,
+    slot: usize,
 }
 
+impl Bindless {
+
+    pub fn new(device: &Device, frames: usize) -> VulkanResult<Self> {
+
+        if !device.features().descriptor_indexing {
+            return Err(VulkanError::Descriptor(DescriptorError::IndexingUnsupported));
+        }
+
+        let binding = |binding: u32, ty: vk::DescriptorType, count: u32| {
+            vk::DescriptorSetLayoutBinding::default()
+                .binding(binding)
+                .descriptor_type(ty)
+                .descriptor_count(count)
+                .stage_flags(vk::ShaderStageFlags::ALL)
+        };
+
+        let bindings = vec![
+            binding(TEXTURES_BINDING, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, MAX_TEXTURES),
+            binding(STORAGE_IMAGES_BINDING, vk::DescriptorType::STORAGE_IMAGE, MAX_STORAGE_IMAGES),
+            binding(TRANSFORMS_BINDING, vk::DescriptorType::UNIFORM_BUFFER, 1),
+            // Points at the `UniformRing`, the frame's offset is given in `bind`
+            binding(CAMERA_BINDING, vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC, 1),
+            binding(BUFFERS_BINDING, vk::DescriptorType::STORAGE_BUFFER, MAX_BUFFERS),
+            binding(AABB_LIST_BINDING, vk::DescriptorType::UNIFORM_BUFFER, 1),
+        ];
+
+        // Slots that were never written or were freed are not accessed by shaders
+        let flags = vec![vk::DescriptorBindingFlags::PARTIALLY_BOUND; bindings.len()];
+
+        let sizes = bindings.iter()
+            .map(|b| {
+                vk::DescriptorPoolSize::default()
+                    .ty(b.descriptor_type)
+                    .descriptor_count(b.descriptor_count * frames as u32)
+            })
+            .collect::<Vec<_>>();
+
+        let layout = DescriptorSetLayoutBuilder::new(device)
+            .bindings(bindings)
+            .binding_flags(flags)
+            .build()?;
+
+        let pool = DescriptorPoolBuilder::new(device)
+            .pool_sizes(&sizes)
+            .max_sets(frames as u32)
+            .build()?;
+
+        let sets = pool.create_descriptor_set(device, &vec![layout.raw; frames])?;
+
+        debug!("Create bindless set with {} copies", frames);
+
+        Ok(Self {
+            layout,
+            _pool: pool,
+            sets,
+            writes: vec![],
+            applied: vec![0; frames],
+            textures: Slots::new(TEXTURES_BINDING, MAX_TEXTURES, frames),
+            storage_images: Slots::new(STORAGE_IMAGES_BINDING, MAX_STORAGE_IMAGES, frames),
+            buffers: Slots::new(BUFFERS_BINDING, MAX_BUFFERS, frames),
+            slot: 0,
+        })
+    }
+
+    /// Layout to put at set 0 of every pipeline layout that uses the global set
+    pub fn layout(&self) -> vk::DescriptorSetLayout {
+        self.layout.raw
+    }
+
+    /// Copy of the set for the current frame slot
+    pub fn set(&self) -> vk::DescriptorSet {
+        self.sets[self.slot]
+    }
+
+    /// Recycle the indices freed the last time `slot` was recorded and bring its copy
+    /// up to date, call once the slot's fence has signaled
+    pub fn begin_frame(&mut self, device: &Device, slot: usize) {
+        puffin::profile_scope!("Bindless::begin_frame");
+
+        self.slot = slot;
+
+        self.textures.begin_frame(slot);
+        self.storage_images.begin_frame(slot);
+        self.buffers.begin_frame(slot);
+
+        let set = self.sets[slot];
+        let pending = &self.writes[self.applied[slot]..];
+
+        let writes = pending.iter()
+            .map(|write| match write {
+                Write::Image { binding, index, info } => {
+                    let ty = if *binding == TEXTURES_BINDING {
+                        vk::DescriptorType::COMBINED_IMAGE_SAMPLER
+                    } else {
+                        vk::DescriptorType::STORAGE_IMAGE
+                    };
+
+                    vk::WriteDescriptorSet::default()
+                        .dst_set(set)
+                        .dst_binding(*binding)
+                        .dst_array_element(*index)
+                        .descriptor_type(ty)
+                        .image_info(std::slice::from_ref(info))
+                }
+                Write::Buffer { binding, index, ty, info } => {
+                    vk::WriteDescriptorSet::default()
+                        .dst_set(set)
+                        .dst_binding(*binding)
+                        .dst_array_element(*index)
+                        .descriptor_type(*ty)
+                        .buffer_info(std::slice::from_ref(info))
+                }
+            })
+            .collect::<Vec<_>>();
+
+        if !writes.is_empty() {
+            unsafe { device.update_descriptor_sets(&writes, &[]) };
+        }
+
+        self.applied[slot] = self.writes.len();
+
+        // Drop the writes every copy has seen
+        let done = self.applied.iter().copied().min().unwrap_or(0);
+
+        if done > 0 {
+            self.writes.drain(..done);
+            self.applied.iter_mut().for_each(|applied| *applied -= done);
+        }
+    }
+
+    /// Bind the set for the current frame slot at set 0 of `layout`, `camera_offset` is the
+    /// dynamic offset of this frame's `GlobalUniforms` in the buffer behind `CAMERA_BINDING`
+    pub fn bind(&self, recorder: &mut CommandRecorder, bind_point: vk::PipelineBindPoint, layout: vk::PipelineLayout, camera_offset: u32) {
+        recorder.bind_descriptor_sets(bind_point, layout, 0, &[self.set()], &[camera_offset]);
+    }
+
+    pub fn register_texture(&mut self, view: vk::ImageView, sampler: vk::Sampler) -> VulkanResult<TextureIndex> {
+        let index = self.textures.alloc()?;
+
+        self.writes.push(Write::Image {
+            binding: TEXTURES_BINDING,
+            index,
+            info: vk::DescriptorImageInfo::default()
+                .image_view(view)
+                .sampler(sampler)
+                .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL),
+        });
+
+        Ok(TextureIndex(index))
+    }
+
+    pub fn register_storage_image(&mut self, view: vk::ImageView) -> VulkanResult<StorageImageIndex> {
+        let index = self.storage_images.alloc()?;
+
+        self.writes.push(Write::Image {
+            binding: STORAGE_IMAGES_BINDING,
+            index,
+            info: vk::DescriptorImageInfo::default()
+                .image_view(view)
+                .image_layout(vk::ImageLayout::GENERAL),
+        });
+
+        Ok(StorageImageIndex(index))
+    }
+
+    pub fn register_buffer(&mut self, buffer: vk::Buffer, offset: vk::DeviceSize, range: vk::DeviceSize) -> VulkanResult<BufferIndex> {
+        let index = self.buffers.alloc()?;
+
+        self.writes.push(Write::Buffer {
+            binding: BUFFERS_BINDING,
+            index,
+            ty: vk::DescriptorType::STORAGE_BUFFER,
+            info: vk::DescriptorBufferInfo::default()
+                .buffer(buffer)
+                .offset(offset)
+                .range(range),
+        });
+
+        Ok(BufferIndex(index))
+    }
+
+    /// Point `TRANSFORMS_BINDING`, `CAMERA_BINDING` or `AABB_LIST_BINDING` at a uniform buffer,
+    /// for the camera `range` is the size of one frame's data
+    pub fn set_uniform_buffer(&mut self, binding: u32, buffer: vk::Buffer, offset: vk::DeviceSize, range: vk::DeviceSize) {
+        debug_assert!([TRANSFORMS_BINDING, CAMERA_BINDING, AABB_LIST_BINDING].contains(&binding));
+
+        let ty = if binding == CAMERA_BINDING {
+            vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC
+        } else {
+            vk::DescriptorType::UNIFORM_BUFFER
+        };
+
+        self.writes.push(Write::Buffer {
+            binding,
+            index: 0,
+            ty,
+            info: vk::DescriptorBufferInfo::default()
+                .buffer(buffer)
+                .offset(offset)
+                .range(range),
+        });
+    }
+
+    /// The index is handed out again once every frame that could still read it finished
+    pub fn free_texture(&mut self, texture: TextureIndex) {
+        self.textures.retire(self.slot, texture.0);
+    }
+
+    pub fn free_storage_image(&mut self, image: StorageImageIndex) {
+        self.storage_images.retire(self.slot, image.0);
+    }
+
+    pub fn free_buffer(&mut self, buffer: BufferIndex) {
+        self.buffers.retire(self.slot, buffer.0);
+    }
+
+    /// Registered textures, storage images and buffers
+    pub fn usage(&self) -> (u32, u32, u32) {
+        (self.textures.used(), self.storage_images.used(), self.buffers.used())
+    }
+}
@@ -2,7 +2,7 @@ use ash::vk;
 use log::debug;
 use std::ffi::CStr;
 
-use crate::VulkanResult;
+use crate::{AppError, VulkanError, VulkanResult};
 
 const ENGINE_VERSION: u32 = 0;
 const ENGINE_NAME: &'static CStr = c"Ferrum";
@@ -10,25 +10,39 @@ const ENGINE_NAME: &'static CStr = c"Ferrum";
 pub struct App {
     pub(crate) create_info: vk::ApplicationInfo<'static>,
     pub(crate) entry: ash::Entry,
+    pub(crate) api_version: u32,
 }
 
 pub struct AppBuilder {
     api_version: u32,
+    min_api_version: u32,
     app_name: &'static CStr,
     app_version: u32,
 }
 
 impl AppBuilder {
-    
+
     pub fn default() -> Self {
-        AppBuilder { app_name: c"App", app_version: 0, api_version: vk::API_VERSION_1_0 }
+        AppBuilder {
+            app_name: c"App",
+            app_version: 0,
+            api_version: vk::API_VERSION_1_3,
+            min_api_version: vk::API_VERSION_1_0
+        }
     }
 
+    /// Highest api version to use, lowered to what the loader supports
     pub fn with_api_version(mut self, version: u32) -> Self {
         self.api_version = version;
         self
     }
 
+    /// Lowest api version the application can run on
+    pub fn with_min_api_version(mut self, version: u32) -> Self {
+        self.min_api_version = version;
+        self
+    }
+
     pub fn with_app_name(mut self, name: &'static CStr) -> Self {
         self.app_name = name;
         self
@@ -36,27 +50,32 @@ impl AppBuilder {
 
     pub fn build(self) -> VulkanResult<App> {
 
-        let entry = unsafe { ash::Entry::load().expect("Error load entry point") };
+        let entry = unsafe { ash::Entry::load().map_err(|e| {
+            VulkanError::App(AppError::LoadingVulkan(e))
+        })}?;
+
         let max_api_versions = unsafe {
             entry
                 .try_enumerate_instance_version()
-                .expect("Error enumerate api veriosn")
+                .map_err(|e| VulkanError::App(AppError::LoadingVulkanApiVersion(e)))?
                 .unwrap_or(vk::API_VERSION_1_0)
         };
 
-        if self.api_version > max_api_versions {
-            panic!("R");
+        let api_version = self.api_version.min(max_api_versions);
+
+        if api_version < self.min_api_version {
+            return Err(VulkanError::App(AppError::Api(api_version)));
         }
 
         let create_info = vk::ApplicationInfo::default()
-            .api_version(self.api_version)
+            .api_version(api_version)
             .application_name(self.app_name)
             .engine_name(ENGINE_NAME)
             .engine_version(ENGINE_VERSION);
 
         debug!("App: {:?}", create_info);
 
-        Ok(App { create_info, entry })
+        Ok(App { create_info, entry, api_version })
     }
 }
 
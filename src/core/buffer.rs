@@ -1,12 +1,132 @@
+use std::mem::ManuallyDrop;
 
-use vk_mem::Allocation;
+use vk_mem::{Alloc, Allocation};
 use ash::vk;
 
+use crate::{BufferError, Device, MemoryCategory, VulkanError, VulkanResult};
+use crate::core::{Deferred, DeletionQueue};
+
 pub struct GpuBuffer {
     buffer: vk::Buffer,
-    allocation: Allocation
+    size: vk::DeviceSize,
+    /// Null unless the buffer was created host visible
+    mapped: *mut u8,
+    allocation: ManuallyDrop<Allocation>,
+    deletion: DeletionQueue,
+}
+
+impl Drop for GpuBuffer {
+    fn drop(&mut self) {
+        let allocation = unsafe { ManuallyDrop::take(&mut self.allocation) };
+        self.deletion.push(Deferred::Buffer(self.buffer, allocation));
+    }
+}
+
+impl GpuBuffer {
+    pub fn raw(&self) -> vk::Buffer {
+        self.buffer
+    }
+
+    pub fn size(&self) -> vk::DeviceSize {
+        self.size
+    }
+
+    pub fn is_mapped(&self) -> bool {
+        !self.mapped.is_null()
+    }
+
+    /// Copy `data` into a host visible buffer, the range must not be read by the GPU right now
+    pub fn write(&self, device: &Device, offset: vk::DeviceSize, data: &[u8]) -> VulkanResult<()> {
+
+        if self.mapped.is_null() {
+            return Err(VulkanError::GpuBuffer(BufferError::NotMapped));
+        }
+
+        if offset + data.len() as vk::DeviceSize > self.size {
+            return Err(VulkanError::GpuBuffer(BufferError::OutOfBounds {
+                offset,
+                len: data.len() as vk::DeviceSize,
+                size: self.size,
+            }));
+        }
+
+        unsafe {
+            std::ptr::copy_nonoverlapping(data.as_ptr(), self.mapped.add(offset as usize), data.len());
+        }
+
+        // No-op on host coherent memory
+        device.allocator.flush_allocation(&self.allocation, offset, data.len() as vk::DeviceSize)
+            .map_err(|e| VulkanError::GpuBuffer(BufferError::Update(e)))
+    }
 }
 
-struct GpuBufferBuilder {
-    
-}
\ No newline at end of file
+pub struct GpuBufferBuilder<'a> {
+    device: &'a Device,
+    create_info: vk::BufferCreateInfo<'static>,
+    alloc_info: vk_mem::AllocationCreateInfo,
+    host_visible: bool,
+    category: Option<MemoryCategory>,
+}
+
+impl<'a> GpuBufferBuilder<'a> {
+
+    pub fn new(device: &'a Device, size: vk::DeviceSize) -> Self {
+        Self {
+            device,
+            create_info: vk::BufferCreateInfo::default()
+                .size(size)
+                .sharing_mode(vk::SharingMode::EXCLUSIVE),
+            alloc_info: vk_mem::AllocationCreateInfo {
+                usage: vk_mem::MemoryUsage::AutoPreferDevice,
+                ..Default::default()
+            },
+            host_visible: false,
+            category: None,
+        }
+    }
+
+    pub fn usage(mut self, usage: vk::BufferUsageFlags) -> Self {
+        self.create_info = self.create_info.usage(usage);
+        self
+    }
+
+    /// Persistently mapped memory the CPU writes sequentially, for data updated every frame
+    pub fn host_visible(mut self) -> Self {
+        self.alloc_info = vk_mem::AllocationCreateInfo {
+            usage: vk_mem::MemoryUsage::Auto,
+            flags: vk_mem::AllocationCreateFlags::MAPPED | vk_mem::AllocationCreateFlags::HOST_ACCESS_SEQUENTIAL_WRITE,
+            ..Default::default()
+        };
+        self.host_visible = true;
+        self
+    }
+
+    /// Inferred from the usage flags when not set
+    pub fn category(mut self, category: MemoryCategory) -> Self {
+        self.category = Some(category);
+        self
+    }
+
+    pub fn build(self) -> VulkanResult<GpuBuffer> {
+
+        let (buffer, allocation) = unsafe {
+            self.device.allocator.create_buffer(&self.create_info, &self.alloc_info).map_err(|e| {
+                VulkanError::GpuBuffer(BufferError::CreationFailed(self.create_info.size, e))
+            })?
+        };
+
+        let info = self.device.allocator.get_allocation_info(&allocation);
+        let mapped = info.mapped_data as *mut u8;
+
+        let category = self.category.unwrap_or(MemoryCategory::from_buffer_usage(self.create_info.usage, self.host_visible));
+        self.device.memory.track(buffer, category, info.size);
+
+        Ok(GpuBuffer {
+            buffer,
+            size: self.create_info.size,
+            mapped,
+            allocation: ManuallyDrop::new(allocation),
+            deletion: self.device.register(buffer)
+        })
+    }
+}
@@ -1,9 +1,17 @@
 
 use ash::vk;
-use crate::{VulkanError, VulkanResult, core::device::Device};
+use crate::core::{Deferred, DeletionQueue};
+use crate::{CommandPoolError, VulkanError, VulkanResult, core::device::Device};
 
 pub struct CommandPool {
-    raw: vk::CommandPool
+    raw: vk::CommandPool,
+    deletion: DeletionQueue,
+}
+
+impl Drop for CommandPool {
+    fn drop(&mut self) {
+        self.deletion.push(Deferred::CommandPool(self.raw));
+    }
 }
 
 impl CommandPool {
@@ -16,7 +24,7 @@ impl CommandPool {
 
         let buffers = unsafe {
             device.allocate_command_buffers(&create_info)
-                .map_err(|e| VulkanError::Unknown(e))
+                .map_err(|e| VulkanError::CommandPool(CommandPoolError::CommandBuffersCreationFailed(e)))
         }?;
 
         Ok(buffers)
@@ -42,9 +50,12 @@ impl<'a> CommandPoolBuilder<'a> {
         puffin::profile_scope!("vkCommandBuffers");
 
         let pool = unsafe { self.device.create_command_pool(&self.create_info, None).map_err(|e| {
-            VulkanError::Unknown(e)
+            VulkanError::CommandPool(CommandPoolError::CommandPoolCreationFailed(e))
         })}?;
 
-        Ok(CommandPool { raw: pool })
+        Ok(CommandPool {
+            raw: pool,
+            deletion: self.device.register(pool)
+        })
     }
 }
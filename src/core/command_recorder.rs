@@ -0,0 +1,314 @@
+use std::ffi::CString;
+
+use ash::vk;
+
+use crate::{BarrierBuilder, CommandError, Device, RenderingBuilder, VulkanError, VulkanResult};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Scope {
+    Outside,
+    RenderPass,
+    Rendering,
+}
+
+impl Scope {
+    fn name(&self) -> &'static str {
+        match self {
+            Scope::Outside => "command buffer",
+            Scope::RenderPass => "render pass",
+            Scope::Rendering => "dynamic rendering",
+        }
+    }
+}
+
+/// Records into a command buffer and tracks the state the commands depend on.
+///
+/// Invalid sequences, like a draw without a pipeline or a dispatch inside a render pass,
+/// return `CommandError` in debug builds and are recorded as is in release builds
+pub struct CommandRecorder<'a> {
+    device: &'a Device,
+    cmd: vk::CommandBuffer,
+    scope: Scope,
+    graphics: Option<(vk::Pipeline, vk::PipelineLayout)>,
+    compute: Option<(vk::Pipeline, vk::PipelineLayout)>,
+    sets: Vec<(vk::PipelineBindPoint, u32, vk::DescriptorSet)>,
+}
+
+impl<'a> CommandRecorder<'a> {
+
+    /// Begin recording `cmd`, which must be in the initial state
+    pub fn begin(device: &'a Device, cmd: vk::CommandBuffer, flags: vk::CommandBufferUsageFlags) -> VulkanResult<Self> {
+
+        let begin_info = vk::CommandBufferBeginInfo::default().flags(flags);
+
+        unsafe {
+            device.begin_command_buffer(cmd, &begin_info)
+                .map_err(|e| VulkanError::Command(CommandError::BeginFailed(e)))?;
+        }
+
+        Ok(Self::from_raw(device, cmd))
+    }
+
+    /// Wrap a command buffer that is already recording, outside of any render pass
+    pub fn from_raw(device: &'a Device, cmd: vk::CommandBuffer) -> Self {
+        Self {
+            device,
+            cmd,
+            scope: Scope::Outside,
+            graphics: None,
+            compute: None,
+            sets: vec![],
+        }
+    }
+
+    pub fn raw(&self) -> vk::CommandBuffer {
+        self.cmd
+    }
+
+    pub fn device(&self) -> &'a Device {
+        self.device
+    }
+
+    pub fn in_render_pass(&self) -> bool {
+        self.scope != Scope::Outside
+    }
+
+    pub fn pipeline(&self, bind_point: vk::PipelineBindPoint) -> Option<vk::Pipeline> {
+        self.bound(bind_point).map(|(pipeline, _)| pipeline)
+    }
+
+    pub fn layout(&self, bind_point: vk::PipelineBindPoint) -> Option<vk::PipelineLayout> {
+        self.bound(bind_point).map(|(_, layout)| layout)
+    }
+
+    /// Bind point, set index and set of every bind since recording started
+    pub fn bound_sets(&self) -> &[(vk::PipelineBindPoint, u32, vk::DescriptorSet)] {
+        &self.sets
+    }
+
+    fn bound(&self, bind_point: vk::PipelineBindPoint) -> Option<(vk::Pipeline, vk::PipelineLayout)> {
+        match bind_point {
+            vk::PipelineBindPoint::COMPUTE => self.compute,
+            _ => self.graphics,
+        }
+    }
+
+    fn check(&self, valid: bool, error: impl FnOnce() -> CommandError) -> VulkanResult<()> {
+        if cfg!(debug_assertions) && !valid {
+            return Err(VulkanError::Command(error()));
+        }
+
+        Ok(())
+    }
+
+    fn outside(&self, command: &'static str) -> VulkanResult<()> {
+        self.check(self.scope == Scope::Outside, || CommandError::InsideRenderPass(command))
+    }
+
+    fn inside(&self, command: &'static str) -> VulkanResult<()> {
+        self.check(self.scope != Scope::Outside, || CommandError::OutsideRenderPass(command))
+    }
+
+    pub fn end(self) -> VulkanResult<()> {
+        self.outside("vkEndCommandBuffer")?;
+
+        unsafe {
+            self.device.end_command_buffer(self.cmd)
+                .map_err(|e| VulkanError::Command(CommandError::EndFailed(e)))
+        }
+    }
+
+    pub fn begin_render_pass(&mut self, info: &vk::RenderPassBeginInfo, contents: vk::SubpassContents) -> VulkanResult<()> {
+        self.outside("vkCmdBeginRenderPass")?;
+        unsafe { self.device.cmd_begin_render_pass(self.cmd, info, contents) };
+        self.scope = Scope::RenderPass;
+        Ok(())
+    }
+
+    pub fn end_render_pass(&mut self) -> VulkanResult<()> {
+        self.check(self.scope == Scope::RenderPass, || CommandError::WrongScope("vkCmdEndRenderPass", self.scope.name()))?;
+        unsafe { self.device.cmd_end_render_pass(self.cmd) };
+        self.scope = Scope::Outside;
+        Ok(())
+    }
+
+    pub fn begin_rendering(&mut self, rendering: RenderingBuilder) -> VulkanResult<()> {
+        self.outside("vkCmdBeginRendering")?;
+        rendering.begin(self.cmd);
+        self.scope = Scope::Rendering;
+        Ok(())
+    }
+
+    pub fn end_rendering(&mut self) -> VulkanResult<()> {
+        self.check(self.scope == Scope::Rendering, || CommandError::WrongScope("vkCmdEndRendering", self.scope.name()))?;
+        self.device.end_rendering(self.cmd);
+        self.scope = Scope::Outside;
+        Ok(())
+    }
+
+    /// Close whichever render pass or rendering scope is open
+    pub fn end_scope(&mut self) -> VulkanResult<()> {
+        match self.scope {
+            Scope::RenderPass => self.end_render_pass(),
+            Scope::Rendering => self.end_rendering(),
+            Scope::Outside => Ok(()),
+        }
+    }
+
+    /// Open a debug utils label, shown by debuggers and attached to validation messages
+    pub fn begin_label(&mut self, name: &str) {
+        let Some(debug_utils) = &self.device.debug_utils else {
+            return;
+        };
+
+        let Ok(name) = CString::new(name) else {
+            return;
+        };
+
+        let label = vk::DebugUtilsLabelEXT::default().label_name(&name);
+        unsafe { debug_utils.cmd_begin_debug_utils_label(self.cmd, &label) };
+    }
+
+    pub fn end_label(&mut self) {
+        if let Some(debug_utils) = &self.device.debug_utils {
+            unsafe { debug_utils.cmd_end_debug_utils_label(self.cmd) };
+        }
+    }
+
+    pub fn barrier(&mut self, barriers: BarrierBuilder) -> VulkanResult<()> {
+        self.outside("vkCmdPipelineBarrier")?;
+        barriers.record(self.cmd);
+        Ok(())
+    }
+
+    pub fn bind_pipeline(&mut self, bind_point: vk::PipelineBindPoint, pipeline: vk::Pipeline, layout: vk::PipelineLayout) {
+        unsafe { self.device.cmd_bind_pipeline(self.cmd, bind_point, pipeline) };
+
+        match bind_point {
+            vk::PipelineBindPoint::COMPUTE => self.compute = Some((pipeline, layout)),
+            _ => self.graphics = Some((pipeline, layout)),
+        }
+    }
+
+    /// Bind `sets` starting at `first_set`, `layout` may differ from the bound pipeline's if compatible
+    pub fn bind_descriptor_sets(
+        &mut self,
+        bind_point: vk::PipelineBindPoint,
+        layout: vk::PipelineLayout,
+        first_set: u32,
+        sets: &[vk::DescriptorSet],
+        dynamic_offsets: &[u32],
+    ) {
+        unsafe {
+            self.device.cmd_bind_descriptor_sets(self.cmd, bind_point, layout, first_set, sets, dynamic_offsets);
+        }
+
+        for (i, &set) in sets.iter().enumerate() {
+            self.sets.retain(|&(point, index, _)| point != bind_point || index != first_set + i as u32);
+            self.sets.push((bind_point, first_set + i as u32, set));
+        }
+    }
+
+    /// Uses the layout of the pipeline bound at `bind_point`
+    pub fn push_constants(&mut self, bind_point: vk::PipelineBindPoint, stages: vk::ShaderStageFlags, offset: u32, data: &[u8]) -> VulkanResult<()> {
+        let layout = self.layout(bind_point);
+        self.check(layout.is_some(), || CommandError::NoLayout("vkCmdPushConstants"))?;
+
+        if let Some(layout) = layout {
+            unsafe { self.device.cmd_push_constants(self.cmd, layout, stages, offset, data) };
+        }
+
+        Ok(())
+    }
+
+    pub fn set_viewport(&mut self, viewport: vk::Viewport) {
+        unsafe { self.device.cmd_set_viewport(self.cmd, 0, &[viewport]) };
+    }
+
+    pub fn set_scissor(&mut self, scissor: vk::Rect2D) {
+        unsafe { self.device.cmd_set_scissor(self.cmd, 0, &[scissor]) };
+    }
+
+    /// Viewport and scissor covering `extent`
+    pub fn set_full_viewport(&mut self, extent: vk::Extent2D) {
+        self.set_viewport(
+            vk::Viewport::default()
+                .width(extent.width as f32)
+                .height(extent.height as f32)
+                .min_depth(0.0)
+                .max_depth(1.0)
+        );
+
+        self.set_scissor(vk::Rect2D::default().extent(extent));
+    }
+
+    pub fn bind_vertex_buffers(&mut self, first_binding: u32, buffers: &[vk::Buffer], offsets: &[vk::DeviceSize]) {
+        unsafe { self.device.cmd_bind_vertex_buffers(self.cmd, first_binding, buffers, offsets) };
+    }
+
+    pub fn bind_index_buffer(&mut self, buffer: vk::Buffer, offset: vk::DeviceSize, index_type: vk::IndexType) {
+        unsafe { self.device.cmd_bind_index_buffer(self.cmd, buffer, offset, index_type) };
+    }
+
+    fn check_draw(&self, command: &'static str) -> VulkanResult<()> {
+        self.inside(command)?;
+        self.check(self.graphics.is_some(), || CommandError::NoPipeline(command, vk::PipelineBindPoint::GRAPHICS))
+    }
+
+    pub fn draw(&mut self, vertex_count: u32, instance_count: u32, first_vertex: u32, first_instance: u32) -> VulkanResult<()> {
+        self.check_draw("vkCmdDraw")?;
+        unsafe { self.device.cmd_draw(self.cmd, vertex_count, instance_count, first_vertex, first_instance) };
+        Ok(())
+    }
+
+    pub fn draw_indexed(&mut self, index_count: u32, instance_count: u32, first_index: u32, vertex_offset: i32, first_instance: u32) -> VulkanResult<()> {
+        self.check_draw("vkCmdDrawIndexed")?;
+        unsafe { self.device.cmd_draw_indexed(self.cmd, index_count, instance_count, first_index, vertex_offset, first_instance) };
+        Ok(())
+    }
+
+    pub fn dispatch(&mut self, x: u32, y: u32, z: u32) -> VulkanResult<()> {
+        self.outside("vkCmdDispatch")?;
+        self.check(self.compute.is_some(), || CommandError::NoPipeline("vkCmdDispatch", vk::PipelineBindPoint::COMPUTE))?;
+        unsafe { self.device.cmd_dispatch(self.cmd, x, y, z) };
+        Ok(())
+    }
+
+    pub fn copy_buffer(&mut self, src: vk::Buffer, dst: vk::Buffer, regions: &[vk::BufferCopy]) -> VulkanResult<()> {
+        self.outside("vkCmdCopyBuffer")?;
+        unsafe { self.device.cmd_copy_buffer(self.cmd, src, dst, regions) };
+        Ok(())
+    }
+
+    pub fn copy_buffer_to_image(&mut self, src: vk::Buffer, dst: vk::Image, layout: vk::ImageLayout, regions: &[vk::BufferImageCopy]) -> VulkanResult<()> {
+        self.outside("vkCmdCopyBufferToImage")?;
+        unsafe { self.device.cmd_copy_buffer_to_image(self.cmd, src, dst, layout, regions) };
+        Ok(())
+    }
+
+    pub fn blit_image(
+        &mut self,
+        src: (vk::Image, vk::ImageLayout),
+        dst: (vk::Image, vk::ImageLayout),
+        regions: &[vk::ImageBlit],
+        filter: vk::Filter,
+    ) -> VulkanResult<()> {
+        self.outside("vkCmdBlitImage")?;
+        unsafe { self.device.cmd_blit_image(self.cmd, src.0, src.1, dst.0, dst.1, regions, filter) };
+        Ok(())
+    }
+
+    pub fn reset_queries(&mut self, pool: vk::QueryPool, first: u32, count: u32) -> VulkanResult<()> {
+        self.outside("vkCmdResetQueryPool")?;
+        unsafe { self.device.cmd_reset_query_pool(self.cmd, pool, first, count) };
+        Ok(())
+    }
+
+    pub fn begin_query(&mut self, pool: vk::QueryPool, query: u32) {
+        unsafe { self.device.cmd_begin_query(self.cmd, pool, query, vk::QueryControlFlags::empty()) };
+    }
+
+    pub fn end_query(&mut self, pool: vk::QueryPool, query: u32) {
+        unsafe { self.device.cmd_end_query(self.cmd, pool, query) };
+    }
+}
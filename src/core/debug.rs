@@ -1,19 +1,215 @@
+use std::{ffi::{c_void, CStr}, sync::{atomic::{AtomicU32, Ordering}, Mutex}};
+
 use ash::vk;
 
+use crate::{InstanceError, VulkanError, VulkanResult};
+
+/// VK_EXT_validation_features checks enabled on top of the validation layer defaults
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ValidationConfig {
+    /// Hazards between commands, queues and submits that lack a barrier or semaphore
+    pub synchronization: bool,
+    /// Valid but slow or non-portable usage, reported as performance warnings
+    pub best_practices: bool,
+    /// Out of bounds descriptor and buffer access checked by instrumented shaders,
+    /// older layers can't combine it with `debug_printf`
+    pub gpu_assisted: bool,
+    /// `debugPrintfEXT` output logged under the `shader` target, tagged with the pass name
+    pub debug_printf: bool,
+}
+
+impl ValidationConfig {
+
+    pub fn synchronization(mut self, enable: bool) -> Self {
+        self.synchronization = enable;
+        self
+    }
+
+    pub fn best_practices(mut self, enable: bool) -> Self {
+        self.best_practices = enable;
+        self
+    }
+
+    pub fn gpu_assisted(mut self, enable: bool) -> Self {
+        self.gpu_assisted = enable;
+        self
+    }
+
+    pub fn debug_printf(mut self, enable: bool) -> Self {
+        self.debug_printf = enable;
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    pub(crate) fn features(&self) -> Vec<vk::ValidationFeatureEnableEXT> {
+        let mut features = vec![];
+
+        if self.synchronization {
+            features.push(vk::ValidationFeatureEnableEXT::SYNCHRONIZATION_VALIDATION);
+        }
+
+        if self.best_practices {
+            features.push(vk::ValidationFeatureEnableEXT::BEST_PRACTICES);
+        }
+
+        if self.gpu_assisted {
+            features.push(vk::ValidationFeatureEnableEXT::GPU_ASSISTED);
+            features.push(vk::ValidationFeatureEnableEXT::GPU_ASSISTED_RESERVE_BINDING_SLOT);
+        }
+
+        if self.debug_printf {
+            features.push(vk::ValidationFeatureEnableEXT::DEBUG_PRINTF);
+        }
+
+        features
+    }
+}
+
+/// What the debug messenger logs, records and fails on
+#[derive(Clone, Debug)]
+pub struct DebugPolicy {
+    /// Less severe messages are dropped, errors are still counted
+    pub min_severity: vk::DebugUtilsMessageSeverityFlagsEXT,
+    /// `messageIdNumber` of messages that are dropped entirely
+    pub suppressed_ids: Vec<i32>,
+    /// `pMessageIdName` of messages that are dropped entirely, e.g. a VUID
+    pub suppressed_names: Vec<String>,
+    /// Keep every logged message for `DebugCallback::take_messages`
+    pub capture: bool,
+    /// Panic from `DebugCallback::check` once an error was reported
+    pub panic_on_error: bool,
+}
+
+impl Default for DebugPolicy {
+    fn default() -> Self {
+        Self {
+            min_severity: vk::DebugUtilsMessageSeverityFlagsEXT::WARNING,
+            suppressed_ids: vec![],
+            suppressed_names: vec![],
+            capture: false,
+            panic_on_error: false,
+        }
+    }
+}
+
+impl DebugPolicy {
+
+    /// Capture every message and panic on the first validation error
+    pub fn testing() -> Self {
+        Self {
+            capture: true,
+            panic_on_error: true,
+            ..Default::default()
+        }
+    }
+
+    pub fn min_severity(mut self, severity: vk::DebugUtilsMessageSeverityFlagsEXT) -> Self {
+        self.min_severity = severity;
+        self
+    }
+
+    pub fn suppress_id(mut self, id: i32) -> Self {
+        self.suppressed_ids.push(id);
+        self
+    }
+
+    pub fn suppress_name<S: Into<String>>(mut self, name: S) -> Self {
+        self.suppressed_names.push(name.into());
+        self
+    }
+
+    pub fn capture(mut self, capture: bool) -> Self {
+        self.capture = capture;
+        self
+    }
+
+    pub fn panic_on_error(mut self, panic: bool) -> Self {
+        self.panic_on_error = panic;
+        self
+    }
+
+    fn is_suppressed(&self, id: i32, name: Option<&str>) -> bool {
+        self.suppressed_ids.contains(&id)
+            || name.is_some_and(|name| self.suppressed_names.iter().any(|suppressed| suppressed == name))
+    }
+}
+
+/// Message recorded when `DebugPolicy::capture` is set
+#[derive(Clone, Debug)]
+pub struct DebugMessage {
+    pub severity: vk::DebugUtilsMessageSeverityFlagsEXT,
+    pub ty: vk::DebugUtilsMessageTypeFlagsEXT,
+    pub id: i32,
+    pub id_name: Option<String>,
+    pub message: String,
+}
+
+/// Shared with the messenger through its user data, which may call in from any thread
+struct DebugState {
+    policy: DebugPolicy,
+    frame_errors: AtomicU32,
+    total_errors: AtomicU32,
+    first_error: Mutex<Option<String>>,
+    messages: Mutex<Vec<DebugMessage>>,
+}
+
+impl DebugState {
+
+    fn new(policy: DebugPolicy) -> Self {
+        Self {
+            policy,
+            frame_errors: AtomicU32::new(0),
+            total_errors: AtomicU32::new(0),
+            first_error: Mutex::new(None),
+            messages: Mutex::new(vec![]),
+        }
+    }
+
+    fn end_frame(&self) -> u32 {
+        let errors = self.frame_errors.swap(0, Ordering::Relaxed);
+        self.check();
+        errors
+    }
+
+    fn check(&self) {
+        if !self.policy.panic_on_error {
+            return;
+        }
+
+        if let Some(message) = self.first_error.lock().unwrap().take() {
+            panic!("Vulkan validation error: {}", message);
+        }
+    }
+}
+
 pub struct DebugCallback {
     callback: vk::DebugUtilsMessengerEXT,
     loader: ash::ext::debug_utils::Instance,
+    /// Boxed so the address handed to the messenger stays put, outlives it by field order
+    state: Box<DebugState>,
 }
 
-impl DebugCallback {
-    pub fn destroy(&self) {
+impl Drop for DebugCallback {
+    fn drop(&mut self) {
         unsafe {
             self.loader
                 .destroy_debug_utils_messenger(self.callback, None)
         };
+
+        if !std::thread::panicking() {
+            self.check();
+        }
     }
+}
+
+impl DebugCallback {
 
-    pub fn new(entry: &ash::Entry, instance: &ash::Instance) -> Self {
+    pub fn new(entry: &ash::Entry, instance: &ash::Instance, policy: DebugPolicy) -> VulkanResult<Self> {
+
+        let state = Box::new(DebugState::new(policy));
 
         let debug_info = vk::DebugUtilsMessengerCreateInfoEXT::default()
             .message_severity(
@@ -27,17 +223,55 @@ impl DebugCallback {
                     | vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION
                     | vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE,
             )
-            .pfn_user_callback(Some(vulkan_debug_callback));
+            .pfn_user_callback(Some(vulkan_debug_callback))
+            .user_data(&*state as *const DebugState as *mut c_void);
 
         let loader = ash::ext::debug_utils::Instance::new(entry, instance);
 
         let callback = unsafe {
             loader
                 .create_debug_utils_messenger(&debug_info, None)
-                .unwrap()
+                .map_err(|e| VulkanError::Instance(InstanceError::DebugUtilsMessengerCreationFailed(e)))?
         };
 
-        DebugCallback { callback, loader }
+        Ok(DebugCallback { callback, loader, state })
+    }
+
+    pub fn policy(&self) -> &DebugPolicy {
+        &self.state.policy
+    }
+
+    /// Errors since the last `end_frame`
+    pub fn frame_errors(&self) -> u32 {
+        self.state.frame_errors.load(Ordering::Relaxed)
+    }
+
+    /// Errors since the messenger was created
+    pub fn total_errors(&self) -> u32 {
+        self.state.total_errors.load(Ordering::Relaxed)
+    }
+
+    /// Reset the per-frame counter and return what it counted, then `check`
+    pub fn end_frame(&self) -> u32 {
+        self.state.end_frame()
+    }
+
+    /// Captured messages, oldest first, the capture starts over empty
+    pub fn take_messages(&self) -> Vec<DebugMessage> {
+        std::mem::take(&mut *self.state.messages.lock().unwrap())
+    }
+
+    /// Panic with the first reported error when `panic_on_error` is set.
+    /// The messenger itself can't unwind through the driver, so it only records the error
+    pub fn check(&self) {
+        self.state.check()
+    }
+}
+
+fn c_str(ptr: *const std::ffi::c_char) -> Option<String> {
+    match ptr.is_null() {
+        true => None,
+        false => Some(unsafe { CStr::from_ptr(ptr) }.to_string_lossy().into_owned()),
     }
 }
 
@@ -45,27 +279,189 @@ pub unsafe extern "system" fn vulkan_debug_callback(
     message_severity: vk::DebugUtilsMessageSeverityFlagsEXT,
     message_type: vk::DebugUtilsMessageTypeFlagsEXT,
     p_callback_data: *const vk::DebugUtilsMessengerCallbackDataEXT<'_>,
-    _user_data: *mut std::os::raw::c_void,
+    user_data: *mut c_void,
 ) -> vk::Bool32 {
     let callback_data = unsafe { *p_callback_data };
 
-    if callback_data.p_message.is_null() {
+    let Some(message) = c_str(callback_data.p_message) else {
+        return vk::FALSE;
+    };
+
+    if is_debug_printf(&callback_data) {
+        log_debug_printf(&callback_data, &message);
         return vk::FALSE;
     }
 
-    let message = unsafe { std::ffi::CStr::from_ptr(callback_data.p_message).to_string_lossy() };
+    // Messengers created elsewhere than `DebugCallback::new` come without a policy
+    let default = DebugPolicy::default();
+    let state = unsafe { (user_data as *const DebugState).as_ref() };
+    let policy = state.map_or(&default, |state| &state.policy);
+
+    let id = callback_data.message_id_number;
+    let id_name = c_str(callback_data.p_message_id_name);
 
-    if message_severity == vk::DebugUtilsMessageSeverityFlagsEXT::WARNING {
-        log::warn!("{}", message);
+    if policy.is_suppressed(id, id_name.as_deref()) {
+        return vk::FALSE;
     }
 
-    if message_severity == vk::DebugUtilsMessageSeverityFlagsEXT::ERROR {
-        log::error!("{}", message)
+    if let Some(state) = state && message_severity == vk::DebugUtilsMessageSeverityFlagsEXT::ERROR {
+        state.frame_errors.fetch_add(1, Ordering::Relaxed);
+        state.total_errors.fetch_add(1, Ordering::Relaxed);
+
+        if let Ok(mut first) = state.first_error.lock() {
+            first.get_or_insert_with(|| message.clone());
+        }
     }
 
-    if message_severity == vk::DebugUtilsMessageSeverityFlagsEXT::INFO {
-        //tracing::info!("{}", message)
+    if message_severity.as_raw() < policy.min_severity.as_raw() {
+        return vk::FALSE;
+    }
+
+    match message_severity {
+        vk::DebugUtilsMessageSeverityFlagsEXT::ERROR => log::error!("{}", message),
+        vk::DebugUtilsMessageSeverityFlagsEXT::WARNING => log::warn!("{}", message),
+        vk::DebugUtilsMessageSeverityFlagsEXT::INFO => log::info!("{}", message),
+        _ => log::trace!("{}", message),
+    }
+
+    if let Some(state) = state && policy.capture && let Ok(mut messages) = state.messages.lock() {
+        messages.push(DebugMessage {
+            severity: message_severity,
+            ty: message_type,
+            id,
+            id_name,
+            message,
+        });
     }
 
     vk::FALSE
 }
+
+fn is_debug_printf(data: &vk::DebugUtilsMessengerCallbackDataEXT<'_>) -> bool {
+    if data.p_message_id_name.is_null() {
+        return false;
+    }
+
+    // "WARNING-DEBUG-PRINTF" in recent layers, "UNASSIGNED-DEBUG-PRINTF" before
+    let id = unsafe { CStr::from_ptr(data.p_message_id_name) };
+    id.to_bytes().ends_with(b"DEBUG-PRINTF")
+}
+
+/// Log the printf text without the layer's object and message ID prefix,
+/// tagged with the innermost command buffer label, which the render graph sets to the pass name
+fn log_debug_printf(data: &vk::DebugUtilsMessengerCallbackDataEXT<'_>, message: &str) {
+
+    let text = message.rsplit(" | ").next().unwrap_or(message).trim();
+
+    let labels = match data.p_cmd_buf_labels.is_null() {
+        true => &[][..],
+        false => unsafe { std::slice::from_raw_parts(data.p_cmd_buf_labels, data.cmd_buf_label_count as usize) },
+    };
+
+    let pass = labels.last()
+        .and_then(|label| unsafe { label.label_name_as_c_str() })
+        .map(|name| name.to_string_lossy());
+
+    match pass {
+        Some(pass) => log::info!(target: "shader", "[{}] {}", pass, text),
+        None => log::info!(target: "shader", "{}", text),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CString;
+    use super::*;
+
+    const ERROR: vk::DebugUtilsMessageSeverityFlagsEXT = vk::DebugUtilsMessageSeverityFlagsEXT::ERROR;
+    const WARNING: vk::DebugUtilsMessageSeverityFlagsEXT = vk::DebugUtilsMessageSeverityFlagsEXT::WARNING;
+    const INFO: vk::DebugUtilsMessageSeverityFlagsEXT = vk::DebugUtilsMessageSeverityFlagsEXT::INFO;
+
+    /// Call the messenger like the layer would, with `state` as user data
+    fn send(state: &DebugState, severity: vk::DebugUtilsMessageSeverityFlagsEXT, id: i32, id_name: &str, message: &str) {
+        let id_name = CString::new(id_name).unwrap();
+        let message = CString::new(message).unwrap();
+
+        let data = vk::DebugUtilsMessengerCallbackDataEXT::default()
+            .message_id_number(id)
+            .message_id_name(&id_name)
+            .message(&message);
+
+        let result = unsafe {
+            vulkan_debug_callback(
+                severity,
+                vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION,
+                &data,
+                state as *const DebugState as *mut c_void,
+            )
+        };
+
+        assert_eq!(result, vk::FALSE);
+    }
+
+    fn messages(state: &DebugState) -> Vec<String> {
+        state.messages.lock().unwrap().iter().map(|m| m.message.clone()).collect()
+    }
+
+    #[test]
+    fn suppression_by_id_and_name() {
+        let policy = DebugPolicy::default()
+            .suppress_id(7)
+            .suppress_name("VUID-vkCmdDraw-None-08600");
+
+        assert!(policy.is_suppressed(7, None));
+        assert!(policy.is_suppressed(1, Some("VUID-vkCmdDraw-None-08600")));
+        assert!(!policy.is_suppressed(1, Some("VUID-vkCmdDraw-None")));
+        assert!(!policy.is_suppressed(1, None));
+    }
+
+    #[test]
+    fn suppressed_errors_are_not_counted() {
+        let state = DebugState::new(DebugPolicy::default().capture(true).suppress_name("VUID-ignored"));
+
+        send(&state, ERROR, 1, "VUID-ignored", "ignored");
+        send(&state, ERROR, 2, "VUID-reported", "reported");
+
+        assert_eq!(state.total_errors.load(Ordering::Relaxed), 1);
+        assert_eq!(messages(&state), ["reported"]);
+    }
+
+    #[test]
+    fn severity_threshold() {
+        let state = DebugState::new(DebugPolicy::default().capture(true));
+
+        send(&state, INFO, 1, "info", "dropped");
+        send(&state, WARNING, 2, "warning", "kept warning");
+        send(&state, ERROR, 3, "error", "kept error");
+
+        assert_eq!(messages(&state), ["kept warning", "kept error"]);
+    }
+
+    #[test]
+    fn end_frame_resets_the_frame_count() {
+        let state = DebugState::new(DebugPolicy::default());
+
+        send(&state, ERROR, 1, "a", "first");
+        send(&state, ERROR, 2, "b", "second");
+        send(&state, WARNING, 3, "c", "warning");
+
+        assert_eq!(state.end_frame(), 2);
+        assert_eq!(state.end_frame(), 0);
+
+        send(&state, ERROR, 4, "d", "third");
+
+        assert_eq!(state.end_frame(), 1);
+        assert_eq!(state.total_errors.load(Ordering::Relaxed), 3);
+    }
+
+    #[test]
+    #[should_panic(expected = "Vulkan validation error: first")]
+    fn end_frame_panics_with_the_first_error() {
+        let state = DebugState::new(DebugPolicy::testing());
+
+        send(&state, ERROR, 1, "a", "first");
+        send(&state, ERROR, 2, "b", "second");
+
+        state.end_frame();
+    }
+}
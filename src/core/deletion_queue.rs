@@ -0,0 +1,153 @@
+use std::{mem, sync::{Arc, Mutex}};
+
+use ash::vk::{self, Handle};
+use log::debug;
+
+use crate::Device;
+
+/// Vulkan object waiting until the GPU no longer uses it
+pub(crate) enum Deferred {
+    Image(vk::Image, vk_mem::Allocation),
+    Buffer(vk::Buffer, vk_mem::Allocation),
+    ImageView(vk::ImageView),
+    FrameBuffer(vk::Framebuffer),
+    RenderPass(vk::RenderPass),
+    Pipeline(vk::Pipeline),
+    PipelineCache(vk::PipelineCache),
+    PipelineLayout(vk::PipelineLayout),
+    DescriptorSetLayout(vk::DescriptorSetLayout),
+    DescriptorPool(vk::DescriptorPool),
+    QueryPool(vk::QueryPool),
+    Sampler(vk::Sampler),
+    ShaderModule(vk::ShaderModule),
+    CommandPool(vk::CommandPool),
+    Semaphore(vk::Semaphore),
+    Fence(vk::Fence),
+    Swapchain(vk::SwapchainKHR, ash::khr::swapchain::Device),
+}
+
+impl Deferred {
+
+    fn handle(&self) -> (vk::ObjectType, u64) {
+        fn key<H: Handle>(handle: H) -> (vk::ObjectType, u64) {
+            (H::TYPE, handle.as_raw())
+        }
+
+        match self {
+            Deferred::Image(raw, _) => key(*raw),
+            Deferred::Buffer(raw, _) => key(*raw),
+            Deferred::ImageView(raw) => key(*raw),
+            Deferred::FrameBuffer(raw) => key(*raw),
+            Deferred::RenderPass(raw) => key(*raw),
+            Deferred::Pipeline(raw) => key(*raw),
+            Deferred::PipelineCache(raw) => key(*raw),
+            Deferred::PipelineLayout(raw) => key(*raw),
+            Deferred::DescriptorSetLayout(raw) => key(*raw),
+            Deferred::DescriptorPool(raw) => key(*raw),
+            Deferred::QueryPool(raw) => key(*raw),
+            Deferred::Sampler(raw) => key(*raw),
+            Deferred::ShaderModule(raw) => key(*raw),
+            Deferred::CommandPool(raw) => key(*raw),
+            Deferred::Semaphore(raw) => key(*raw),
+            Deferred::Fence(raw) => key(*raw),
+            Deferred::Swapchain(raw, _) => key(*raw),
+        }
+    }
+
+    /// Safety: the GPU must be done with the object
+    pub(crate) unsafe fn destroy(self, device: &Device) {
+        let (ty, raw) = self.handle();
+        device.tracker.untrack(ty, raw);
+        device.memory.untrack(ty, raw);
+
+        unsafe {
+            match self {
+                Deferred::Image(raw, mut allocation) => device.allocator.destroy_image(raw, &mut allocation),
+                Deferred::Buffer(raw, mut allocation) => device.allocator.destroy_buffer(raw, &mut allocation),
+                Deferred::ImageView(raw) => device.destroy_image_view(raw, None),
+                Deferred::FrameBuffer(raw) => device.destroy_framebuffer(raw, None),
+                Deferred::RenderPass(raw) => device.destroy_render_pass(raw, None),
+                Deferred::Pipeline(raw) => device.destroy_pipeline(raw, None),
+                Deferred::PipelineCache(raw) => device.destroy_pipeline_cache(raw, None),
+                Deferred::PipelineLayout(raw) => device.destroy_pipeline_layout(raw, None),
+                Deferred::DescriptorSetLayout(raw) => device.destroy_descriptor_set_layout(raw, None),
+                Deferred::DescriptorPool(raw) => device.destroy_descriptor_pool(raw, None),
+                Deferred::QueryPool(raw) => device.destroy_query_pool(raw, None),
+                Deferred::Sampler(raw) => device.destroy_sampler(raw, None),
+                Deferred::ShaderModule(raw) => device.destroy_shader_module(raw, None),
+                Deferred::CommandPool(raw) => device.destroy_command_pool(raw, None),
+                Deferred::Semaphore(raw) => device.destroy_semaphore(raw, None),
+                Deferred::Fence(raw) => device.destroy_fence(raw, None),
+                Deferred::Swapchain(raw, loader) => loader.destroy_swapchain(raw, None),
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct Frames {
+    current: usize,
+    slots: Vec<Vec<Deferred>>,
+}
+
+/// Objects dropped while recording a frame slot are destroyed
+/// the next time that slot's fence is waited on
+#[derive(Clone, Default)]
+pub struct DeletionQueue {
+    frames: Arc<Mutex<Frames>>,
+}
+
+impl DeletionQueue {
+
+    pub(crate) fn push(&self, object: Deferred) {
+        let mut frames = self.frames.lock().unwrap();
+        let current = frames.current;
+
+        if frames.slots.len() <= current {
+            frames.slots.resize_with(current + 1, Vec::new);
+        }
+
+        frames.slots[current].push(object);
+    }
+
+    /// Make `slot` current and take everything dropped the last time it was recorded
+    pub(crate) fn begin_frame(&self, slot: usize) -> Vec<Deferred> {
+        let mut frames = self.frames.lock().unwrap();
+        frames.current = slot;
+
+        match frames.slots.get_mut(slot) {
+            Some(objects) => mem::take(objects),
+            None => vec![],
+        }
+    }
+
+    pub(crate) fn drain_all(&self) -> Vec<Deferred> {
+        let mut frames = self.frames.lock().unwrap();
+        frames.slots.drain(..).flatten().collect()
+    }
+
+    /// Number of objects waiting for destruction
+    pub fn pending(&self) -> usize {
+        let frames = self.frames.lock().unwrap();
+        frames.slots.iter().map(Vec::len).sum()
+    }
+}
+
+impl Device {
+
+    /// Destroy objects dropped while `slot` was last recorded,
+    /// call once the slot's fence has signaled
+    pub(crate) fn begin_frame(&self, slot: usize) {
+        puffin::profile_scope!("DeletionQueue::flush");
+
+        let objects = self.deletion_queue.begin_frame(slot);
+
+        if !objects.is_empty() {
+            debug!("Destroy {} deferred objects of frame slot {}", objects.len(), slot);
+        }
+
+        for object in objects {
+            unsafe { object.destroy(self) };
+        }
+    }
+}
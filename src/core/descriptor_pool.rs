@@ -3,23 +3,48 @@
 
 
 use ash::vk;
+use crate::core::{Deferred, DeletionQueue};
 
-use crate::{Device, VulkanError, VulkanResult};
+use crate::{DescriptorError, Device, VulkanError, VulkanResult};
 
 pub struct DescriptorPool {
-    pub raw: vk::DescriptorPool
+    pub raw: vk::DescriptorPool,
+    deletion: DeletionQueue,
+}
+
+impl Drop for DescriptorPool {
+    fn drop(&mut self) {
+        self.deletion.push(Deferred::DescriptorPool(self.raw));
+    }
 }
 
 impl DescriptorPool {
-    pub fn create_descriptor_set(&self, device: &Device, layouts: &[vk::DescriptorSetLayout]) -> Vec<vk::DescriptorSet> {
+    pub fn create_descriptor_set(&self, device: &Device, layouts: &[vk::DescriptorSetLayout]) -> VulkanResult<Vec<vk::DescriptorSet>> {
 
         let desc = vk::DescriptorSetAllocateInfo::default()
             .descriptor_pool(self.raw)
             .set_layouts(&layouts);
 
-        let sets = unsafe { device.allocate_descriptor_sets(&desc).unwrap() };
+        unsafe {
+            device.allocate_descriptor_sets(&desc)
+                .map_err(|e| VulkanError::Descriptor(DescriptorError::AllocateSetsFailed(e)))
+        }
+    }
+
+    /// Only valid for pools created with `FREE_DESCRIPTOR_SET`
+    pub fn free_descriptor_sets(&self, device: &Device, sets: &[vk::DescriptorSet]) -> VulkanResult<()> {
+        unsafe {
+            device.free_descriptor_sets(self.raw, sets)
+                .map_err(|e| VulkanError::Descriptor(DescriptorError::FreeSetsFailed(e)))
+        }
+    }
 
-        sets
+    /// Return every set to the pool, none of them may still be in use by the GPU
+    pub fn reset(&self, device: &Device) -> VulkanResult<()> {
+        unsafe {
+            device.reset_descriptor_pool(self.raw, vk::DescriptorPoolResetFlags::empty())
+                .map_err(|e| VulkanError::Descriptor(DescriptorError::ResetPoolFailed(e)))
+        }
     }
 }
 
@@ -47,13 +72,21 @@ impl<'a> DescriptorPoolBuilder<'a> {
         self
     }
 
+    pub fn flags(mut self, flags: vk::DescriptorPoolCreateFlags) -> Self {
+        self.create_info = self.create_info.flags(flags);
+        self
+    }
+
     pub fn build(self) -> VulkanResult<DescriptorPool> {
 
         let pool = unsafe {
             self.device.create_descriptor_pool(&self.create_info, None)
-                .map_err(|e| VulkanError::Unknown(e))
+                .map_err(|e| VulkanError::Descriptor(DescriptorError::PoolCreationFailed(e)))
         }?;
 
-        Ok(DescriptorPool { raw: pool })
+        Ok(DescriptorPool {
+            raw: pool,
+            deletion: self.device.register(pool)
+        })
     }
 }
\ No newline at end of file
@@ -1,22 +1,34 @@
 
 
 use ash::vk;
+use crate::core::{Deferred, DeletionQueue};
 
-use crate::{Device, VulkanError, VulkanResult};
+use crate::{DescriptorError, Device, VulkanError, VulkanResult};
 
 pub struct DescriptorSetLayout {
-    pub raw: vk::DescriptorSetLayout
+    pub raw: vk::DescriptorSetLayout,
+    deletion: DeletionQueue,
+}
+
+impl Drop for DescriptorSetLayout {
+    fn drop(&mut self) {
+        self.deletion.push(Deferred::DescriptorSetLayout(self.raw));
+    }
 }
 
 pub struct DescriptorSetLayoutBuilder<'a> {
     device: &'a Device,
-    bindings: Vec<vk::DescriptorSetLayoutBinding<'static>>
+    bindings: Vec<vk::DescriptorSetLayoutBinding<'static>>,
+    binding_flags: Vec<vk::DescriptorBindingFlags>,
+    flags: vk::DescriptorSetLayoutCreateFlags,
 }
 
 impl<'a> DescriptorSetLayoutBuilder<'a> {
     pub fn new(device: &'a Device) -> Self {
         Self { 
             bindings: vec![],
+            binding_flags: vec![],
+            flags: vk::DescriptorSetLayoutCreateFlags::empty(),
             device,
         }
     }
@@ -26,16 +38,38 @@ impl<'a> DescriptorSetLayoutBuilder<'a> {
         self
     }
 
+    /// One entry per binding, needs descriptor indexing
+    pub fn binding_flags(mut self, flags: Vec<vk::DescriptorBindingFlags>) -> Self {
+        self.binding_flags = flags;
+        self
+    }
+
+    pub fn flags(mut self, flags: vk::DescriptorSetLayoutCreateFlags) -> Self {
+        self.flags = flags;
+        self
+    }
+
     pub fn build(self) -> VulkanResult<DescriptorSetLayout> {
 
-        let create_info = vk::DescriptorSetLayoutCreateInfo::default()
+        let mut binding_flags = vk::DescriptorSetLayoutBindingFlagsCreateInfo::default()
+            .binding_flags(&self.binding_flags);
+
+        let mut create_info = vk::DescriptorSetLayoutCreateInfo::default()
+            .flags(self.flags)
             .bindings(&self.bindings);
 
+        if !self.binding_flags.is_empty() {
+            create_info = create_info.push_next(&mut binding_flags);
+        }
+
         let layout = unsafe {
             self.device.create_descriptor_set_layout(&create_info, None)
-                .map_err(|e| VulkanError::Unknown(e))
+                .map_err(|e| VulkanError::Descriptor(DescriptorError::SetLayoutCreationFailed(e)))
         }?;
 
-        Ok(DescriptorSetLayout { raw: layout })
+        Ok(DescriptorSetLayout {
+            raw: layout,
+            deletion: self.device.register(layout)
+        })
     }
 }
\ No newline at end of file
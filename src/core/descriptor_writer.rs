@@ -0,0 +1,291 @@
+use std::ops::Range;
+
+use ash::vk;
+
+use crate::{DescriptorError, Device, GpuBuffer, ImageView, Sampler, VulkanError, VulkanResult};
+
+enum Infos {
+    Image(Range<usize>),
+    Buffer(Range<usize>),
+}
+
+struct PendingWrite {
+    set: vk::DescriptorSet,
+    binding: u32,
+    array_element: u32,
+    ty: vk::DescriptorType,
+    infos: Infos,
+}
+
+/// Collects descriptor writes and applies them with a single `update_descriptor_sets`.
+///
+/// Writes to consecutive array elements of the same binding are merged into one
+/// `VkWriteDescriptorSet`
+#[derive(Default)]
+pub struct DescriptorWriter {
+    writes: Vec<PendingWrite>,
+    images: Vec<vk::DescriptorImageInfo>,
+    buffers: Vec<vk::DescriptorBufferInfo>,
+}
+
+impl DescriptorWriter {
+
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn uniform_buffer(self, set: vk::DescriptorSet, binding: u32, buffer: &GpuBuffer) -> Self {
+        self.buffer(set, binding, 0, vk::DescriptorType::UNIFORM_BUFFER, buffer, 0, vk::WHOLE_SIZE)
+    }
+
+    /// `range` is the size seen by one draw, the offset comes from `cmd_bind_descriptor_sets`
+    pub fn dynamic_uniform_buffer(self, set: vk::DescriptorSet, binding: u32, buffer: &GpuBuffer, range: vk::DeviceSize) -> Self {
+        self.buffer(set, binding, 0, vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC, buffer, 0, range)
+    }
+
+    pub fn storage_buffer(self, set: vk::DescriptorSet, binding: u32, buffer: &GpuBuffer) -> Self {
+        self.buffer(set, binding, 0, vk::DescriptorType::STORAGE_BUFFER, buffer, 0, vk::WHOLE_SIZE)
+    }
+
+    pub fn combined_image_sampler(self, set: vk::DescriptorSet, binding: u32, view: &ImageView, sampler: &Sampler) -> Self {
+        self.image(set, binding, 0, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, Some(view), Some(sampler), vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+    }
+
+    pub fn sampled_image(self, set: vk::DescriptorSet, binding: u32, view: &ImageView) -> Self {
+        self.image(set, binding, 0, vk::DescriptorType::SAMPLED_IMAGE, Some(view), None, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+    }
+
+    pub fn storage_image(self, set: vk::DescriptorSet, binding: u32, view: &ImageView) -> Self {
+        self.image(set, binding, 0, vk::DescriptorType::STORAGE_IMAGE, Some(view), None, vk::ImageLayout::GENERAL)
+    }
+
+    pub fn sampler(self, set: vk::DescriptorSet, binding: u32, sampler: &Sampler) -> Self {
+        self.image(set, binding, 0, vk::DescriptorType::SAMPLER, None, Some(sampler), vk::ImageLayout::UNDEFINED)
+    }
+
+    pub fn input_attachment(self, set: vk::DescriptorSet, binding: u32, view: &ImageView) -> Self {
+        self.image(set, binding, 0, vk::DescriptorType::INPUT_ATTACHMENT, Some(view), None, vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
+    }
+
+    /// Write one element of a buffer binding
+    #[allow(clippy::too_many_arguments)]
+    pub fn buffer(
+        mut self,
+        set: vk::DescriptorSet,
+        binding: u32,
+        array_element: u32,
+        ty: vk::DescriptorType,
+        buffer: &GpuBuffer,
+        offset: vk::DeviceSize,
+        range: vk::DeviceSize,
+    ) -> Self {
+        self.buffers.push(
+            vk::DescriptorBufferInfo::default()
+                .buffer(buffer.raw())
+                .offset(offset)
+                .range(range)
+        );
+
+        self.push(set, binding, array_element, ty, false);
+        self
+    }
+
+    /// Write one element of an image or sampler binding, pass `None` for the part the type doesn't use
+    #[allow(clippy::too_many_arguments)]
+    pub fn image(
+        mut self,
+        set: vk::DescriptorSet,
+        binding: u32,
+        array_element: u32,
+        ty: vk::DescriptorType,
+        view: Option<&ImageView>,
+        sampler: Option<&Sampler>,
+        layout: vk::ImageLayout,
+    ) -> Self {
+        self.images.push(
+            vk::DescriptorImageInfo::default()
+                .image_view(view.map(|v| v.raw).unwrap_or_default())
+                .sampler(sampler.map(|s| s.raw).unwrap_or_default())
+                .image_layout(layout)
+        );
+
+        self.push(set, binding, array_element, ty, true);
+        self
+    }
+
+    fn push(&mut self, set: vk::DescriptorSet, binding: u32, array_element: u32, ty: vk::DescriptorType, image: bool) {
+
+        let index = if image { self.images.len() - 1 } else { self.buffers.len() - 1 };
+
+        if let Some(last) = self.writes.last_mut() {
+            let count = match &last.infos {
+                Infos::Image(range) | Infos::Buffer(range) => range.len() as u32,
+            };
+
+            let same = last.set == set
+                && last.binding == binding
+                && last.ty == ty
+                && last.array_element + count == array_element;
+
+            match &mut last.infos {
+                Infos::Image(range) if same && image && range.end == index => {
+                    range.end += 1;
+                    return;
+                }
+                Infos::Buffer(range) if same && !image && range.end == index => {
+                    range.end += 1;
+                    return;
+                }
+                _ => {}
+            }
+        }
+
+        self.writes.push(PendingWrite {
+            set,
+            binding,
+            array_element,
+            ty,
+            infos: if image { Infos::Image(index..index + 1) } else { Infos::Buffer(index..index + 1) },
+        });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.writes.is_empty()
+    }
+
+    /// Apply every collected write, none of the sets may be in use by the GPU
+    pub fn update(self, device: &Device) -> VulkanResult<()> {
+
+        let mut writes = Vec::with_capacity(self.writes.len());
+
+        for write in &self.writes {
+
+            let raw = vk::WriteDescriptorSet::default()
+                .dst_set(write.set)
+                .dst_binding(write.binding)
+                .dst_array_element(write.array_element)
+                .descriptor_type(write.ty);
+
+            let raw = match (&write.infos, write.ty) {
+                (
+                    Infos::Buffer(range),
+                    vk::DescriptorType::UNIFORM_BUFFER
+                    | vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC
+                    | vk::DescriptorType::STORAGE_BUFFER
+                    | vk::DescriptorType::STORAGE_BUFFER_DYNAMIC
+                ) => raw.buffer_info(&self.buffers[range.clone()]),
+                (
+                    Infos::Image(range),
+                    vk::DescriptorType::SAMPLER
+                    | vk::DescriptorType::COMBINED_IMAGE_SAMPLER
+                    | vk::DescriptorType::SAMPLED_IMAGE
+                    | vk::DescriptorType::STORAGE_IMAGE
+                    | vk::DescriptorType::INPUT_ATTACHMENT
+                ) => raw.image_info(&self.images[range.clone()]),
+                (_, ty) => {
+                    return Err(VulkanError::Descriptor(DescriptorError::WrongWriteType {
+                        binding: write.binding,
+                        ty,
+                    }));
+                }
+            };
+
+            writes.push(raw);
+        }
+
+        if !writes.is_empty() {
+            unsafe { device.update_descriptor_sets(&writes, &[]) };
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ash::vk::Handle;
+    use super::*;
+
+    fn set(raw: u64) -> vk::DescriptorSet {
+        vk::DescriptorSet::from_raw(raw)
+    }
+
+    fn buffer(writer: &mut DescriptorWriter, set: vk::DescriptorSet, binding: u32, element: u32) {
+        writer.buffers.push(vk::DescriptorBufferInfo::default());
+        writer.push(set, binding, element, vk::DescriptorType::STORAGE_BUFFER, false);
+    }
+
+    fn image(writer: &mut DescriptorWriter, set: vk::DescriptorSet, binding: u32, element: u32, ty: vk::DescriptorType) {
+        writer.images.push(vk::DescriptorImageInfo::default());
+        writer.push(set, binding, element, ty, true);
+    }
+
+    /// (binding, first element, image infos, range of infos) of every pending write
+    fn writes(writer: &DescriptorWriter) -> Vec<(u32, u32, bool, Range<usize>)> {
+        writer.writes.iter()
+            .map(|write| match &write.infos {
+                Infos::Image(range) => (write.binding, write.array_element, true, range.clone()),
+                Infos::Buffer(range) => (write.binding, write.array_element, false, range.clone()),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn consecutive_elements_are_merged() {
+        let mut writer = DescriptorWriter::new();
+
+        for element in 3..7 {
+            buffer(&mut writer, set(1), 0, element);
+        }
+
+        assert_eq!(writes(&writer), vec![(0, 3, false, 0..4)]);
+    }
+
+    #[test]
+    fn gaps_start_a_new_write() {
+        let mut writer = DescriptorWriter::new();
+
+        buffer(&mut writer, set(1), 0, 0);
+        buffer(&mut writer, set(1), 0, 1);
+        buffer(&mut writer, set(1), 0, 3);
+        buffer(&mut writer, set(1), 0, 2);
+
+        assert_eq!(writes(&writer), vec![(0, 0, false, 0..2), (0, 3, false, 2..3), (0, 2, false, 3..4)]);
+    }
+
+    #[test]
+    fn different_targets_are_not_merged() {
+        let sampler = vk::DescriptorType::COMBINED_IMAGE_SAMPLER;
+        let mut writer = DescriptorWriter::new();
+
+        image(&mut writer, set(1), 0, 0, sampler);
+        image(&mut writer, set(2), 0, 1, sampler);
+        image(&mut writer, set(2), 1, 2, sampler);
+        image(&mut writer, set(2), 1, 3, vk::DescriptorType::SAMPLED_IMAGE);
+
+        assert_eq!(writes(&writer), vec![
+            (0, 0, true, 0..1),
+            (0, 1, true, 1..2),
+            (1, 2, true, 2..3),
+            (1, 3, true, 3..4),
+        ]);
+    }
+
+    #[test]
+    fn images_and_buffers_keep_their_own_infos() {
+        let mut writer = DescriptorWriter::new();
+
+        buffer(&mut writer, set(1), 0, 0);
+        image(&mut writer, set(1), 1, 0, vk::DescriptorType::STORAGE_IMAGE);
+        buffer(&mut writer, set(1), 0, 1);
+        image(&mut writer, set(1), 1, 1, vk::DescriptorType::STORAGE_IMAGE);
+        image(&mut writer, set(1), 1, 2, vk::DescriptorType::STORAGE_IMAGE);
+
+        assert_eq!(writes(&writer), vec![
+            (0, 0, false, 0..1),
+            (1, 0, true, 0..1),
+            (0, 1, false, 1..2),
+            (1, 1, true, 1..3),
+        ]);
+    }
+}
@@ -1,22 +1,171 @@
-use std::{ffi::CStr, mem::ManuallyDrop};
+use std::{ffi::{CStr, CString}, mem::ManuallyDrop};
 
-use ash::vk;
+use ash::vk::{self, Handle};
+use log::{error, info, warn};
 use vk_mem::Allocator;
 
-use crate::{Instance, VulkanResult};
+use crate::{DeletionQueue, Instance, LogicalDeviceError, MemoryTracker, ObjectTracker, PhysicalDevice, VulkanError, VulkanResult};
+
+/// Optional device features negotiated at device creation
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DeviceFeatures {
+    pub descriptor_indexing: bool,
+    pub timeline_semaphore: bool,
+    pub buffer_device_address: bool,
+    pub synchronization2: bool,
+    pub dynamic_rendering: bool,
+    pub fill_mode_non_solid: bool,
+    pub pipeline_statistics_query: bool,
+    pub sampler_anisotropy: bool,
+}
+
+impl DeviceFeatures {
+
+    pub fn all() -> Self {
+        Self {
+            descriptor_indexing: true,
+            timeline_semaphore: true,
+            buffer_device_address: true,
+            synchronization2: true,
+            dynamic_rendering: true,
+            fill_mode_non_solid: true,
+            pipeline_statistics_query: true,
+            sampler_anisotropy: true,
+        }
+    }
+
+    /// Features set here but not in `other`
+    fn difference(&self, other: &DeviceFeatures) -> DeviceFeatures {
+        DeviceFeatures {
+            descriptor_indexing: self.descriptor_indexing && !other.descriptor_indexing,
+            timeline_semaphore: self.timeline_semaphore && !other.timeline_semaphore,
+            buffer_device_address: self.buffer_device_address && !other.buffer_device_address,
+            synchronization2: self.synchronization2 && !other.synchronization2,
+            dynamic_rendering: self.dynamic_rendering && !other.dynamic_rendering,
+            fill_mode_non_solid: self.fill_mode_non_solid && !other.fill_mode_non_solid,
+            pipeline_statistics_query: self.pipeline_statistics_query && !other.pipeline_statistics_query,
+            sampler_anisotropy: self.sampler_anisotropy && !other.sampler_anisotropy,
+        }
+    }
+
+    fn intersect(&self, other: &DeviceFeatures) -> DeviceFeatures {
+        DeviceFeatures {
+            descriptor_indexing: self.descriptor_indexing && other.descriptor_indexing,
+            timeline_semaphore: self.timeline_semaphore && other.timeline_semaphore,
+            buffer_device_address: self.buffer_device_address && other.buffer_device_address,
+            synchronization2: self.synchronization2 && other.synchronization2,
+            dynamic_rendering: self.dynamic_rendering && other.dynamic_rendering,
+            fill_mode_non_solid: self.fill_mode_non_solid && other.fill_mode_non_solid,
+            pipeline_statistics_query: self.pipeline_statistics_query && other.pipeline_statistics_query,
+            sampler_anisotropy: self.sampler_anisotropy && other.sampler_anisotropy,
+        }
+    }
+}
 
 pub struct Device {
     pub(crate) allocator: ManuallyDrop<Allocator>,
     pub(crate) queue_family_props: Vec<vk::QueueFamilyProperties>,
+    /// Family of the single queue the device was created with
+    pub(crate) queue_family: u32,
+    pub(crate) api_version: u32,
+    pub(crate) features: DeviceFeatures,
+    pub(crate) extensions: Vec<&'static CStr>,
+    /// Loaded when dynamic rendering comes from the extension instead of Vulkan 1.3
+    pub(crate) dynamic_rendering_ext: Option<ash::khr::dynamic_rendering::Device>,
+    /// Loaded when synchronization2 comes from the extension instead of Vulkan 1.3
+    pub(crate) synchronization2_ext: Option<ash::khr::synchronization2::Device>,
+    /// Loaded when the instance has debug utils enabled
+    pub(crate) debug_utils: Option<ash::ext::debug_utils::Device>,
+    pub(crate) deletion_queue: DeletionQueue,
+    pub(crate) tracker: ObjectTracker,
+    pub(crate) memory: MemoryTracker,
+    pub(crate) memory_heaps: Vec<vk::MemoryHeap>,
+    /// VK_EXT_memory_budget is enabled and reported to `vk_mem`
+    pub(crate) memory_budget_ext: bool,
     pub(crate) raw: ash::Device,
 }
 
-impl Device {
-    pub fn destroy(&mut self) {
+/// Waits for the GPU, destroys every deferred object, the allocator and the device.
+/// Objects created from this device must be dropped before
+impl Drop for Device {
+    fn drop(&mut self) {
         unsafe {
+            let _ = self.raw.device_wait_idle();
+
+            for object in self.deletion_queue.drain_all() {
+                object.destroy(self);
+            }
+
+            let leaks = self.tracker.report();
+
+            if leaks > 0 {
+                error!("{} Vulkan objects are still alive on device destruction", leaks);
+            }
+
             ManuallyDrop::drop(&mut self.allocator);
             self.raw.destroy_device(None);
         }
+
+        // Leaks fail tests, checked once the device itself is gone
+        if cfg!(test) && !std::thread::panicking() {
+            self.tracker.assert_no_leaks();
+        }
+    }
+}
+
+impl Device {
+
+    /// Features that were both requested and supported
+    pub fn features(&self) -> &DeviceFeatures {
+        &self.features
+    }
+
+    pub fn queue_family(&self) -> u32 {
+        self.queue_family
+    }
+
+    pub fn api_version(&self) -> u32 {
+        self.api_version
+    }
+
+    pub fn is_extension_enabled(&self, name: &CStr) -> bool {
+        self.extensions.contains(&name)
+    }
+
+    pub fn deletion_queue(&self) -> &DeletionQueue {
+        &self.deletion_queue
+    }
+
+    pub fn tracker(&self) -> &ObjectTracker {
+        &self.tracker
+    }
+
+    /// Track a newly created object, the returned queue destroys it on Drop
+    pub(crate) fn register<H: Handle>(&self, handle: H) -> DeletionQueue {
+        self.tracker.track(H::TYPE, handle.as_raw());
+        self.deletion_queue.clone()
+    }
+
+    /// Name shown by validation layers, debuggers and the leak report
+    pub fn set_object_name<H: Handle + Copy>(&self, handle: H, name: &str) {
+        self.tracker.set_name(H::TYPE, handle.as_raw(), name);
+        self.memory.set_name(H::TYPE, handle.as_raw(), name);
+
+        let Some(debug_utils) = &self.debug_utils else {
+            return;
+        };
+
+        let Ok(name) = CString::new(name) else {
+            return;
+        };
+
+        let name_info = vk::DebugUtilsObjectNameInfoEXT::default()
+            .object_handle(handle)
+            .object_name(&name);
+
+        if let Err(e) = unsafe { debug_utils.set_debug_utils_object_name(&name_info) } {
+            warn!("Error set object name {:?}: {:?}", name, e);
+        }
     }
 }
 
@@ -28,49 +177,259 @@ impl std::ops::Deref for Device {
 }
 
 pub struct DeviceBuilder<'a> {
-    phys_dev: &'a vk::PhysicalDevice,
+    phys_dev: &'a PhysicalDevice,
     instance: &'a Instance,
-    extenions: Vec<&'static CStr>
+    extenions: Vec<&'static CStr>,
+    features: DeviceFeatures,
+    queue_family: Option<u32>,
 }
 
 impl<'a> DeviceBuilder<'a> {
 
-    pub fn default(instance: &'a Instance, phys_dev: &'a vk::PhysicalDevice) -> Self {
-        DeviceBuilder { 
-            instance, 
-            phys_dev, 
-            extenions: vec![c"VK_KHR_swapchain"] 
+    pub fn default(instance: &'a Instance, phys_dev: &'a PhysicalDevice) -> Self {
+        DeviceBuilder {
+            instance,
+            phys_dev,
+            extenions: vec![c"VK_KHR_swapchain"],
+            features: DeviceFeatures::all(),
+            queue_family: None,
         }
     }
 
-    pub fn build(self) -> VulkanResult<Device> {
+    /// Features to enable when supported, missing ones are left disabled
+    pub fn features(mut self, features: DeviceFeatures) -> Self {
+        self.features = features;
+        self
+    }
+
+    /// Family to create the queue from, the graphics and present family of the physical device by default
+    pub fn queue_family(mut self, index: u32) -> Self {
+        self.queue_family = Some(index);
+        self
+    }
+
+    pub fn extension(mut self, name: &'static CStr) -> Self {
+        self.extenions.push(name);
+        self
+    }
+
+    pub fn build(mut self) -> VulkanResult<Device> {
+
+        let phys_dev = self.phys_dev.raw;
+        let api_version = self.instance.api_version.min(self.phys_dev.properties.api_version);
+        let api_1_2 = api_version >= vk::API_VERSION_1_2;
+        let api_1_3 = api_version >= vk::API_VERSION_1_3;
+
+        // Dynamic rendering and synchronization2 are core in 1.3, extensions on top of 1.2
+        let dynamic_rendering_ext = !api_1_3 && api_1_2 && self.phys_dev.supports_extension(ash::khr::dynamic_rendering::NAME);
+        let synchronization2_ext = !api_1_3 && api_1_2 && self.phys_dev.supports_extension(ash::khr::synchronization2::NAME);
+
+        // ----------------- Supported Features -------------------------
+        let core = self.phys_dev.features;
+        let mut supported_12 = vk::PhysicalDeviceVulkan12Features::default();
+        let mut supported_13 = vk::PhysicalDeviceVulkan13Features::default();
+        let mut supported_dr = vk::PhysicalDeviceDynamicRenderingFeatures::default();
+        let mut supported_s2 = vk::PhysicalDeviceSynchronization2Features::default();
+
+        if api_1_2 {
+            let mut features2 = vk::PhysicalDeviceFeatures2::default()
+                .push_next(&mut supported_12);
+
+            if api_1_3 {
+                features2 = features2.push_next(&mut supported_13);
+            }
+
+            if dynamic_rendering_ext {
+                features2 = features2.push_next(&mut supported_dr);
+            }
+
+            if synchronization2_ext {
+                features2 = features2.push_next(&mut supported_s2);
+            }
+
+            unsafe { self.instance.raw.get_physical_device_features2(phys_dev, &mut features2) };
+        }
+
+        let supported = DeviceFeatures {
+            descriptor_indexing: supported_12.descriptor_indexing == vk::TRUE
+                && supported_12.runtime_descriptor_array == vk::TRUE
+                && supported_12.descriptor_binding_partially_bound == vk::TRUE
+                && supported_12.shader_sampled_image_array_non_uniform_indexing == vk::TRUE,
+            timeline_semaphore: supported_12.timeline_semaphore == vk::TRUE,
+            buffer_device_address: supported_12.buffer_device_address == vk::TRUE,
+            synchronization2: supported_13.synchronization2 == vk::TRUE || supported_s2.synchronization2 == vk::TRUE,
+            dynamic_rendering: supported_13.dynamic_rendering == vk::TRUE || supported_dr.dynamic_rendering == vk::TRUE,
+            fill_mode_non_solid: core.fill_mode_non_solid == vk::TRUE,
+            pipeline_statistics_query: core.pipeline_statistics_query == vk::TRUE,
+            sampler_anisotropy: core.sampler_anisotropy == vk::TRUE,
+        };
+
+        let enabled = self.features.intersect(&supported);
+
+        if enabled != self.features {
+            warn!("Requested device features: {:?}", self.features);
+            warn!("Unsupported device features are disabled: {:?}", self.features.difference(&enabled));
+        }
+
+        info!("Device features: {:?}", enabled);
+        // ----------------- End ------------------------------------
+
+        // Real per-heap budget instead of an estimate from the heap size
+        let memory_budget_ext = api_version >= vk::API_VERSION_1_1
+            && self.phys_dev.supports_extension(ash::ext::memory_budget::NAME);
+
+        if memory_budget_ext {
+            self.extenions.push(ash::ext::memory_budget::NAME);
+        }
+
+        let validation = *self.instance.validation();
+
+        // debugPrintfEXT compiles to a non-semantic instruction, core in 1.3
+        if validation.debug_printf && !api_1_3 {
+            match self.phys_dev.supports_extension(ash::khr::shader_non_semantic_info::NAME) {
+                true => self.extenions.push(ash::khr::shader_non_semantic_info::NAME),
+                false => warn!("VK_KHR_shader_non_semantic_info is not supported, shader printf is skipped"),
+            }
+        }
+
+        // ----------------- Enabled Features -------------------------
+        let core = vk::PhysicalDeviceFeatures::default()
+            .fill_mode_non_solid(enabled.fill_mode_non_solid)
+            .pipeline_statistics_query(enabled.pipeline_statistics_query)
+            .sampler_anisotropy(enabled.sampler_anisotropy)
+            // GPU-assisted validation writes its findings from the instrumented shaders
+            .vertex_pipeline_stores_and_atomics(validation.gpu_assisted && core.vertex_pipeline_stores_and_atomics == vk::TRUE)
+            .fragment_stores_and_atomics(validation.gpu_assisted && core.fragment_stores_and_atomics == vk::TRUE);
+
+        let mut enable_12 = vk::PhysicalDeviceVulkan12Features::default()
+            .timeline_semaphore(enabled.timeline_semaphore)
+            .buffer_device_address(enabled.buffer_device_address);
+
+        if enabled.descriptor_indexing {
+            enable_12 = enable_12
+                .descriptor_indexing(true)
+                .runtime_descriptor_array(true)
+                .descriptor_binding_partially_bound(true)
+                .shader_sampled_image_array_non_uniform_indexing(true)
+                .shader_storage_image_array_non_uniform_indexing(supported_12.shader_storage_image_array_non_uniform_indexing == vk::TRUE)
+                .shader_storage_buffer_array_non_uniform_indexing(supported_12.shader_storage_buffer_array_non_uniform_indexing == vk::TRUE)
+                .descriptor_binding_variable_descriptor_count(supported_12.descriptor_binding_variable_descriptor_count == vk::TRUE)
+                .descriptor_binding_update_unused_while_pending(supported_12.descriptor_binding_update_unused_while_pending == vk::TRUE);
+        }
+
+        let mut enable_13 = vk::PhysicalDeviceVulkan13Features::default()
+            .synchronization2(enabled.synchronization2)
+            .dynamic_rendering(enabled.dynamic_rendering);
+
+        let mut enable_dr = vk::PhysicalDeviceDynamicRenderingFeatures::default()
+            .dynamic_rendering(true);
+
+        let mut enable_s2 = vk::PhysicalDeviceSynchronization2Features::default()
+            .synchronization2(true);
+
+        let mut features2 = vk::PhysicalDeviceFeatures2::default()
+            .features(core);
+
+        if api_1_2 {
+            features2 = features2.push_next(&mut enable_12);
+        }
+
+        if api_1_3 {
+            features2 = features2.push_next(&mut enable_13);
+        }
+
+        if enabled.dynamic_rendering && dynamic_rendering_ext {
+            self.extenions.push(ash::khr::dynamic_rendering::NAME);
+            features2 = features2.push_next(&mut enable_dr);
+        }
+
+        if enabled.synchronization2 && synchronization2_ext {
+            self.extenions.push(ash::khr::synchronization2::NAME);
+            features2 = features2.push_next(&mut enable_s2);
+        }
+        // ----------------- End ------------------------------------
+
+        // Without a surface no family reports present support, fall back to any graphics family
+        let queue_family = self.queue_family
+            .or_else(|| self.phys_dev.graphics_present_family())
+            .or_else(|| {
+                self.phys_dev.queue_families()
+                    .iter()
+                    .find(|family| family.properties.queue_flags.contains(vk::QueueFlags::GRAPHICS))
+                    .map(|family| family.index)
+            })
+            .ok_or(VulkanError::LogicalDevice(LogicalDeviceError::NoGraphicsQueue))?;
 
         let queue_create_info = vk::DeviceQueueCreateInfo::default()
-            .queue_family_index(0)
+            .queue_family_index(queue_family)
             .queue_priorities(&[1.0]);
 
         let p_extenions = self.extenions.iter().map(|p| p.as_ptr() as *const i8).collect::<Vec<_>>();
 
         let binding = [queue_create_info];
-        let create_info = vk::DeviceCreateInfo::default()
+        let mut create_info = vk::DeviceCreateInfo::default()
             .enabled_extension_names(&p_extenions)
             .queue_create_infos(&binding);
 
+        // Vulkan 1.0 has no pNext feature chain
+        if api_1_2 {
+            create_info = create_info.push_next(&mut features2);
+        } else {
+            create_info = create_info.enabled_features(&core);
+        }
+
         let device = unsafe {
             self.instance.raw
-                .create_device(*self.phys_dev, &create_info, None)
-                .unwrap()
+                .create_device(phys_dev, &create_info, None)
+                .map_err(|e| VulkanError::LogicalDevice(LogicalDeviceError::CreateDevice(e)))?
+        };
+
+        let queue_prop = unsafe { self.instance.raw.get_physical_device_queue_family_properties(phys_dev) };
+        let memory_properties = self.phys_dev.memory_properties;
+        let mut create_info = vk_mem::AllocatorCreateInfo::new(&self.instance.raw, &device, phys_dev);
+        create_info.vulkan_api_version = api_version;
+
+        if enabled.buffer_device_address {
+            create_info.flags |= vk_mem::AllocatorCreateFlags::BUFFER_DEVICE_ADDRESS;
+        }
+
+        if memory_budget_ext {
+            create_info.flags |= vk_mem::AllocatorCreateFlags::EXT_MEMORY_BUDGET;
+        }
+
+        let allocator = match unsafe { vk_mem::Allocator::new(create_info) } {
+            Ok(allocator) => allocator,
+            Err(e) => {
+                unsafe { device.destroy_device(None) };
+                return Err(VulkanError::LogicalDevice(LogicalDeviceError::CreateAllocator(e)));
+            }
         };
 
-        let queue_prop = unsafe { self.instance.raw.get_physical_device_queue_family_properties(*self.phys_dev) };
-        let create_info = vk_mem::AllocatorCreateInfo::new(&self.instance.raw, &device, *self.phys_dev);
-        let allocator = unsafe { vk_mem::Allocator::new(create_info).unwrap() };
+        let dynamic_rendering_ext = (enabled.dynamic_rendering && dynamic_rendering_ext)
+            .then(|| ash::khr::dynamic_rendering::Device::new(&self.instance.raw, &device));
+
+        let synchronization2_ext = (enabled.synchronization2 && synchronization2_ext)
+            .then(|| ash::khr::synchronization2::Device::new(&self.instance.raw, &device));
+
+        let debug_utils = self.instance.is_extension_enabled(ash::ext::debug_utils::NAME)
+            .then(|| ash::ext::debug_utils::Device::new(&self.instance.raw, &device));
 
         Ok(Device {
             raw: device,
             allocator: ManuallyDrop::new(allocator),
-            queue_family_props: queue_prop
+            queue_family_props: queue_prop,
+            queue_family,
+            api_version,
+            features: enabled,
+            extensions: self.extenions,
+            dynamic_rendering_ext,
+            synchronization2_ext,
+            debug_utils,
+            deletion_queue: DeletionQueue::default(),
+            tracker: ObjectTracker::default(),
+            memory: MemoryTracker::default(),
+            memory_heaps: memory_properties.memory_heaps_as_slice().to_vec(),
+            memory_budget_ext,
         })
     }
 }
-
@@ -3,8 +3,16 @@ use thiserror::Error;
 
 #[derive(Debug, Error)]
 pub enum BufferError {
+    #[error("Failed create GpuBuffer of {0} bytes (Vulkan error: {1:?})")]
+    CreationFailed(u64, Result),
     #[error("Error update GpuBuffer")]
     Update(ash::vk::Result),
     #[error("Error get support vulkan api version")]
     LoadingVulkanApiVersion(Result),
+    #[error("GpuBuffer isn't host visible")]
+    NotMapped,
+    #[error("Write of {len} bytes at {offset} is outside of a GpuBuffer of {size} bytes")]
+    OutOfBounds { offset: u64, len: u64, size: u64 },
+    #[error("UniformRing frame of {capacity} bytes can't fit {requested} more bytes")]
+    RingFull { capacity: u64, requested: u64 },
 }
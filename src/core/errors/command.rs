@@ -0,0 +1,20 @@
+use ash::vk;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum CommandError {
+    #[error("Failed begin command buffer (Vulkan error: {0:?})")]
+    BeginFailed(vk::Result),
+    #[error("Failed end command buffer (Vulkan error: {0:?})")]
+    EndFailed(vk::Result),
+    #[error("{0} needs a bound {1:?} pipeline")]
+    NoPipeline(&'static str, vk::PipelineBindPoint),
+    #[error("{0} needs a bound pipeline layout")]
+    NoLayout(&'static str),
+    #[error("{0} is not allowed inside a render pass")]
+    InsideRenderPass(&'static str),
+    #[error("{0} is only allowed inside a render pass")]
+    OutsideRenderPass(&'static str),
+    #[error("{0} doesn't match the open {1}")]
+    WrongScope(&'static str, &'static str),
+}
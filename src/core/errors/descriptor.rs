@@ -0,0 +1,22 @@
+use ash::vk;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum DescriptorError {
+    #[error("Failed create DescriptorSetLayout (Vulkan error: {0:?})")]
+    SetLayoutCreationFailed(vk::Result),
+    #[error("Failed create DescriptorPool (Vulkan error: {0:?})")]
+    PoolCreationFailed(vk::Result),
+    #[error("Failed allocate DescriptorSets (Vulkan error: {0:?})")]
+    AllocateSetsFailed(vk::Result),
+    #[error("Failed free DescriptorSets (Vulkan error: {0:?})")]
+    FreeSetsFailed(vk::Result),
+    #[error("Failed reset DescriptorPool (Vulkan error: {0:?})")]
+    ResetPoolFailed(vk::Result),
+    #[error("Bindless descriptors need descriptor indexing, the device doesn't support it")]
+    IndexingUnsupported,
+    #[error("No free bindless slot in binding {binding} (capacity {capacity})")]
+    BindlessFull { binding: u32, capacity: u32 },
+    #[error("Descriptor type {ty:?} doesn't match the resource written to binding {binding}")]
+    WrongWriteType { binding: u32, ty: vk::DescriptorType },
+}
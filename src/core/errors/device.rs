@@ -5,4 +5,8 @@ use thiserror::Error;
 pub enum LogicalDeviceError {
     #[error("Failed create logical devices (Vulkan error: {0:?})")]
     CreateDevice(vk::Result),
+    #[error("Failed create memory allocator (Vulkan error: {0:?})")]
+    CreateAllocator(vk::Result),
+    #[error("Physical device has no graphics queue family")]
+    NoGraphicsQueue,
 }
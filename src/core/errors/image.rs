@@ -0,0 +1,12 @@
+use ash::vk;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ImageError {
+    #[error("Failed create Image {0}x{1} (Vulkan error: {2:?})")]
+    ImageCreationFailed(u32, u32, vk::Result),
+    #[error("Failed create ImageView (Vulkan error: {0:?})")]
+    ImageViewCreationFailed(vk::Result),
+    #[error("Failed create FrameBuffer (Vulkan error: {0:?})")]
+    FrameBufferCreationFailed(vk::Result),
+}
@@ -1,6 +1,6 @@
 
 pub mod app;
-use app::AppError;
+pub use app::AppError;
 
 pub mod instance;
 use ash::vk;
@@ -33,6 +33,27 @@ pub use command_pool::CommandPoolError;
 pub mod surface;
 pub use surface::SurfaceError;
 
+pub mod sync;
+pub use sync::SyncError;
+
+pub mod image;
+pub use image::ImageError;
+
+pub mod pipeline;
+pub use pipeline::PipelineError;
+
+pub mod descriptor;
+pub use descriptor::DescriptorError;
+
+pub mod reflection;
+pub use reflection::ReflectionError;
+
+pub mod query;
+pub use query::QueryError;
+
+pub mod command;
+pub use command::CommandError;
+
 use thiserror::Error;
 
 #[derive(Debug, Error)]
@@ -43,7 +64,7 @@ pub enum VulkanError {
     Instance(InstanceError),
     #[error("Physical device error: {0}")]
     PhysicalDevice(PhysicalDeviceError),
-    #[error("Logical device error: {0}")]
+    #[error("Command pool error: {0}")]
     CommandPool(CommandPoolError),
     #[error("Logical device error: {0}")]
     LogicalDevice(LogicalDeviceError),
@@ -59,7 +80,21 @@ pub enum VulkanError {
     Surface(SurfaceError),
     #[error("RenderPass error: {0}")]
     RenderPass(RenderPassError),
-    #[error("Unknown error")]
+    #[error("Sync error: {0}")]
+    Sync(SyncError),
+    #[error("Image error: {0}")]
+    Image(ImageError),
+    #[error("Pipeline error: {0}")]
+    Pipeline(PipelineError),
+    #[error("Descriptor error: {0}")]
+    Descriptor(DescriptorError),
+    #[error("Reflection error: {0}")]
+    Reflection(ReflectionError),
+    #[error("Query error: {0}")]
+    Query(QueryError),
+    #[error("Command error: {0}")]
+    Command(CommandError),
+    #[error("Unknown error (Vulkan error: {0:?})")]
     Unknown(vk::Result),
 }
 
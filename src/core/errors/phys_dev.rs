@@ -9,4 +9,12 @@ pub enum PhysicalDeviceError {
     EnumerateDeviceExtensionPropertiesFailed(vk::Result),
     #[error("Failed to get device layer properties (Vulkan error: {0:?})")]
     EnumerateDeviceLayerPropertiesFailed(vk::Result),
+    #[error("No Vulkan physical devices found")]
+    NoDevices,
+    #[error("No physical device satisfies the requirements")]
+    NoSuitableDevice,
+    #[error("Requested physical device not found: {0}")]
+    RequestedDeviceNotFound(String),
+    #[error("Requested physical device {0} is not suitable: {1}")]
+    RequestedDeviceUnsuitable(String, String),
 }
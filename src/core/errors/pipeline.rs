@@ -0,0 +1,22 @@
+use std::path::PathBuf;
+
+use ash::vk;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum PipelineError {
+    #[error("Missing pipeline state: {0}")]
+    MissingState(&'static str),
+    #[error("Failed create Pipeline (Vulkan error: {0:?})")]
+    PipelineCreationFailed(vk::Result),
+    #[error("Failed create PipelineLayout (Vulkan error: {0:?})")]
+    PipelineLayoutCreationFailed(vk::Result),
+    #[error("Failed create PipelineCache (Vulkan error: {0:?})")]
+    CacheCreationFailed(vk::Result),
+    #[error("Failed get PipelineCache data (Vulkan error: {0:?})")]
+    CacheDataFailed(vk::Result),
+    #[error("Failed merge PipelineCaches (Vulkan error: {0:?})")]
+    CacheMergeFailed(vk::Result),
+    #[error("Failed write pipeline cache {0:?}: {1}")]
+    CacheIo(PathBuf, std::io::Error),
+}
@@ -0,0 +1,12 @@
+use ash::vk;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum QueryError {
+    #[error("Failed create QueryPool (Vulkan error: {0:?})")]
+    PoolCreationFailed(vk::Result),
+    #[error("Failed get query results (Vulkan error: {0:?})")]
+    GetResultsFailed(vk::Result),
+    #[error("Pipeline statistics queries need the pipelineStatisticsQuery feature")]
+    StatisticsUnsupported,
+}
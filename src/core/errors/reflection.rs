@@ -0,0 +1,23 @@
+use ash::vk;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum ReflectionError {
+    #[error("Malformed SPIR-V: {0}")]
+    Malformed(&'static str),
+    #[error("Unsupported shader execution model {0}")]
+    UnsupportedStage(u32),
+    #[error("Binding {binding} of set {set} is {first:?} in one stage and {second:?} in another")]
+    BindingMismatch {
+        set: u32,
+        binding: u32,
+        first: vk::DescriptorType,
+        second: vk::DescriptorType,
+    },
+    #[error("Vertex input location {location}: shader expects {shader:?}, vertex type provides {vertex:?}")]
+    VertexInputMismatch {
+        location: u32,
+        shader: vk::Format,
+        vertex: Option<vk::Format>,
+    },
+}
@@ -5,4 +5,6 @@ use thiserror::Error;
 pub enum RenderPassError {
     #[error("Failed create RenderPass (Vulkan error: {0:?})")]
     CreateRenderPass(vk::Result),
+    #[error("RenderPass requires at least one subpass")]
+    MissingSubpasses,
 }
@@ -1,7 +1,13 @@
+use std::path::PathBuf;
+
 use thiserror::Error;
 
 #[derive(Debug, Error)]
 pub enum ShaderError {
     #[error("Error create Shader")]
     ShaderCreationFailed(ash::vk::Result),
+    #[error("Error read shader {0:?}: {1}")]
+    Io(PathBuf, std::io::Error),
+    #[error("Invalid SPIR-V in {0:?}: {1}")]
+    InvalidSpirv(PathBuf, String),
 }
@@ -5,4 +5,14 @@ use ash::vk;
 pub enum SurfaceError {
     #[error("Failed create SurfaceKHR (Vulkan error: {0:?})")]
     CreateSurface(vk::Result),
-}
\ No newline at end of file
+    #[error("Failed query surface support (Vulkan error: {0:?})")]
+    GetSurfaceSupport(vk::Result),
+    #[error("Failed query surface capabilities (Vulkan error: {0:?})")]
+    GetSurfaceCapabilities(vk::Result),
+    #[error("Failed query surface formats (Vulkan error: {0:?})")]
+    GetSurfaceFormats(vk::Result),
+    #[error("Failed query surface present modes (Vulkan error: {0:?})")]
+    GetPresentModes(vk::Result),
+    #[error("Window handle is not available: {0}")]
+    WindowHandle(String),
+}
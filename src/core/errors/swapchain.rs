@@ -4,4 +4,10 @@ use thiserror::Error;
 pub enum SwapchainError {
     #[error("Error create Swapchain")]
     SwapchainCreationFailed(ash::vk::Result),
+    #[error("Error get swapchain images")]
+    GetSwapchainImages(ash::vk::Result),
+    #[error("Surface reports no formats")]
+    NoSurfaceFormats,
+    #[error("Surface no longer supports {0:?}")]
+    FormatUnsupported(ash::vk::SurfaceFormatKHR),
 }
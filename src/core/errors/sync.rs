@@ -0,0 +1,19 @@
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum SyncError {
+    #[error("Error create Semaphore")]
+    SemaphoreCreationFailed(ash::vk::Result),
+    #[error("Timeline semaphores are not enabled on this device")]
+    TimelineSemaphoreNotSupported,
+    #[error("Error get semaphore counter value")]
+    GetCounterValueFailed(ash::vk::Result),
+    #[error("Error wait for semaphore")]
+    WaitFailed(ash::vk::Result),
+    #[error("Error signal semaphore")]
+    SignalFailed(ash::vk::Result),
+    #[error("Error submit commands to queue")]
+    QueueSubmitFailed(ash::vk::Result),
+    #[error("Error create Fence")]
+    FenceCreationFailed(ash::vk::Result),
+}
@@ -1,10 +1,18 @@
 
 use ash::vk;
+use crate::core::{Deferred, DeletionQueue};
 
-use crate::{Device, VulkanError, VulkanResult};
+use crate::{Device, SyncError, VulkanError, VulkanResult};
 
 pub struct Fence {
-    pub raw: vk::Fence
+    pub raw: vk::Fence,
+    deletion: DeletionQueue,
+}
+
+impl Drop for Fence {
+    fn drop(&mut self) {
+        self.deletion.push(Deferred::Fence(self.raw));
+    }
 }
 
 pub struct FenceBuilder<'a> {
@@ -21,12 +29,13 @@ impl<'a> FenceBuilder<'a> {
         
         let fence = unsafe { 
             self.device.create_fence(&self.create_info, None).map_err(|e| {
-                VulkanError::Unknown(e)
+                VulkanError::Sync(SyncError::FenceCreationFailed(e))
             })
         }?;
 
         Ok(Fence {
-            raw: fence
+            raw: fence,
+            deletion: self.device.register(fence)
         })
     }
 }
\ No newline at end of file
@@ -1,16 +1,16 @@
 use ash::vk;
+use crate::core::{Deferred, DeletionQueue};
 
-use crate::{Device, VulkanError, VulkanResult};
+use crate::{Device, ImageError, VulkanError, VulkanResult};
 
 pub struct FrameBuffer {
     pub(crate) raw: vk::Framebuffer,
+    deletion: DeletionQueue,
 }
 
-impl FrameBuffer {
-    pub fn destroy(&self, device: &Device) {
-        unsafe {
-            device.destroy_framebuffer(self.raw, None);
-        }
+impl Drop for FrameBuffer {
+    fn drop(&mut self) {
+        self.deletion.push(Deferred::FrameBuffer(self.raw));
     }
 }
 
@@ -60,10 +60,13 @@ impl<'a> FrameBufferBuilder<'a> {
         let frame_buffer = unsafe { 
             
             self.device.create_framebuffer(&self.create_info, None).map_err(|e| {
-                VulkanError::Unknown(e)
+                VulkanError::Image(ImageError::FrameBufferCreationFailed(e))
             })?
         };
-        Ok(FrameBuffer { raw: frame_buffer })
+        Ok(FrameBuffer {
+            raw: frame_buffer,
+            deletion: self.device.register(frame_buffer)
+        })
     }
   
 }
@@ -1,26 +1,54 @@
 
 
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 use ash::vk;
-use crate::{ShaderBuilder, ShaderModule, VulkanError, VulkanResult, core::device::Device};
+use crate::core::{Deferred, DeletionQueue};
+use crate::{PipelineError, ShaderBuilder, VulkanError, VulkanResult, core::device::Device};
 
 pub struct GraphicsPipeline {
-    pub raw: vk::Pipeline
+    pub raw: vk::Pipeline,
+    shaders: Vec<PathBuf>,
+    deletion: DeletionQueue,
 }
 
+impl GraphicsPipeline {
+    /// SPIR-V files the pipeline was built from, empty for modules passed directly
+    pub fn shaders(&self) -> &[PathBuf] {
+        &self.shaders
+    }
+}
+
+impl Drop for GraphicsPipeline {
+    fn drop(&mut self) {
+        self.deletion.push(Deferred::Pipeline(self.raw));
+    }
+}
+
+/// What a pipeline renders into
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum PipelineTarget {
+    RenderPass(vk::RenderPass),
+    /// Attachment formats for dynamic rendering, no render pass needed
+    Rendering {
+        color_formats: Vec<vk::Format>,
+        depth_format: Option<vk::Format>,
+    },
+}
 
 
 pub struct GraphicsPipelineBuilder<'n, S: AsRef<Path>> {
     device: &'n Device,
     pipeline_layout: Option<vk::PipelineLayout>,
-    render_pass: Option<vk::RenderPass>,
+    cache: vk::PipelineCache,
+    target: Option<PipelineTarget>,
     descriptor_set_layout: Option<&'n [vk::DescriptorSetLayout]>,
     color_blending_info: Option<vk::PipelineColorBlendStateCreateInfo<'n>>,
     vertex_input_info: Option<vk::PipelineVertexInputStateCreateInfo<'n>>,
     input_assembly_info: Option<vk::PipelineInputAssemblyStateCreateInfo<'n>>,
     multisampling_info: Option<vk::PipelineMultisampleStateCreateInfo<'n>>,
     rasterization: Option<vk::PipelineRasterizationStateCreateInfo<'n>>,
+    depth_stencil: Option<vk::PipelineDepthStencilStateCreateInfo<'n>>,
     viewport: Option<Vec<vk::Viewport>>,
     scissors: Option<Vec<vk::Rect2D>>,
     dynamic_state: Option<Vec<vk::DynamicState>>,
@@ -36,13 +64,15 @@ impl<'n, S: AsRef<Path>> GraphicsPipelineBuilder<'n, S> {
         Self { 
             device,
             pipeline_layout: None,
-            render_pass: None,
+            cache: vk::PipelineCache::null(),
+            target: None,
             descriptor_set_layout: None,
             color_blending_info: None,
             vertex_input_info: None,
             input_assembly_info: None,
             multisampling_info: None,
             rasterization: None,
+            depth_stencil: None,
             fragment_shader: None,
             vertex_shader: None,
             fragment_shader_path: None,
@@ -58,6 +88,12 @@ impl<'n, S: AsRef<Path>> GraphicsPipelineBuilder<'n, S> {
         self
     }
 
+    /// Depth test on with depth writes off when not set
+    pub fn depth_stencil(mut self, depth_stencil: vk::PipelineDepthStencilStateCreateInfo<'static>) -> Self {
+        self.depth_stencil = Some(depth_stencil);
+        self
+    }
+
     pub fn multisampling(mut self, multisampling: vk::PipelineMultisampleStateCreateInfo<'static>) -> Self {
         self.multisampling_info = Some(multisampling);
         self
@@ -88,8 +124,27 @@ impl<'n, S: AsRef<Path>> GraphicsPipelineBuilder<'n, S> {
         self
     }
 
+    pub fn cache(mut self, cache: vk::PipelineCache) -> Self {
+        self.cache = cache;
+        self
+    }
+
     pub fn render_pass(mut self, render_pass: vk::RenderPass) -> Self {
-        self.render_pass = Some(render_pass);
+        self.target = Some(PipelineTarget::RenderPass(render_pass));
+        self
+    }
+
+    /// Build for dynamic rendering instead of a render pass
+    pub fn rendering(mut self, color_formats: &[vk::Format], depth_format: Option<vk::Format>) -> Self {
+        self.target = Some(PipelineTarget::Rendering {
+            color_formats: color_formats.to_vec(),
+            depth_format
+        });
+        self
+    }
+
+    pub fn target(mut self, target: PipelineTarget) -> Self {
+        self.target = Some(target);
         self
     }
 
@@ -149,6 +204,9 @@ impl<'n, S: AsRef<Path>> GraphicsPipelineBuilder<'n, S> {
 
         // ----------------- Shader States -------------------------------
         let mut shader_states_infos = vec![];
+        // Modules loaded here only need to live until the pipeline is created
+        let mut shader_modules = vec![];
+        let mut shaders = vec![];
 
         if let Some(vertex) = self.vertex_shader {
             shader_states_infos.push(
@@ -160,6 +218,7 @@ impl<'n, S: AsRef<Path>> GraphicsPipelineBuilder<'n, S> {
         } else {
             if let Some(path) = self.vertex_shader_path {
 
+                shaders.push(path.as_ref().to_path_buf());
                 let shader = ShaderBuilder::from_file(self.device, path)?;
                 shader_states_infos.push(
                     vk::PipelineShaderStageCreateInfo::default()
@@ -168,7 +227,7 @@ impl<'n, S: AsRef<Path>> GraphicsPipelineBuilder<'n, S> {
                         .stage(vk::ShaderStageFlags::VERTEX)
                 );
 
-                std::mem::forget(shader);
+                shader_modules.push(shader);
             }
         }
 
@@ -183,6 +242,7 @@ impl<'n, S: AsRef<Path>> GraphicsPipelineBuilder<'n, S> {
         } else {
             if let Some(path) = self.fragment_shader_path {
 
+                shaders.push(path.as_ref().to_path_buf());
                 let shader = ShaderBuilder::from_file(self.device, path)?;
 
                 shader_states_infos.push(
@@ -192,7 +252,7 @@ impl<'n, S: AsRef<Path>> GraphicsPipelineBuilder<'n, S> {
                         .stage(vk::ShaderStageFlags::FRAGMENT)
                 );
 
-                std::mem::forget(shader);
+                shader_modules.push(shader);
             }
         }
         create_info = create_info.stages(&shader_states_infos);
@@ -200,8 +260,8 @@ impl<'n, S: AsRef<Path>> GraphicsPipelineBuilder<'n, S> {
 
 
         // --------------- Viewport and Scissors -------------------
-        let viewport = self.viewport.expect("Viewport");
-        let scissors = self.scissors.expect("Scissors");
+        let viewport = self.viewport.ok_or(missing("viewport"))?;
+        let scissors = self.scissors.ok_or(missing("scissors"))?;
 
         let viewport_info = vk::PipelineViewportStateCreateInfo::default()
             .viewports(&viewport)
@@ -211,22 +271,24 @@ impl<'n, S: AsRef<Path>> GraphicsPipelineBuilder<'n, S> {
             .viewport_state(&viewport_info);
         // ----------------- End ------------------------------------
 
-        let vertex_input_info = self.vertex_input_info.unwrap();
-        let input_assembly_info = self.input_assembly_info.unwrap();
-        let raster = self.rasterization.unwrap();
-        let multisampling = self.multisampling_info.unwrap();
-        let color_blend = self.color_blending_info.unwrap();
-        let layout = self.pipeline_layout.unwrap();
-        let render_pass = self.render_pass.unwrap();
-
-        let depth_stencil_state = vk::PipelineDepthStencilStateCreateInfo::default()
-            .depth_test_enable(true)
-            .depth_write_enable(false)
-            .depth_compare_op(vk::CompareOp::LESS)
-            .depth_bounds_test_enable(false)
-            .min_depth_bounds(0.0)
-            .max_depth_bounds(1.0)
-            .stencil_test_enable(false);
+        let vertex_input_info = self.vertex_input_info.ok_or(missing("vertex input"))?;
+        let input_assembly_info = self.input_assembly_info.ok_or(missing("input assembly"))?;
+        let raster = self.rasterization.ok_or(missing("rasterization"))?;
+        let multisampling = self.multisampling_info.ok_or(missing("multisampling"))?;
+        let color_blend = self.color_blending_info.ok_or(missing("color blending"))?;
+        let layout = self.pipeline_layout.ok_or(missing("pipeline layout"))?;
+        let target = self.target.ok_or(missing("render pass or rendering formats"))?;
+
+        let depth_stencil_state = self.depth_stencil.unwrap_or(
+            vk::PipelineDepthStencilStateCreateInfo::default()
+                .depth_test_enable(true)
+                .depth_write_enable(false)
+                .depth_compare_op(vk::CompareOp::LESS)
+                .depth_bounds_test_enable(false)
+                .min_depth_bounds(0.0)
+                .max_depth_bounds(1.0)
+                .stencil_test_enable(false)
+        );
 
         create_info = create_info
             .vertex_input_state(&vertex_input_info)
@@ -237,13 +299,39 @@ impl<'n, S: AsRef<Path>> GraphicsPipelineBuilder<'n, S> {
             .depth_stencil_state(
                 &depth_stencil_state
             )
-            .layout(layout)
-            .render_pass(render_pass);
+            .layout(layout);
+
+        // ----------------- Target ---------------------------------
+        let mut rendering_info = vk::PipelineRenderingCreateInfo::default();
+
+        match &target {
+            PipelineTarget::RenderPass(render_pass) => {
+                create_info = create_info.render_pass(*render_pass);
+            }
+            PipelineTarget::Rendering { color_formats, depth_format } => {
+                rendering_info = rendering_info
+                    .color_attachment_formats(color_formats)
+                    .depth_attachment_format(depth_format.unwrap_or(vk::Format::UNDEFINED));
+
+                create_info = create_info.push_next(&mut rendering_info);
+            }
+        }
+        // ----------------- End ------------------------------------
 
         let pipeline = unsafe {
-            self.device.create_graphics_pipelines(vk::PipelineCache::null(), &[create_info], None).expect("Error create Graphics Pipeline")[0]
+            self.device.create_graphics_pipelines(self.cache, &[create_info], None).map_err(|(_, e)| {
+                VulkanError::Pipeline(PipelineError::PipelineCreationFailed(e))
+            })?[0]
         };
 
-        Ok(GraphicsPipeline { raw: pipeline })
+        Ok(GraphicsPipeline {
+            raw: pipeline,
+            shaders,
+            deletion: self.device.register(pipeline)
+        })
     }
 }
+
+fn missing(state: &'static str) -> VulkanError {
+    VulkanError::Pipeline(PipelineError::MissingState(state))
+}
@@ -1,51 +1,100 @@
 
-use crate::{VulkanResult, core::device::Device};
+use std::mem::ManuallyDrop;
+
+use crate::{ImageError, MemoryCategory, VulkanError, VulkanResult, core::{Deferred, DeletionQueue, device::Device}};
 use ash::vk;
 use vk_mem::Alloc;
 
 pub struct Image {
     pub(crate) raw: vk::Image,
-    allocation: vk_mem::Allocation
+    pub(crate) format: vk::Format,
+    pub(crate) extent: vk::Extent3D,
+    pub(crate) mip_levels: u32,
+    pub(crate) array_layers: u32,
+    pub(crate) flags: vk::ImageCreateFlags,
+    pub(crate) image_type: vk::ImageType,
+    allocation: ManuallyDrop<vk_mem::Allocation>,
+    deletion: DeletionQueue,
+}
+
+impl Drop for Image {
+    fn drop(&mut self) {
+        // Taken once, the allocation is freed together with the image
+        let allocation = unsafe { ManuallyDrop::take(&mut self.allocation) };
+        self.deletion.push(Deferred::Image(self.raw, allocation));
+    }
 }
 
 impl Image {
-    pub fn destory(&mut self, device: &Device) {
-        unsafe {
-            device.allocator.destroy_image(self.raw, &mut self.allocation);
+
+    pub fn format(&self) -> vk::Format {
+        self.format
+    }
+
+    pub fn extent(&self) -> vk::Extent3D {
+        self.extent
+    }
+
+    pub fn mip_levels(&self) -> u32 {
+        self.mip_levels
+    }
+
+    pub fn array_layers(&self) -> u32 {
+        self.array_layers
+    }
+
+    pub fn is_cube(&self) -> bool {
+        self.flags.contains(vk::ImageCreateFlags::CUBE_COMPATIBLE)
+    }
+
+    /// Subresource range covering every mip level and array layer of the image
+    pub fn full_range(&self) -> vk::ImageSubresourceRange {
+        vk::ImageSubresourceRange {
+            aspect_mask: aspect_mask_from_format(self.format),
+            base_mip_level: 0,
+            level_count: self.mip_levels,
+            base_array_layer: 0,
+            layer_count: self.array_layers,
         }
     }
 }
 
+/// Number of mip levels of a full chain down to 1x1
+pub fn mip_levels_for_extent(extent: vk::Extent3D) -> u32 {
+    let max = extent.width.max(extent.height).max(extent.depth).max(1);
+    u32::BITS - max.leading_zeros()
+}
+
+/// Aspect mask to use for views and barriers of the given format
+pub fn aspect_mask_from_format(format: vk::Format) -> vk::ImageAspectFlags {
+    match format {
+        vk::Format::D16_UNORM
+        | vk::Format::X8_D24_UNORM_PACK32
+        | vk::Format::D32_SFLOAT => vk::ImageAspectFlags::DEPTH,
+        vk::Format::S8_UINT => vk::ImageAspectFlags::STENCIL,
+        vk::Format::D16_UNORM_S8_UINT
+        | vk::Format::D24_UNORM_S8_UINT
+        | vk::Format::D32_SFLOAT_S8_UINT => vk::ImageAspectFlags::DEPTH | vk::ImageAspectFlags::STENCIL,
+        _ => vk::ImageAspectFlags::COLOR,
+    }
+}
+
 pub struct ImageBuilder<'a> {
     pub device: &'a Device,
     pub create_info: vk::ImageCreateInfo<'static>,
     pub alloc_info: vk_mem::AllocationCreateInfo,
+    /// Inferred from the usage flags when `None`
+    pub category: Option<MemoryCategory>,
 }
 
 impl<'a> ImageBuilder<'a> {
     pub fn depth(device: &'a Device, format: vk::Format, extent: vk::Extent2D) -> Self {
-        Self { 
-            device,
-            create_info: vk::ImageCreateInfo::default()
-                .mip_levels(1)
-                .initial_layout(vk::ImageLayout::UNDEFINED)
-                .extent(extent.into())
-                .format(format)
-                .tiling(vk::ImageTiling::OPTIMAL)
-                .samples(vk::SampleCountFlags::TYPE_1)
-                .array_layers(1)
-                .sharing_mode(vk::SharingMode::EXCLUSIVE)
-                .usage(vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT)
-                .image_type(vk::ImageType::TYPE_2D), 
-            alloc_info: vk_mem::AllocationCreateInfo {
-                usage: vk_mem::MemoryUsage::AutoPreferDevice,
-                ..Default::default()
-            } 
-        }
+        Self::new_2d(device, format, extent)
+            .usage(vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT)
     }
 
     pub fn new_2d(device: &'a Device, format: vk::Format, extent: vk::Extent2D) -> Self {
-        Self { 
+        Self {
             device,
             create_info: vk::ImageCreateInfo::default()
                 .mip_levels(1)
@@ -57,24 +106,103 @@ impl<'a> ImageBuilder<'a> {
                 .array_layers(1)
                 .sharing_mode(vk::SharingMode::EXCLUSIVE)
                 .usage(vk::ImageUsageFlags::COLOR_ATTACHMENT)
-                .image_type(vk::ImageType::TYPE_2D), 
+                .image_type(vk::ImageType::TYPE_2D),
             alloc_info: vk_mem::AllocationCreateInfo {
                 usage: vk_mem::MemoryUsage::AutoPreferDevice,
                 ..Default::default()
-            } 
+            },
+            category: None,
         }
     }
 
+    /// 2D image with `layers` array layers, e.g. shadow cascades
+    pub fn new_2d_array(device: &'a Device, format: vk::Format, extent: vk::Extent2D, layers: u32) -> Self {
+        Self::new_2d(device, format, extent)
+            .array_layers(layers)
+    }
+
+    /// Cube compatible image with 6 layers per cube
+    pub fn cube(device: &'a Device, format: vk::Format, size: u32) -> Self {
+        Self::cube_array(device, format, size, 1)
+    }
+
+    pub fn cube_array(device: &'a Device, format: vk::Format, size: u32, cubes: u32) -> Self {
+        Self::new_2d(device, format, vk::Extent2D { width: size, height: size })
+            .flags(vk::ImageCreateFlags::CUBE_COMPATIBLE)
+            .array_layers(6 * cubes)
+    }
+
+    pub fn new_3d(device: &'a Device, format: vk::Format, extent: vk::Extent3D) -> Self {
+        let mut builder = Self::new_2d(device, format, vk::Extent2D { width: extent.width, height: extent.height });
+        builder.create_info = builder.create_info
+            .image_type(vk::ImageType::TYPE_3D)
+            .extent(extent);
+        builder
+    }
+
     pub fn usage(mut self, usage: vk::ImageUsageFlags) -> Self {
         self.create_info = self.create_info.usage(usage);
         self
     }
 
+    pub fn flags(mut self, flags: vk::ImageCreateFlags) -> Self {
+        self.create_info.flags |= flags;
+        self
+    }
+
+    pub fn mip_levels(mut self, levels: u32) -> Self {
+        self.create_info = self.create_info.mip_levels(levels);
+        self
+    }
+
+    /// Allocate the full mip chain down to 1x1
+    pub fn full_mip_chain(mut self) -> Self {
+        self.create_info = self.create_info.mip_levels(mip_levels_for_extent(self.create_info.extent));
+        self
+    }
+
+    pub fn array_layers(mut self, layers: u32) -> Self {
+        self.create_info = self.create_info.array_layers(layers);
+        self
+    }
+
+    pub fn samples(mut self, samples: vk::SampleCountFlags) -> Self {
+        self.create_info = self.create_info.samples(samples);
+        self
+    }
+
+    pub fn tiling(mut self, tiling: vk::ImageTiling) -> Self {
+        self.create_info = self.create_info.tiling(tiling);
+        self
+    }
+
+    pub fn category(mut self, category: MemoryCategory) -> Self {
+        self.category = Some(category);
+        self
+    }
+
     pub fn build(self) -> VulkanResult<Image> {
-        let (image, allocation) = unsafe { 
-            self.device.allocator.create_image(&self.create_info, &self.alloc_info).unwrap() 
+        let (image, allocation) = unsafe {
+            self.device.allocator.create_image(&self.create_info, &self.alloc_info).map_err(|e| {
+                VulkanError::Image(ImageError::ImageCreationFailed(self.create_info.extent.width, self.create_info.extent.height, e))
+            })?
         };
-        Ok(Image { raw: image, allocation })
+
+        let category = self.category.unwrap_or(MemoryCategory::from_image_usage(self.create_info.usage));
+        let size = self.device.allocator.get_allocation_info(&allocation).size;
+        self.device.memory.track(image, category, size);
+
+        Ok(Image {
+            raw: image,
+            format: self.create_info.format,
+            extent: self.create_info.extent,
+            mip_levels: self.create_info.mip_levels,
+            array_layers: self.create_info.array_layers,
+            flags: self.create_info.flags,
+            image_type: self.create_info.image_type,
+            allocation: ManuallyDrop::new(allocation),
+            deletion: self.device.register(image)
+        })
     }
 }
 
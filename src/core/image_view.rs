@@ -1,17 +1,17 @@
 
 use ash::vk;
-use crate::{VulkanError, VulkanResult, core::device::Device};
+use crate::core::{Deferred, DeletionQueue};
+use crate::{Image, ImageError, VulkanError, VulkanResult, aspect_mask_from_format, core::device::Device};
 
 
 pub struct ImageView {
-    pub(crate) raw: vk::ImageView
+    pub(crate) raw: vk::ImageView,
+    deletion: DeletionQueue,
 }
 
-impl ImageView {
-    pub fn destroy(&self, device: &Device) {
-        unsafe {
-            device.destroy_image_view(self.raw, None);
-        }
+impl Drop for ImageView {
+    fn drop(&mut self) {
+        self.deletion.push(Deferred::ImageView(self.raw));
     }
 }
 
@@ -32,51 +32,125 @@ impl<'a> ImageViewBuilder<'a> {
         self
     }
 
-    pub fn new_2d(device: &'a Device, format: vk::Format, image: vk::Image) -> Self {
-        Self { 
+    pub fn view_type(mut self, view_type: vk::ImageViewType) -> Self {
+        self.create_info = self.create_info.view_type(view_type);
+        self
+    }
+
+    pub fn aspect_mask(mut self, aspect_mask: vk::ImageAspectFlags) -> Self {
+        self.create_info.subresource_range.aspect_mask = aspect_mask;
+        self
+    }
+
+    /// View only `count` mip levels starting from `base`
+    pub fn mip_levels(mut self, base: u32, count: u32) -> Self {
+        self.create_info.subresource_range.base_mip_level = base;
+        self.create_info.subresource_range.level_count = count;
+        self
+    }
+
+    /// View only `count` array layers starting from `base`
+    pub fn array_layers(mut self, base: u32, count: u32) -> Self {
+        self.create_info.subresource_range.base_array_layer = base;
+        self.create_info.subresource_range.layer_count = count;
+        self
+    }
+
+    pub fn components(mut self, components: vk::ComponentMapping) -> Self {
+        self.create_info = self.create_info.components(components);
+        self
+    }
+
+    fn with_type(device: &'a Device, view_type: vk::ImageViewType, format: vk::Format, image: vk::Image) -> Self {
+        Self {
             device,
-            create_info: vk::ImageViewCreateInfo::default() 
+            create_info: vk::ImageViewCreateInfo::default()
                 .components(vk::ComponentMapping::default())
                 .format(format)
                 .image(image)
                 .subresource_range(vk::ImageSubresourceRange {
-                    aspect_mask: vk::ImageAspectFlags::COLOR,
+                    aspect_mask: aspect_mask_from_format(format),
                     base_mip_level: 0,
                     level_count: 1,
                     base_array_layer: 0,
                     layer_count: 1,
-                })   
-                .view_type(vk::ImageViewType::TYPE_2D)
+                })
+                .view_type(view_type)
         }
     }
 
+    pub fn new_2d(device: &'a Device, format: vk::Format, image: vk::Image) -> Self {
+        Self::with_type(device, vk::ImageViewType::TYPE_2D, format, image)
+            .aspect_mask(vk::ImageAspectFlags::COLOR)
+    }
+
+    /// Depth view, stencil formats get a combined depth and stencil aspect
     pub fn depth(device: &'a Device, format: vk::Format, image: vk::Image) -> Self {
-        Self { 
-            device,
-            create_info: vk::ImageViewCreateInfo::default() 
-                .components(vk::ComponentMapping::default())
-                .image(image)
-                .format(format)
-                .subresource_range(vk::ImageSubresourceRange {
-                    aspect_mask: vk::ImageAspectFlags::DEPTH,
-                    base_mip_level: 0,
-                    level_count: 1,
-                    base_array_layer: 0,
-                    layer_count: 1,
-                })   
-                .view_type(vk::ImageViewType::TYPE_2D)
-        }
+        Self::with_type(device, vk::ImageViewType::TYPE_2D, format, image)
+    }
+
+    pub fn new_2d_array(device: &'a Device, format: vk::Format, image: vk::Image, layers: u32) -> Self {
+        Self::with_type(device, vk::ImageViewType::TYPE_2D_ARRAY, format, image)
+            .array_layers(0, layers)
+    }
+
+    pub fn cube(device: &'a Device, format: vk::Format, image: vk::Image) -> Self {
+        Self::with_type(device, vk::ImageViewType::CUBE, format, image)
+            .array_layers(0, 6)
+    }
+
+    pub fn cube_array(device: &'a Device, format: vk::Format, image: vk::Image, cubes: u32) -> Self {
+        Self::with_type(device, vk::ImageViewType::CUBE_ARRAY, format, image)
+            .array_layers(0, 6 * cubes)
+    }
+
+    pub fn new_3d(device: &'a Device, format: vk::Format, image: vk::Image) -> Self {
+        Self::with_type(device, vk::ImageViewType::TYPE_3D, format, image)
+    }
+
+    /// View over every mip level and layer of `image` with a matching view type
+    pub fn from_image(device: &'a Device, image: &Image) -> Self {
+
+        let view_type = match (image.image_type, image.is_cube(), image.array_layers) {
+            (vk::ImageType::TYPE_3D, _, _) => vk::ImageViewType::TYPE_3D,
+            (vk::ImageType::TYPE_1D, _, 1) => vk::ImageViewType::TYPE_1D,
+            (vk::ImageType::TYPE_1D, _, _) => vk::ImageViewType::TYPE_1D_ARRAY,
+            (_, true, 6) => vk::ImageViewType::CUBE,
+            (_, true, _) => vk::ImageViewType::CUBE_ARRAY,
+            (_, false, 1) => vk::ImageViewType::TYPE_2D,
+            (_, false, _) => vk::ImageViewType::TYPE_2D_ARRAY,
+        };
+
+        let mut builder = Self::with_type(device, view_type, image.format, image.raw);
+        builder.create_info.subresource_range = image.full_range();
+        builder
+    }
+
+    /// Single mip level and layer of `image`, e.g. a render target for one cascade or cube face
+    pub fn subresource(device: &'a Device, image: &Image, mip_level: u32, layer: u32) -> Self {
+        let view_type = if image.image_type == vk::ImageType::TYPE_3D {
+            vk::ImageViewType::TYPE_3D
+        } else {
+            vk::ImageViewType::TYPE_2D
+        };
+
+        Self::with_type(device, view_type, image.format, image.raw)
+            .mip_levels(mip_level, 1)
+            .array_layers(layer, 1)
     }
 
     pub fn build(self) -> VulkanResult<ImageView> {
         puffin::profile_scope!("vkImageView");
 
-        let image_view = unsafe { 
+        let image_view = unsafe {
             self.device.create_image_view(&self.create_info, None).map_err(|e| {
-                VulkanError::Unknown(e)
+                VulkanError::Image(ImageError::ImageViewCreationFailed(e))
             })?
         };
 
-        Ok(ImageView { raw: image_view })
+        Ok(ImageView {
+            raw: image_view,
+            deletion: self.device.register(image_view)
+        })
     }
-}
\ No newline at end of file
+}
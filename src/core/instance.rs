@@ -1,13 +1,16 @@
 use ash::vk;
-use log::debug;
+use log::{debug, info, warn};
 use std::ffi::CStr;
-use crate::{App, DebugCallback, VulkanError, VulkanResult};
+use winit::raw_window_handle::RawDisplayHandle;
+use crate::{App, DebugCallback, DebugPolicy, InstanceError, ValidationConfig, VulkanError, VulkanResult};
 
-/// Unsafe Wrapper around [`vk::Instance`]
-/// Required manually destroy before Drop
-/// 
+const VALIDATION_LAYER: &CStr = c"VK_LAYER_KHRONOS_validation";
+
+/// Wrapper around [`vk::Instance`], destroyed on Drop
+/// Every child object must be dropped before
+///
 /// <h1>Example:</h1>
-/// 
+///
 /// ```
 ///
 /// fn main() {
@@ -18,88 +21,270 @@ use crate::{App, DebugCallback, VulkanError, VulkanResult};
 ///             .entry(&app.entry)
 ///     );
 /// }
-/// 
+///
 /// ```
 pub struct Instance {
     pub(crate) raw: ash::Instance,
     pub(crate) layers: Vec<&'static CStr>,
     pub(crate) extensions: Vec<&'static CStr>,
+    pub(crate) api_version: u32,
+    /// Checks the validation layer actually runs, empty without the layer or VK_EXT_validation_features
+    pub(crate) validation: ValidationConfig,
     pub(crate) debug_callback: Option<DebugCallback>
 }
 
-impl Instance {
-    /// Safety if all child object destroyed before 
-    pub fn destroy(&self) {
-        if let Some(debug) = &self.debug_callback {
-            debug.destroy();
-        }
+impl Drop for Instance {
+    fn drop(&mut self) {
+        // Messenger is a child of the instance
+        self.debug_callback.take();
         unsafe { self.raw.destroy_instance(None) };
     }
 }
 
+impl Instance {
+
+    pub fn is_layer_enabled(&self, name: &CStr) -> bool {
+        self.layers.contains(&name)
+    }
+
+    pub fn is_extension_enabled(&self, name: &CStr) -> bool {
+        self.extensions.contains(&name)
+    }
+
+    pub fn validation(&self) -> &ValidationConfig {
+        &self.validation
+    }
+
+    /// `None` when debug utils are not enabled
+    pub fn debug_callback(&self) -> Option<&DebugCallback> {
+        self.debug_callback.as_ref()
+    }
+}
+
 pub struct InstanceBuilder<'a> {
     app: &'a App,
-    enable_debug: bool
+    enable_debug: bool,
+    validation: ValidationConfig,
+    debug_policy: DebugPolicy,
+    display_handle: Option<RawDisplayHandle>,
+    layers: Vec<(&'static CStr, bool)>,
+    extensions: Vec<(&'static CStr, bool)>,
 }
 
 impl<'a> InstanceBuilder<'a> {
 
     pub fn default(app: &'a App) -> Self {
-        Self { app, enable_debug: true }
+        Self {
+            app,
+            enable_debug: true,
+            validation: ValidationConfig::default(),
+            debug_policy: DebugPolicy::default(),
+            display_handle: None,
+            layers: vec![],
+            extensions: vec![]
+        }
     }
 
-    pub fn build(self) -> VulkanResult<Instance> {
+    /// Validation layer and debug messenger, both skipped when not installed
+    pub fn enable_debug(mut self, enable: bool) -> Self {
+        self.enable_debug = enable;
+        self
+    }
+
+    /// Extra validation checks, ignored when debug is disabled
+    pub fn validation(mut self, config: ValidationConfig) -> Self {
+        self.validation = config;
+        self
+    }
+
+    /// Filtering, capture and failure mode of the debug messenger
+    pub fn debug_policy(mut self, policy: DebugPolicy) -> Self {
+        self.debug_policy = policy;
+        self
+    }
+
+    /// Request the surface extensions required to present on this display
+    pub fn display_handle(mut self, handle: RawDisplayHandle) -> Self {
+        self.display_handle = Some(handle);
+        self
+    }
+
+    pub fn layer(mut self, name: &'static CStr) -> Self {
+        self.layers.push((name, true));
+        self
+    }
+
+    pub fn optional_layer(mut self, name: &'static CStr) -> Self {
+        self.layers.push((name, false));
+        self
+    }
+
+    pub fn extension(mut self, name: &'static CStr) -> Self {
+        self.extensions.push((name, true));
+        self
+    }
+
+    pub fn optional_extension(mut self, name: &'static CStr) -> Self {
+        self.extensions.push((name, false));
+        self
+    }
+
+    pub fn build(mut self) -> VulkanResult<Instance> {
+
+        let entry = &self.app.entry;
+
+        if let Some(handle) = self.display_handle {
 
-        let extensions = unsafe {
-            self.app.entry
+            let required = ash_window::enumerate_required_extensions(handle)
+                .map_err(|e| VulkanError::Instance(InstanceError::EnumerateInstanceExtensionsFailed(e)))?;
+
+            for name in required {
+                // Extension names returned by ash-window are static strings
+                let name: &'static CStr = unsafe { CStr::from_ptr(*name) };
+                self.extensions.push((name, true));
+            }
+
+            // Lets the surface report the HDR color spaces of `SwapchainConfig::hdr10` and `scrgb`
+            self.extensions.push((ash::ext::swapchain_colorspace::NAME, false));
+        }
+
+        if self.enable_debug {
+            self.layers.push((VALIDATION_LAYER, false));
+            self.extensions.push((ash::ext::debug_utils::NAME, false));
+
+            if !self.validation.is_empty() {
+                self.extensions.push((ash::ext::validation_features::NAME, false));
+            }
+        }
+
+        self.extensions.push((ash::khr::portability_enumeration::NAME, false));
+
+        let available_layers = unsafe {
+            entry
+                .enumerate_instance_layer_properties()
+                .map_err(|e| VulkanError::Instance(InstanceError::EnumerateInstanceLayerPropertiesFailed(e)))
+        }?;
+
+        let mut layers: Vec<&'static CStr> = vec![];
+
+        for (name, required) in &self.layers {
+
+            if layers.contains(name) {
+                continue;
+            }
+
+            let available = available_layers
+                .iter()
+                .any(|layer| layer.layer_name_as_c_str() == Ok(*name));
+
+            match (available, required) {
+                (true, _) => layers.push(*name),
+                (false, true) => return Err(VulkanError::Instance(InstanceError::NotSupportRequiredLayer(name))),
+                (false, false) => warn!("Optional instance layer {:?} is not available, skipping", name),
+            }
+        }
+
+        let mut available_extensions = unsafe {
+            entry
                 .enumerate_instance_extension_properties(None)
-                .map_err(|e| {
-                    VulkanError::Unknown(e)
-                })
+                .map_err(|e| VulkanError::Instance(InstanceError::EnumerateInstanceExtensionsFailed(e)))
         }?;
 
-        let layers = [
-            c"VK_LAYER_KHRONOS_validation"
-        ];
-
-        let extenions = [
-            c"VK_KHR_win32_surface",
-            c"VK_EXT_debug_utils",
-            c"VK_KHR_surface",
-        ];
-        
-        let p_extenions = extenions
+        // Layers can provide extensions too, e.g. validation provides debug utils
+        for layer in &layers {
+            let provided = unsafe {
+                entry
+                    .enumerate_instance_extension_properties(Some(layer))
+                    .map_err(|e| VulkanError::Instance(InstanceError::EnumerateInstanceExtensionsFailed(e)))
+            }?;
+            available_extensions.extend(provided);
+        }
+
+        let mut extensions: Vec<&'static CStr> = vec![];
+
+        for (name, required) in &self.extensions {
+
+            if extensions.contains(name) {
+                continue;
+            }
+
+            let available = available_extensions
+                .iter()
+                .any(|ext| ext.extension_name_as_c_str() == Ok(*name));
+
+            match (available, required) {
+                (true, _) => extensions.push(*name),
+                (false, true) => return Err(VulkanError::Instance(InstanceError::NotSupportRequiredExtension(name))),
+                (false, false) => warn!("Optional instance extension {:?} is not available, skipping", name),
+            }
+        }
+
+        let p_extensions = extensions
             .iter()
             .map(|name| (*name).as_ptr())
             .collect::<Vec<_>>();
 
-        let p_layers = layers.iter()
+        let p_layers = layers
+            .iter()
             .map(|name| (*name).as_ptr())
             .collect::<Vec<_>>();
 
-        let create_info = vk::InstanceCreateInfo::default()
+        let flags = if extensions.contains(&ash::khr::portability_enumeration::NAME) {
+            vk::InstanceCreateFlags::ENUMERATE_PORTABILITY_KHR
+        } else {
+            vk::InstanceCreateFlags::empty()
+        };
+
+        // The extension is provided by the validation layer, so both are enabled when it is
+        let validation = match self.enable_debug && extensions.contains(&ash::ext::validation_features::NAME) {
+            true => self.validation,
+            false => ValidationConfig::default(),
+        };
+
+        if self.enable_debug && validation != self.validation {
+            warn!("VK_EXT_validation_features is not available, skipping {:?}", self.validation);
+        }
+
+        let enabled_features = validation.features();
+        let mut validation_features = vk::ValidationFeaturesEXT::default()
+            .enabled_validation_features(&enabled_features);
+
+        let mut create_info = vk::InstanceCreateInfo::default()
+            .flags(flags)
             .enabled_layer_names(&p_layers)
-            .enabled_extension_names(&p_extenions)
+            .enabled_extension_names(&p_extensions)
             .application_info(&self.app.create_info);
 
+        if !validation.is_empty() {
+            info!("Validation features: {:?}", enabled_features);
+            create_info = create_info.push_next(&mut validation_features);
+        }
+
         debug!("Instance: {:?}", create_info);
 
-        let instance = unsafe { self.app.entry.create_instance(&create_info, None).map_err(|e| {
-            VulkanError::Unknown(e)
+        let instance = unsafe { entry.create_instance(&create_info, None).map_err(|e| {
+            VulkanError::Instance(InstanceError::InstanceCreationFailed(e))
         })}?;
 
-        let debug = if self.enable_debug {
-            Some(DebugCallback::new(&self.app.entry, &instance))
+        let debug = if extensions.contains(&ash::ext::debug_utils::NAME) {
+            match DebugCallback::new(entry, &instance, self.debug_policy) {
+                Ok(callback) => Some(callback),
+                Err(e) => {
+                    unsafe { instance.destroy_instance(None) };
+                    return Err(e);
+                }
+            }
         } else {
             None
         };
 
-        Ok(Instance { 
-            raw: instance, 
-            layers: vec![], 
-            extensions: vec![], 
-            debug_callback: debug 
+        Ok(Instance {
+            raw: instance,
+            layers,
+            extensions,
+            api_version: self.app.api_version,
+            validation,
+            debug_callback: debug
         })
     }
 }
-
@@ -0,0 +1,289 @@
+use std::{collections::HashMap, sync::Mutex};
+
+use ash::vk::{self, Handle};
+use serde::{Serialize, Serializer};
+
+use crate::Device;
+
+/// What an allocation is used for, inferred from the usage flags unless the builder sets it
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MemoryCategory {
+    RenderTarget,
+    Texture,
+    Mesh,
+    Staging,
+    Uniform,
+    Other,
+}
+
+impl MemoryCategory {
+
+    pub const ALL: [MemoryCategory; 6] = [
+        MemoryCategory::RenderTarget,
+        MemoryCategory::Texture,
+        MemoryCategory::Mesh,
+        MemoryCategory::Staging,
+        MemoryCategory::Uniform,
+        MemoryCategory::Other,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            MemoryCategory::RenderTarget => "render_target",
+            MemoryCategory::Texture => "texture",
+            MemoryCategory::Mesh => "mesh",
+            MemoryCategory::Staging => "staging",
+            MemoryCategory::Uniform => "uniform",
+            MemoryCategory::Other => "other",
+        }
+    }
+
+    pub fn from_image_usage(usage: vk::ImageUsageFlags) -> Self {
+        if usage.intersects(vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT) {
+            MemoryCategory::RenderTarget
+        } else {
+            MemoryCategory::Texture
+        }
+    }
+
+    pub fn from_buffer_usage(usage: vk::BufferUsageFlags, host_visible: bool) -> Self {
+        if usage.intersects(vk::BufferUsageFlags::VERTEX_BUFFER | vk::BufferUsageFlags::INDEX_BUFFER) {
+            MemoryCategory::Mesh
+        } else if host_visible && usage.contains(vk::BufferUsageFlags::TRANSFER_SRC) {
+            MemoryCategory::Staging
+        } else if usage.intersects(vk::BufferUsageFlags::UNIFORM_BUFFER | vk::BufferUsageFlags::STORAGE_BUFFER) {
+            MemoryCategory::Uniform
+        } else {
+            MemoryCategory::Other
+        }
+    }
+}
+
+struct TrackedAllocation {
+    ty: vk::ObjectType,
+    category: MemoryCategory,
+    size: vk::DeviceSize,
+    name: Option<String>,
+}
+
+/// Images and buffers allocated through `vk_mem`, keyed by their handle
+#[derive(Default)]
+pub struct MemoryTracker {
+    allocations: Mutex<HashMap<(vk::ObjectType, u64), TrackedAllocation>>,
+}
+
+impl MemoryTracker {
+
+    pub(crate) fn track<H: Handle>(&self, handle: H, category: MemoryCategory, size: vk::DeviceSize) {
+        self.allocations.lock().unwrap().insert((H::TYPE, handle.as_raw()), TrackedAllocation {
+            ty: H::TYPE,
+            category,
+            size,
+            name: None,
+        });
+    }
+
+    pub(crate) fn untrack(&self, ty: vk::ObjectType, raw: u64) {
+        self.allocations.lock().unwrap().remove(&(ty, raw));
+    }
+
+    pub(crate) fn set_name(&self, ty: vk::ObjectType, raw: u64, name: &str) {
+        if let Some(allocation) = self.allocations.lock().unwrap().get_mut(&(ty, raw)) {
+            allocation.name = Some(name.to_string());
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Serialize)]
+pub struct HeapStats {
+    pub index: u32,
+    pub device_local: bool,
+    pub size: vk::DeviceSize,
+    /// Memory the process may use, from VK_EXT_memory_budget or estimated from the heap size
+    pub budget: vk::DeviceSize,
+    /// Memory the process uses, including objects allocated outside of `vk_mem` with the extension
+    pub usage: vk::DeviceSize,
+    /// `VkDeviceMemory` blocks and their bytes
+    pub blocks: u32,
+    pub block_bytes: vk::DeviceSize,
+    /// Allocations placed in the blocks and their bytes
+    pub allocations: u32,
+    pub allocation_bytes: vk::DeviceSize,
+}
+
+#[derive(Clone, Copy, Debug, Default, Serialize)]
+pub struct CategoryStats {
+    pub count: u32,
+    pub bytes: vk::DeviceSize,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct MemoryStats {
+    /// Budget and usage come from VK_EXT_memory_budget
+    pub budget_ext: bool,
+    pub heaps: Vec<HeapStats>,
+    #[serde(serialize_with = "serialize_categories")]
+    pub categories: Vec<(MemoryCategory, CategoryStats)>,
+}
+
+impl MemoryStats {
+
+    pub fn category(&self, category: MemoryCategory) -> CategoryStats {
+        self.categories.iter()
+            .find(|(c, _)| *c == category)
+            .map(|(_, stats)| *stats)
+            .unwrap_or_default()
+    }
+
+    pub fn total_usage(&self) -> vk::DeviceSize {
+        self.heaps.iter().map(|h| h.usage).sum()
+    }
+
+    /// Heaps whose usage is above their budget
+    pub fn over_budget(&self) -> impl Iterator<Item = &HeapStats> {
+        self.heaps.iter().filter(|h| h.usage > h.budget)
+    }
+}
+
+impl Device {
+
+    pub fn memory_tracker(&self) -> &MemoryTracker {
+        &self.memory
+    }
+
+    pub fn memory_stats(&self) -> MemoryStats {
+
+        let budgets = self.allocator.get_heap_budgets().unwrap_or_default();
+
+        let heaps = self.memory_heaps.iter()
+            .enumerate()
+            .map(|(index, heap)| {
+                let budget = budgets.get(index);
+
+                HeapStats {
+                    index: index as u32,
+                    device_local: heap.flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL),
+                    size: heap.size,
+                    budget: budget.map(|b| b.budget).unwrap_or(heap.size),
+                    usage: budget.map(|b| b.usage).unwrap_or(0),
+                    blocks: budget.map(|b| b.statistics.blockCount).unwrap_or(0),
+                    block_bytes: budget.map(|b| b.statistics.blockBytes).unwrap_or(0),
+                    allocations: budget.map(|b| b.statistics.allocationCount).unwrap_or(0),
+                    allocation_bytes: budget.map(|b| b.statistics.allocationBytes).unwrap_or(0),
+                }
+            })
+            .collect();
+
+        let allocations = self.memory.allocations.lock().unwrap();
+
+        let categories = MemoryCategory::ALL.iter()
+            .map(|&category| {
+                let stats = allocations.values()
+                    .filter(|a| a.category == category)
+                    .fold(CategoryStats::default(), |stats, a| CategoryStats {
+                        count: stats.count + 1,
+                        bytes: stats.bytes + a.size,
+                    });
+
+                (category, stats)
+            })
+            .collect();
+
+        MemoryStats {
+            budget_ext: self.memory_budget_ext,
+            heaps,
+            categories,
+        }
+    }
+
+    /// Heaps, category totals and every live allocation with its name as JSON
+    pub fn memory_json(&self) -> String {
+        let stats = self.memory_stats();
+        let allocations = self.memory.allocations.lock().unwrap();
+
+        dump_json(&stats, &allocations)
+    }
+}
+
+#[derive(Serialize)]
+struct MemoryDump<'a> {
+    #[serde(flatten)]
+    stats: &'a MemoryStats,
+    allocations: Vec<AllocationDump<'a>>,
+}
+
+#[derive(Serialize)]
+struct AllocationDump<'a> {
+    handle: String,
+    kind: &'static str,
+    category: MemoryCategory,
+    size: vk::DeviceSize,
+    name: Option<&'a str>,
+}
+
+fn dump_json(stats: &MemoryStats, allocations: &HashMap<(vk::ObjectType, u64), TrackedAllocation>) -> String {
+
+    let mut sorted = allocations.iter().collect::<Vec<_>>();
+
+    // Biggest first, stable between dumps so they can be diffed
+    sorted.sort_by_key(|((_, raw), a)| (std::cmp::Reverse(a.size), *raw));
+
+    let allocations = sorted.into_iter()
+        .map(|((_, raw), allocation)| AllocationDump {
+            handle: format!("0x{:x}", raw),
+            kind: if allocation.ty == vk::ObjectType::IMAGE { "image" } else { "buffer" },
+            category: allocation.category,
+            size: allocation.size,
+            name: allocation.name.as_deref(),
+        })
+        .collect();
+
+    serde_json::to_string(&MemoryDump { stats, allocations }).expect("Error serialize memory stats")
+}
+
+/// Categories as an object keyed by their name
+fn serialize_categories<S: Serializer>(categories: &[(MemoryCategory, CategoryStats)], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_map(categories.iter().map(|(category, stats)| (category.name(), stats)))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::{Value, json};
+    use super::*;
+
+    fn allocation(ty: vk::ObjectType, category: MemoryCategory, size: vk::DeviceSize, name: Option<&str>) -> TrackedAllocation {
+        TrackedAllocation { ty, category, size, name: name.map(str::to_string) }
+    }
+
+    #[test]
+    fn dump_parses() {
+        let stats = MemoryStats {
+            budget_ext: true,
+            heaps: vec![HeapStats { index: 0, device_local: true, size: 1024, budget: 512, usage: 256, ..Default::default() }],
+            categories: vec![
+                (MemoryCategory::RenderTarget, CategoryStats { count: 1, bytes: 64 }),
+                (MemoryCategory::Uniform, CategoryStats { count: 1, bytes: 128 }),
+            ],
+        };
+
+        let allocations = HashMap::from([
+            ((vk::ObjectType::IMAGE, 0x10), allocation(vk::ObjectType::IMAGE, MemoryCategory::RenderTarget, 64, Some("Grid \"offscreen\" target"))),
+            ((vk::ObjectType::BUFFER, 0x20), allocation(vk::ObjectType::BUFFER, MemoryCategory::Uniform, 128, None)),
+        ]);
+
+        let json: Value = serde_json::from_str(&dump_json(&stats, &allocations)).unwrap();
+
+        assert_eq!(json["budget_ext"], json!(true));
+        assert_eq!(json["heaps"][0]["budget"], json!(512));
+        assert_eq!(json["heaps"][0]["device_local"], json!(true));
+        assert_eq!(json["categories"]["render_target"], json!({ "count": 1, "bytes": 64 }));
+        assert_eq!(json["categories"]["uniform"]["bytes"], json!(128));
+
+        // Biggest first
+        assert_eq!(json["allocations"], json!([
+            { "handle": "0x20", "kind": "buffer", "category": "uniform", "size": 128, "name": null },
+            { "handle": "0x10", "kind": "image", "category": "render_target", "size": 64, "name": "Grid \"offscreen\" target" },
+        ]));
+    }
+}
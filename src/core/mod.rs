@@ -14,6 +14,15 @@ pub use surface::*;
 mod device;
 pub use device::*;
 
+mod deletion_queue;
+pub use deletion_queue::*;
+
+mod object_tracker;
+pub use object_tracker::*;
+
+mod memory_stats;
+pub use memory_stats::*;
+
 mod swapchain;
 pub use swapchain::*;
 
@@ -32,6 +41,9 @@ pub use phys_dev::*;
 mod shader;
 pub use shader::*;
 
+mod reflection;
+pub use reflection::*;
+
 mod pipeline_layout;
 pub use pipeline_layout::*;
 
@@ -44,6 +56,9 @@ pub use subpass::*;
 mod render_pass;
 pub use render_pass::*;
 
+mod rendering;
+pub use rendering::*;
+
 mod image_view;
 pub use image_view::*;
 
@@ -53,6 +68,12 @@ pub use errors::*;
 mod graphics_pipeline;
 pub use graphics_pipeline::*;
 
+mod pipeline_cache;
+pub use pipeline_cache::*;
+
+mod pipeline_desc;
+pub use pipeline_desc::*;
+
 mod semaphore;
 pub use semaphore::*;
 
@@ -62,11 +83,23 @@ pub use descriptor_set_layout::*;
 mod fence;
 pub use fence::*;
 
+mod sync;
+pub use sync::*;
+
 mod sampler;
 pub use sampler::*;
 
 mod descriptor_pool;
 pub use descriptor_pool::*;
 
+mod descriptor_writer;
+pub use descriptor_writer::*;
+
+mod query_pool;
+pub use query_pool::*;
+
+mod command_recorder;
+pub use command_recorder::*;
+
 mod types;
 pub use types::*;
\ No newline at end of file
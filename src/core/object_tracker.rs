@@ -0,0 +1,150 @@
+#[cfg(feature = "runtime-check")]
+use std::{backtrace::Backtrace, collections::HashMap, sync::Mutex};
+
+use ash::vk;
+
+#[cfg(feature = "runtime-check")]
+struct TrackedObject {
+    name: Option<String>,
+    backtrace: Backtrace,
+}
+
+/// Registry of live Vulkan objects, a no-op without the `runtime-check` feature.
+/// Creation backtraces are captured when `RUST_BACKTRACE` is set
+#[derive(Default)]
+pub struct ObjectTracker {
+    #[cfg(feature = "runtime-check")]
+    objects: Mutex<HashMap<(vk::ObjectType, u64), TrackedObject>>,
+}
+
+#[cfg(feature = "runtime-check")]
+impl ObjectTracker {
+
+    pub(crate) fn track(&self, ty: vk::ObjectType, raw: u64) {
+        self.objects.lock().unwrap().insert((ty, raw), TrackedObject {
+            name: None,
+            backtrace: Backtrace::capture()
+        });
+    }
+
+    pub(crate) fn untrack(&self, ty: vk::ObjectType, raw: u64) {
+        self.objects.lock().unwrap().remove(&(ty, raw));
+    }
+
+    pub(crate) fn set_name(&self, ty: vk::ObjectType, raw: u64, name: &str) {
+        if let Some(object) = self.objects.lock().unwrap().get_mut(&(ty, raw)) {
+            object.name = Some(name.to_string());
+        }
+    }
+
+    /// Number of tracked objects that were not destroyed yet
+    pub fn live_objects(&self) -> usize {
+        self.objects.lock().unwrap().len()
+    }
+
+    /// Log every object still alive, returns how many there are
+    pub(crate) fn report(&self) -> usize {
+        let objects = self.objects.lock().unwrap();
+
+        for ((ty, raw), object) in objects.iter() {
+            log::error!(
+                "Leaked {:?} 0x{:x} ({}), created at:\n{}",
+                ty,
+                raw,
+                object.name.as_deref().unwrap_or("unnamed"),
+                object.backtrace
+            );
+        }
+
+        objects.len()
+    }
+
+    /// Panic listing every object still alive
+    pub fn assert_no_leaks(&self) {
+        let leaks = self.objects.lock().unwrap().iter()
+            .map(|((ty, raw), object)| format!("{:?} 0x{:x} ({})", ty, raw, object.name.as_deref().unwrap_or("unnamed")))
+            .collect::<Vec<_>>();
+
+        assert!(leaks.is_empty(), "{} Vulkan objects leaked: {}", leaks.len(), leaks.join(", "));
+    }
+}
+
+#[cfg(not(feature = "runtime-check"))]
+impl ObjectTracker {
+
+    pub(crate) fn track(&self, _ty: vk::ObjectType, _raw: u64) {}
+
+    pub(crate) fn untrack(&self, _ty: vk::ObjectType, _raw: u64) {}
+
+    pub(crate) fn set_name(&self, _ty: vk::ObjectType, _raw: u64, _name: &str) {}
+
+    pub fn live_objects(&self) -> usize {
+        0
+    }
+
+    pub(crate) fn report(&self) -> usize {
+        0
+    }
+
+    pub fn assert_no_leaks(&self) {}
+}
+
+#[cfg(all(test, feature = "runtime-check"))]
+mod tests {
+    use ash::vk;
+
+    use super::ObjectTracker;
+
+    #[test]
+    fn untracked_objects_are_not_reported() {
+        let tracker = ObjectTracker::default();
+
+        tracker.track(vk::ObjectType::BUFFER, 1);
+        tracker.track(vk::ObjectType::IMAGE, 1);
+        tracker.set_name(vk::ObjectType::BUFFER, 1, "Vertices");
+        assert_eq!(tracker.live_objects(), 2);
+
+        tracker.untrack(vk::ObjectType::BUFFER, 1);
+        assert_eq!(tracker.report(), 1);
+
+        tracker.untrack(vk::ObjectType::IMAGE, 1);
+        assert_eq!(tracker.report(), 0);
+    }
+
+    #[test]
+    fn names_are_kept_until_untracked() {
+        let tracker = ObjectTracker::default();
+
+        tracker.track(vk::ObjectType::SAMPLER, 7);
+        tracker.set_name(vk::ObjectType::SAMPLER, 7, "Linear");
+        // Naming an object that is not tracked is ignored
+        tracker.set_name(vk::ObjectType::SAMPLER, 8, "Nearest");
+
+        let objects = tracker.objects.lock().unwrap();
+        assert_eq!(objects.len(), 1);
+        assert_eq!(objects[&(vk::ObjectType::SAMPLER, 7)].name.as_deref(), Some("Linear"));
+    }
+
+    #[test]
+    #[should_panic(expected = "1 Vulkan objects leaked: SAMPLER 0x7 (Linear)")]
+    fn leaks_fail_the_assert() {
+        let tracker = ObjectTracker::default();
+
+        tracker.track(vk::ObjectType::SAMPLER, 7);
+        tracker.set_name(vk::ObjectType::SAMPLER, 7, "Linear");
+        tracker.track(vk::ObjectType::BUFFER, 1);
+        tracker.untrack(vk::ObjectType::BUFFER, 1);
+
+        tracker.assert_no_leaks();
+    }
+
+    #[test]
+    fn no_leaks_pass_the_assert() {
+        let tracker = ObjectTracker::default();
+
+        tracker.track(vk::ObjectType::BUFFER, 1);
+        tracker.untrack(vk::ObjectType::BUFFER, 1);
+
+        tracker.assert_no_leaks();
+    }
+}
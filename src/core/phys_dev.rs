@@ -1,7 +1,353 @@
+use std::ffi::CStr;
+
+use ash::vk;
+use log::{debug, info, warn};
+
+use crate::{Instance, PhysicalDeviceError, Surface, VulkanError, VulkanResult};
+
+/// Environment variable overriding device selection, either an index or a part of the device name
+pub const DEVICE_SELECTOR_ENV: &str = "BANANA_DEVICE";
+
+pub struct QueueFamily {
+    pub index: u32,
+    pub properties: vk::QueueFamilyProperties,
+    pub present_support: bool,
+}
+
+/// Everything the engine needs to know about a [`vk::PhysicalDevice`]
 pub struct PhysicalDevice {
+    pub(crate) raw: vk::PhysicalDevice,
+    pub(crate) index: usize,
+    pub(crate) name: String,
+    pub(crate) properties: vk::PhysicalDeviceProperties,
+    pub(crate) features: vk::PhysicalDeviceFeatures,
+    pub(crate) memory_properties: vk::PhysicalDeviceMemoryProperties,
+    pub(crate) queue_families: Vec<QueueFamily>,
+    pub(crate) extensions: Vec<String>,
+}
+
+impl PhysicalDevice {
+
+    /// Query every physical device, present support is only filled when a surface is given
+    pub fn enumerate(instance: &Instance, surface: Option<&Surface>) -> VulkanResult<Vec<PhysicalDevice>> {
+
+        let devices = unsafe {
+            instance.raw
+                .enumerate_physical_devices()
+                .map_err(|e| VulkanError::PhysicalDevice(PhysicalDeviceError::EnumeratePhysicalDeviceFailed(e)))
+        }?;
+
+        let mut result = Vec::with_capacity(devices.len());
+
+        for (index, raw) in devices.into_iter().enumerate() {
+
+            let properties = unsafe { instance.raw.get_physical_device_properties(raw) };
+            let features = unsafe { instance.raw.get_physical_device_features(raw) };
+            let memory_properties = unsafe { instance.raw.get_physical_device_memory_properties(raw) };
+            let families = unsafe { instance.raw.get_physical_device_queue_family_properties(raw) };
+
+            let extensions = unsafe {
+                instance.raw
+                    .enumerate_device_extension_properties(raw)
+                    .map_err(|e| VulkanError::PhysicalDevice(PhysicalDeviceError::EnumerateDeviceExtensionPropertiesFailed(e)))
+            }?
+            .iter()
+            .filter_map(|ext| ext.extension_name_as_c_str().ok())
+            .map(|name| name.to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+
+            let mut queue_families = Vec::with_capacity(families.len());
+            for (family, properties) in families.into_iter().enumerate() {
+                let present_support = match surface {
+                    Some(surface) => surface.get_physical_device_surface_support(&raw, family as u32)?,
+                    None => false,
+                };
+
+                queue_families.push(QueueFamily { index: family as u32, properties, present_support });
+            }
+
+            let name = properties
+                .device_name_as_c_str()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+
+            result.push(PhysicalDevice {
+                raw,
+                index,
+                name,
+                properties,
+                features,
+                memory_properties,
+                queue_families,
+                extensions
+            });
+        }
+
+        Ok(result)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    pub fn properties(&self) -> &vk::PhysicalDeviceProperties {
+        &self.properties
+    }
+
+    pub fn limits(&self) -> &vk::PhysicalDeviceLimits {
+        &self.properties.limits
+    }
+
+    pub fn features(&self) -> &vk::PhysicalDeviceFeatures {
+        &self.features
+    }
+
+    pub fn memory_properties(&self) -> &vk::PhysicalDeviceMemoryProperties {
+        &self.memory_properties
+    }
+
+    pub fn queue_families(&self) -> &[QueueFamily] {
+        &self.queue_families
+    }
+
+    pub fn extensions(&self) -> &[String] {
+        &self.extensions
+    }
+
+    pub fn supports_extension(&self, name: &CStr) -> bool {
+        let name = name.to_string_lossy();
+        self.extensions.iter().any(|ext| *ext == name)
+    }
+
+    /// First queue family supporting graphics and presentation
+    pub fn graphics_present_family(&self) -> Option<u32> {
+        self.queue_families
+            .iter()
+            .find(|family| family.present_support && family.properties.queue_flags.contains(vk::QueueFlags::GRAPHICS))
+            .map(|family| family.index)
+    }
+
+    /// Total size of device local heaps in bytes
+    pub fn device_local_memory(&self) -> u64 {
+        self.memory_properties
+            .memory_heaps_as_slice()
+            .iter()
+            .filter(|heap| heap.flags.contains(vk::MemoryHeapFlags::DEVICE_LOCAL))
+            .map(|heap| heap.size)
+            .sum()
+    }
+
+    /// Discrete over integrated over virtual over CPU, ties broken by device local memory
+    pub fn score(&self) -> (u32, u64) {
+        let ty = match self.properties.device_type {
+            vk::PhysicalDeviceType::DISCRETE_GPU => 4,
+            vk::PhysicalDeviceType::INTEGRATED_GPU => 3,
+            vk::PhysicalDeviceType::VIRTUAL_GPU => 2,
+            vk::PhysicalDeviceType::CPU => 1,
+            _ => 0,
+        };
+
+        (ty, self.device_local_memory())
+    }
+}
+
+/// Pick a specific device instead of the best ranked one
+#[derive(Clone, Debug)]
+pub enum DeviceSelector {
+    Index(usize),
+    /// Case insensitive part of the device name
+    Name(String),
+}
+
+impl DeviceSelector {
+
+    pub fn from_env() -> Option<DeviceSelector> {
+        let value = std::env::var(DEVICE_SELECTOR_ENV).ok()?;
+        let value = value.trim();
+
+        if value.is_empty() {
+            return None;
+        }
+
+        match value.parse::<usize>() {
+            Ok(index) => Some(DeviceSelector::Index(index)),
+            Err(_) => Some(DeviceSelector::Name(value.to_string())),
+        }
+    }
 
+    fn matches(&self, device: &PhysicalDevice) -> bool {
+        match self {
+            DeviceSelector::Index(index) => device.index == *index,
+            DeviceSelector::Name(name) => device.name.to_lowercase().contains(&name.to_lowercase()),
+        }
+    }
 }
 
 pub struct PhysicalDeviceDesc {
-    
+    pub required_extensions: Vec<&'static CStr>,
+    pub required_features: vk::PhysicalDeviceFeatures,
+    pub require_present: bool,
+    pub selector: Option<DeviceSelector>,
+}
+
+impl Default for PhysicalDeviceDesc {
+    fn default() -> Self {
+        Self {
+            required_extensions: vec![ash::khr::swapchain::NAME],
+            required_features: vk::PhysicalDeviceFeatures::default(),
+            require_present: true,
+            selector: None
+        }
+    }
+}
+
+impl PhysicalDeviceDesc {
+
+    /// Reason why `device` can't be used, `None` if it is suitable
+    fn reject_reason(&self, device: &PhysicalDevice) -> Option<String> {
+
+        for ext in &self.required_extensions {
+            if !device.supports_extension(ext) {
+                return Some(format!("missing extension {:?}", ext));
+            }
+        }
+
+        if !features_supported(&self.required_features, &device.features) {
+            return Some("missing required features".to_string());
+        }
+
+        let has_graphics = device.queue_families
+            .iter()
+            .any(|family| family.properties.queue_flags.contains(vk::QueueFlags::GRAPHICS));
+
+        if !has_graphics {
+            return Some("no graphics queue".to_string());
+        }
+
+        if self.require_present && device.graphics_present_family().is_none() {
+            return Some("no queue with present support".to_string());
+        }
+
+        None
+    }
+}
+
+/// Every feature enabled in `required` is also enabled in `supported`
+fn features_supported(required: &vk::PhysicalDeviceFeatures, supported: &vk::PhysicalDeviceFeatures) -> bool {
+
+    const COUNT: usize = size_of::<vk::PhysicalDeviceFeatures>() / size_of::<vk::Bool32>();
+
+    // PhysicalDeviceFeatures is a plain list of Bool32
+    let required = unsafe { std::slice::from_raw_parts(required as *const _ as *const vk::Bool32, COUNT) };
+    let supported = unsafe { std::slice::from_raw_parts(supported as *const _ as *const vk::Bool32, COUNT) };
+
+    required
+        .iter()
+        .zip(supported)
+        .all(|(required, supported)| *required == vk::FALSE || *supported == vk::TRUE)
+}
+
+pub struct PhysicalDeviceBuilder<'a> {
+    instance: &'a Instance,
+    surface: Option<&'a Surface>,
+    desc: PhysicalDeviceDesc,
+}
+
+impl<'a> PhysicalDeviceBuilder<'a> {
+
+    pub fn default(instance: &'a Instance) -> Self {
+        Self {
+            instance,
+            surface: None,
+            desc: PhysicalDeviceDesc::default()
+        }
+    }
+
+    pub fn surface(mut self, surface: &'a Surface) -> Self {
+        self.surface = Some(surface);
+        self
+    }
+
+    pub fn desc(mut self, desc: PhysicalDeviceDesc) -> Self {
+        self.desc = desc;
+        self
+    }
+
+    pub fn required_extension(mut self, name: &'static CStr) -> Self {
+        self.desc.required_extensions.push(name);
+        self
+    }
+
+    pub fn required_features(mut self, features: vk::PhysicalDeviceFeatures) -> Self {
+        self.desc.required_features = features;
+        self
+    }
+
+    /// Select a device by name or index, overridden by [`DEVICE_SELECTOR_ENV`]
+    pub fn select(mut self, selector: Option<DeviceSelector>) -> Self {
+        self.desc.selector = selector;
+        self
+    }
+
+    pub fn build(self) -> VulkanResult<PhysicalDevice> {
+
+        let mut desc = self.desc;
+        desc.require_present &= self.surface.is_some();
+
+        let devices = PhysicalDevice::enumerate(self.instance, self.surface)?;
+
+        if devices.is_empty() {
+            return Err(VulkanError::PhysicalDevice(PhysicalDeviceError::NoDevices));
+        }
+
+        for device in &devices {
+            debug!(
+                "Physical device {}: {} ({:?}, api {}.{}.{}, {} MiB device local)",
+                device.index,
+                device.name,
+                device.properties.device_type,
+                vk::api_version_major(device.properties.api_version),
+                vk::api_version_minor(device.properties.api_version),
+                vk::api_version_patch(device.properties.api_version),
+                device.device_local_memory() / (1024 * 1024)
+            );
+        }
+
+        let selector = DeviceSelector::from_env().or(desc.selector.clone());
+
+        if let Some(selector) = selector {
+
+            let device = devices
+                .into_iter()
+                .find(|device| selector.matches(device))
+                .ok_or_else(|| VulkanError::PhysicalDevice(PhysicalDeviceError::RequestedDeviceNotFound(format!("{:?}", selector))))?;
+
+            if let Some(reason) = desc.reject_reason(&device) {
+                return Err(VulkanError::PhysicalDevice(PhysicalDeviceError::RequestedDeviceUnsuitable(device.name, reason)));
+            }
+
+            info!("Selected physical device: {} (requested by {:?})", device.name, selector);
+            return Ok(device);
+        }
+
+        let device = devices
+            .into_iter()
+            .filter(|device| match desc.reject_reason(device) {
+                Some(reason) => {
+                    warn!("Skip physical device {}: {}", device.name, reason);
+                    false
+                }
+                None => true,
+            })
+            .max_by_key(|device| device.score())
+            .ok_or(VulkanError::PhysicalDevice(PhysicalDeviceError::NoSuitableDevice))?;
+
+        info!("Selected physical device: {}", device.name);
+
+        Ok(device)
+    }
 }
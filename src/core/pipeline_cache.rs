@@ -1,59 +1,163 @@
-
+use std::{fs, path::{Path, PathBuf}};
 
 use ash::vk;
-use std::fs::File;
-use std::io::{Read, Write};
-use std::path::Path;
+use log::{info, warn};
+
+use crate::core::{Deferred, DeletionQueue};
+use crate::{Device, PhysicalDevice, PipelineError, VulkanError, VulkanResult};
 
-use crate::Device;
+/// Size of `VK_PIPELINE_CACHE_HEADER_VERSION_ONE`
+const HEADER_SIZE: usize = 16 + 4 * 4;
+
+/// Environment variable overriding the cache directory
+pub const CACHE_DIR_ENV: &str = "BANANA_CACHE_DIR";
 
 pub struct PipelineCache {
     pub raw: vk::PipelineCache,
+    path: Option<PathBuf>,
+    deletion: DeletionQueue,
+}
+
+impl Drop for PipelineCache {
+    fn drop(&mut self) {
+        self.deletion.push(Deferred::PipelineCache(self.raw));
+    }
 }
 
 impl PipelineCache {
-    
-    pub fn new(device: &Device) -> Result<Self, vk::Result> {
-        let cache_info = vk::PipelineCacheCreateInfo::default()
-            .flags(vk::PipelineCacheCreateFlags::empty())
-            .initial_data(&[]);
-        
-        let cache = unsafe { device.create_pipeline_cache(&cache_info, None)? };
-        
-        Ok(Self { raw: cache })
-    }
-    
-    pub fn from_file(device: &Device, path: &Path) -> Result<Self, vk::Result> {
-
-        let initial_data = if path.exists() {
-            let mut file = File::open(path).unwrap();
-            let mut data = Vec::new();
-            file.read_to_end(&mut data).unwrap();
-            data
-        } else {
-            return Err(vk::Result::from_raw(0));
-        };
-        
-        let cache_info = vk::PipelineCacheCreateInfo::default()
-            .flags(vk::PipelineCacheCreateFlags::empty())
-            .initial_data(&initial_data);
-        
-        let cache = unsafe { device.create_pipeline_cache(&cache_info, None)? };
-        
-        Ok(Self { 
-            raw: cache 
-        })
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
     }
-    
-    pub fn save_to_file(&self, device: &Device, path: &Path) -> Result<(), vk::Result> {
 
-        let data = unsafe {
-            device.get_pipeline_cache_data(self.raw)?
+    /// Merge pipelines compiled into other caches, e.g. by worker threads
+    pub fn merge(&self, device: &Device, caches: &[&PipelineCache]) -> VulkanResult<()> {
+        let sources = caches.iter().map(|c| c.raw).collect::<Vec<_>>();
+
+        unsafe {
+            device.merge_pipeline_caches(self.raw, &sources)
+                .map_err(|e| VulkanError::Pipeline(PipelineError::CacheMergeFailed(e)))
+        }
+    }
+
+    /// Write the cache next to its file and rename it over, a crash never leaves a torn file
+    pub fn save(&self, device: &Device) -> VulkanResult<()> {
+
+        puffin::profile_scope!("PipelineCache::save");
+
+        let Some(path) = &self.path else {
+            return Ok(());
         };
-        
-        let mut file = File::create(path).unwrap();
-        file.write_all(&data).unwrap();
-        
+
+        let data = unsafe {
+            device.get_pipeline_cache_data(self.raw)
+                .map_err(|e| VulkanError::Pipeline(PipelineError::CacheDataFailed(e)))
+        }?;
+
+        let io = |e| VulkanError::Pipeline(PipelineError::CacheIo(path.clone(), e));
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(io)?;
+        }
+
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, &data).map_err(io)?;
+        fs::rename(&tmp, path).map_err(io)?;
+
+        info!("Saved pipeline cache {:?} ({} bytes)", path, data.len());
         Ok(())
     }
 }
+
+/// Per-user cache directory of the engine
+pub fn default_cache_dir() -> Option<PathBuf> {
+
+    if let Some(dir) = std::env::var_os(CACHE_DIR_ENV) {
+        return Some(PathBuf::from(dir));
+    }
+
+    let base = if cfg!(target_os = "windows") {
+        std::env::var_os("LOCALAPPDATA").map(PathBuf::from)
+    } else if cfg!(target_os = "macos") {
+        std::env::var_os("HOME").map(|home| PathBuf::from(home).join("Library/Caches"))
+    } else {
+        std::env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))
+    };
+
+    base.map(|dir| dir.join("banana"))
+}
+
+/// Check that `data` was written by the same driver for the same device
+fn is_compatible(data: &[u8], properties: &vk::PhysicalDeviceProperties) -> bool {
+
+    if data.len() < HEADER_SIZE {
+        return false;
+    }
+
+    let word = |offset: usize| u32::from_le_bytes([data[offset], data[offset + 1], data[offset + 2], data[offset + 3]]);
+
+    word(0) as usize >= HEADER_SIZE
+        && word(4) == vk::PipelineCacheHeaderVersion::ONE.as_raw() as u32
+        && word(8) == properties.vendor_id
+        && word(12) == properties.device_id
+        && data[16..32] == properties.pipeline_cache_uuid
+}
+
+pub struct PipelineCacheBuilder<'a> {
+    device: &'a Device,
+    phys_dev: &'a PhysicalDevice,
+    path: Option<PathBuf>,
+}
+
+impl<'a> PipelineCacheBuilder<'a> {
+
+    pub fn new(device: &'a Device, phys_dev: &'a PhysicalDevice) -> Self {
+        Self {
+            device,
+            phys_dev,
+            path: None
+        }
+    }
+
+    /// File the cache is loaded from and saved to
+    pub fn path<P: AsRef<Path>>(mut self, path: P) -> Self {
+        self.path = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    pub fn build(self) -> VulkanResult<PipelineCache> {
+
+        puffin::profile_scope!("vkPipelineCache");
+
+        let data = match &self.path {
+            Some(path) => match fs::read(path) {
+                Ok(data) if is_compatible(&data, &self.phys_dev.properties) => {
+                    info!("Load pipeline cache {:?} ({} bytes)", path, data.len());
+                    data
+                }
+                Ok(_) => {
+                    warn!("Discard stale pipeline cache {:?}", path);
+                    vec![]
+                }
+                Err(_) => vec![],
+            },
+            None => vec![],
+        };
+
+        let create_info = vk::PipelineCacheCreateInfo::default()
+            .initial_data(&data);
+
+        let cache = unsafe {
+            self.device.create_pipeline_cache(&create_info, None)
+                .map_err(|e| VulkanError::Pipeline(PipelineError::CacheCreationFailed(e)))
+        }?;
+
+        Ok(PipelineCache {
+            raw: cache,
+            path: self.path,
+            deletion: self.device.register(cache)
+        })
+    }
+}
@@ -0,0 +1,345 @@
+use std::path::{Path, PathBuf};
+
+use ash::vk;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{AttributeDescriptions, BindingDescriptions, Device, GraphicsPipeline, GraphicsPipelineBuilder, PipelineTarget, VulkanResult};
+
+// ----------------- Serde for vk types ---------
+// Vulkan enums and flags are stored as their raw values
+macro_rules! serde_raw {
+    ($name:ident, $ty:ty, $raw:ty) => {
+        mod $name {
+            use super::*;
+
+            pub fn serialize<S: Serializer>(value: &$ty, serializer: S) -> Result<S::Ok, S::Error> {
+                value.as_raw().serialize(serializer)
+            }
+
+            pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<$ty, D::Error> {
+                <$raw>::deserialize(deserializer).map(<$ty>::from_raw)
+            }
+        }
+    };
+}
+
+serde_raw!(format, vk::Format, i32);
+serde_raw!(input_rate, vk::VertexInputRate, i32);
+serde_raw!(topology, vk::PrimitiveTopology, i32);
+serde_raw!(polygon_mode, vk::PolygonMode, i32);
+serde_raw!(cull_mode, vk::CullModeFlags, u32);
+serde_raw!(front_face, vk::FrontFace, i32);
+serde_raw!(compare_op, vk::CompareOp, i32);
+serde_raw!(blend_factor, vk::BlendFactor, i32);
+serde_raw!(blend_op, vk::BlendOp, i32);
+serde_raw!(color_mask, vk::ColorComponentFlags, u32);
+
+mod dynamic_states {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(value: &[vk::DynamicState], serializer: S) -> Result<S::Ok, S::Error> {
+        value.iter().map(|s| s.as_raw()).collect::<Vec<_>>().serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<vk::DynamicState>, D::Error> {
+        Vec::<i32>::deserialize(deserializer).map(|raw| raw.into_iter().map(vk::DynamicState::from_raw).collect())
+    }
+}
+// ----------------- End ------------------------------------
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct VertexBinding {
+    pub binding: u32,
+    pub stride: u32,
+    #[serde(with = "input_rate")]
+    pub input_rate: vk::VertexInputRate,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct VertexAttribute {
+    pub location: u32,
+    pub binding: u32,
+    #[serde(with = "format")]
+    pub format: vk::Format,
+    pub offset: u32,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct VertexLayout {
+    pub bindings: Vec<VertexBinding>,
+    pub attributes: Vec<VertexAttribute>,
+}
+
+impl VertexLayout {
+
+    /// Layout of a vertex type, no vertex buffers when not set
+    pub fn of<V: AttributeDescriptions + BindingDescriptions>() -> Self {
+        Self {
+            bindings: V::bind_desc().iter()
+                .map(|b| VertexBinding { binding: b.binding, stride: b.stride, input_rate: b.input_rate })
+                .collect(),
+            attributes: V::attr_desc().iter()
+                .map(|a| VertexAttribute { location: a.location, binding: a.binding, format: a.format, offset: a.offset })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct RasterState {
+    #[serde(with = "polygon_mode")]
+    pub polygon_mode: vk::PolygonMode,
+    #[serde(with = "cull_mode")]
+    pub cull_mode: vk::CullModeFlags,
+    #[serde(with = "front_face")]
+    pub front_face: vk::FrontFace,
+    pub depth_clamp: bool,
+}
+
+impl Default for RasterState {
+    fn default() -> Self {
+        Self {
+            polygon_mode: vk::PolygonMode::FILL,
+            cull_mode: vk::CullModeFlags::NONE,
+            front_face: vk::FrontFace::CLOCKWISE,
+            depth_clamp: false,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct DepthStencilState {
+    pub test: bool,
+    pub write: bool,
+    #[serde(with = "compare_op")]
+    pub compare_op: vk::CompareOp,
+}
+
+impl DepthStencilState {
+
+    pub fn disabled() -> Self {
+        Self { test: false, write: false, compare_op: vk::CompareOp::ALWAYS }
+    }
+
+    pub fn read_write() -> Self {
+        Self { test: true, write: true, compare_op: vk::CompareOp::LESS }
+    }
+}
+
+/// Depth test without depth writes
+impl Default for DepthStencilState {
+    fn default() -> Self {
+        Self { test: true, write: false, compare_op: vk::CompareOp::LESS }
+    }
+}
+
+/// Blending of one color attachment
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct BlendState {
+    pub enable: bool,
+    #[serde(with = "blend_factor")]
+    pub src_color: vk::BlendFactor,
+    #[serde(with = "blend_factor")]
+    pub dst_color: vk::BlendFactor,
+    #[serde(with = "blend_op")]
+    pub color_op: vk::BlendOp,
+    #[serde(with = "blend_factor")]
+    pub src_alpha: vk::BlendFactor,
+    #[serde(with = "blend_factor")]
+    pub dst_alpha: vk::BlendFactor,
+    #[serde(with = "blend_op")]
+    pub alpha_op: vk::BlendOp,
+    #[serde(with = "color_mask")]
+    pub write_mask: vk::ColorComponentFlags,
+}
+
+impl BlendState {
+
+    pub fn alpha() -> Self {
+        Self {
+            enable: true,
+            src_color: vk::BlendFactor::SRC_ALPHA,
+            dst_color: vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+            src_alpha: vk::BlendFactor::ONE,
+            dst_alpha: vk::BlendFactor::ONE_MINUS_SRC_ALPHA,
+            ..Self::default()
+        }
+    }
+
+    fn to_vk(self) -> vk::PipelineColorBlendAttachmentState {
+        vk::PipelineColorBlendAttachmentState::default()
+            .blend_enable(self.enable)
+            .src_color_blend_factor(self.src_color)
+            .dst_color_blend_factor(self.dst_color)
+            .color_blend_op(self.color_op)
+            .src_alpha_blend_factor(self.src_alpha)
+            .dst_alpha_blend_factor(self.dst_alpha)
+            .alpha_blend_op(self.alpha_op)
+            .color_write_mask(self.write_mask)
+    }
+}
+
+/// Blending off, all channels written
+impl Default for BlendState {
+    fn default() -> Self {
+        Self {
+            enable: false,
+            src_color: vk::BlendFactor::ONE,
+            dst_color: vk::BlendFactor::ZERO,
+            color_op: vk::BlendOp::ADD,
+            src_alpha: vk::BlendFactor::ONE,
+            dst_alpha: vk::BlendFactor::ZERO,
+            alpha_op: vk::BlendOp::ADD,
+            write_mask: vk::ColorComponentFlags::R
+                | vk::ColorComponentFlags::G
+                | vk::ColorComponentFlags::B
+                | vk::ColorComponentFlags::A,
+        }
+    }
+}
+
+/// Owned description of a graphics pipeline, identical descriptions build identical pipelines.
+/// Viewport and scissor are always dynamic, `dynamic_states` lists additional ones
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct GraphicsPipelineDesc {
+    pub vertex_shader: PathBuf,
+    pub fragment_shader: Option<PathBuf>,
+    pub vertex_layout: VertexLayout,
+    #[serde(with = "topology")]
+    pub topology: vk::PrimitiveTopology,
+    pub raster: RasterState,
+    pub depth_stencil: DepthStencilState,
+    /// One entry per color attachment
+    pub blend: Vec<BlendState>,
+    #[serde(with = "dynamic_states")]
+    pub dynamic_states: Vec<vk::DynamicState>,
+}
+
+impl GraphicsPipelineDesc {
+
+    pub fn new<P: AsRef<Path>>(vertex_shader: P, fragment_shader: P) -> Self {
+        Self {
+            vertex_shader: vertex_shader.as_ref().to_path_buf(),
+            fragment_shader: Some(fragment_shader.as_ref().to_path_buf()),
+            vertex_layout: VertexLayout::default(),
+            topology: vk::PrimitiveTopology::TRIANGLE_LIST,
+            raster: RasterState::default(),
+            depth_stencil: DepthStencilState::default(),
+            blend: vec![BlendState::default()],
+            dynamic_states: vec![],
+        }
+    }
+
+    pub fn vertex_layout(mut self, layout: VertexLayout) -> Self {
+        self.vertex_layout = layout;
+        self
+    }
+
+    pub fn topology(mut self, topology: vk::PrimitiveTopology) -> Self {
+        self.topology = topology;
+        self
+    }
+
+    pub fn raster(mut self, raster: RasterState) -> Self {
+        self.raster = raster;
+        self
+    }
+
+    pub fn depth_stencil(mut self, depth_stencil: DepthStencilState) -> Self {
+        self.depth_stencil = depth_stencil;
+        self
+    }
+
+    pub fn blend(mut self, blend: Vec<BlendState>) -> Self {
+        self.blend = blend;
+        self
+    }
+
+    pub fn dynamic_state(mut self, state: vk::DynamicState) -> Self {
+        self.dynamic_states.push(state);
+        self
+    }
+
+    pub fn build(
+        &self,
+        device: &Device,
+        cache: vk::PipelineCache,
+        layout: vk::PipelineLayout,
+        target: &PipelineTarget
+    ) -> VulkanResult<GraphicsPipeline> {
+
+        let bindings = self.vertex_layout.bindings.iter()
+            .map(|b| vk::VertexInputBindingDescription { binding: b.binding, stride: b.stride, input_rate: b.input_rate })
+            .collect::<Vec<_>>();
+
+        let attributes = self.vertex_layout.attributes.iter()
+            .map(|a| vk::VertexInputAttributeDescription { location: a.location, binding: a.binding, format: a.format, offset: a.offset })
+            .collect::<Vec<_>>();
+
+        let blend = self.blend.iter().map(|b| b.to_vk()).collect::<Vec<_>>();
+
+        let mut dynamic_states = vec![vk::DynamicState::VIEWPORT, vk::DynamicState::SCISSOR];
+        for state in &self.dynamic_states {
+            if !dynamic_states.contains(state) {
+                dynamic_states.push(*state);
+            }
+        }
+
+        let mut builder = GraphicsPipelineBuilder::new(device)
+            .cache(cache)
+            .vertex_shader_from_file(self.vertex_shader.as_path())
+            .target(target.clone())
+            .pipeline_layout(layout)
+            // Overridden by the dynamic state, only the counts matter
+            .viewport(vec![vk::Viewport::default().width(1.0).height(1.0).max_depth(1.0)])
+            .scissors(vec![vk::Rect2D::default()])
+            .input_assembly(
+                vk::PipelineInputAssemblyStateCreateInfo::default()
+                    .topology(self.topology)
+                    .primitive_restart_enable(false)
+            )
+            .rasterization(
+                vk::PipelineRasterizationStateCreateInfo::default()
+                    .depth_clamp_enable(self.raster.depth_clamp)
+                    .rasterizer_discard_enable(false)
+                    .polygon_mode(self.raster.polygon_mode)
+                    .line_width(1.0)
+                    .cull_mode(self.raster.cull_mode)
+                    .front_face(self.raster.front_face)
+                    .depth_bias_enable(false)
+            )
+            .vertex_input_info(
+                vk::PipelineVertexInputStateCreateInfo::default()
+                    .vertex_binding_descriptions(&bindings)
+                    .vertex_attribute_descriptions(&attributes)
+            )
+            .multisampling(
+                vk::PipelineMultisampleStateCreateInfo::default()
+                    .sample_shading_enable(false)
+                    .rasterization_samples(vk::SampleCountFlags::TYPE_1)
+            )
+            .color_blending(
+                vk::PipelineColorBlendStateCreateInfo::default()
+                    .logic_op_enable(false)
+                    .logic_op(vk::LogicOp::COPY)
+                    .attachments(&blend)
+            )
+            .depth_stencil(
+                vk::PipelineDepthStencilStateCreateInfo::default()
+                    .depth_test_enable(self.depth_stencil.test)
+                    .depth_write_enable(self.depth_stencil.write)
+                    .depth_compare_op(self.depth_stencil.compare_op)
+                    .depth_bounds_test_enable(false)
+                    .min_depth_bounds(0.0)
+                    .max_depth_bounds(1.0)
+                    .stencil_test_enable(false)
+            )
+            .dynamic_state(dynamic_states);
+
+        if let Some(fragment) = &self.fragment_shader {
+            builder = builder.fragment_shader_from_file(fragment.as_path());
+        }
+
+        builder.build()
+    }
+}
@@ -1,10 +1,18 @@
 
 use ash::vk;
+use crate::core::{Deferred, DeletionQueue};
 
-use crate::{Device, VulkanError, VulkanResult};
+use crate::{Device, PipelineError, VulkanError, VulkanResult};
 
 pub struct PipelineLayout {
-    pub(crate) raw: vk::PipelineLayout
+    pub(crate) raw: vk::PipelineLayout,
+    deletion: DeletionQueue,
+}
+
+impl Drop for PipelineLayout {
+    fn drop(&mut self) {
+        self.deletion.push(Deferred::PipelineLayout(self.raw));
+    }
 }
 
 pub struct PipelineLayoutBuilder<'a> {
@@ -40,9 +48,12 @@ impl<'a> PipelineLayoutBuilder<'a> {
 
         let layout = unsafe { 
             self.device.create_pipeline_layout(&create_info, None) 
-                .map_err(|e| VulkanError::Unknown(e))
+                .map_err(|e| VulkanError::Pipeline(PipelineError::PipelineLayoutCreationFailed(e)))
         }?;
 
-        Ok(PipelineLayout { raw: layout })
+        Ok(PipelineLayout {
+            raw: layout,
+            deletion: self.device.register(layout)
+        })
     }
 }
\ No newline at end of file
@@ -0,0 +1,176 @@
+use ash::vk;
+use log::debug;
+
+use crate::{Device, QueryError, VulkanError, VulkanResult};
+use crate::core::{Deferred, DeletionQueue};
+
+/// Statistics recorded by `QueryPoolBuilder::pipeline_statistics`, in result order
+pub const PASS_STATISTICS: vk::QueryPipelineStatisticFlags = vk::QueryPipelineStatisticFlags::from_raw(
+    vk::QueryPipelineStatisticFlags::VERTEX_SHADER_INVOCATIONS.as_raw()
+        | vk::QueryPipelineStatisticFlags::CLIPPING_PRIMITIVES.as_raw()
+        | vk::QueryPipelineStatisticFlags::FRAGMENT_SHADER_INVOCATIONS.as_raw()
+        | vk::QueryPipelineStatisticFlags::COMPUTE_SHADER_INVOCATIONS.as_raw()
+);
+
+pub struct QueryPool {
+    pub(crate) raw: vk::QueryPool,
+    ty: vk::QueryType,
+    count: u32,
+    statistics: vk::QueryPipelineStatisticFlags,
+    deletion: DeletionQueue,
+}
+
+impl Drop for QueryPool {
+    fn drop(&mut self) {
+        self.deletion.push(Deferred::QueryPool(self.raw));
+    }
+}
+
+impl QueryPool {
+
+    pub fn query_type(&self) -> vk::QueryType {
+        self.ty
+    }
+
+    pub fn count(&self) -> u32 {
+        self.count
+    }
+
+    /// Values each query writes, one per enabled statistic for pipeline statistics
+    pub fn values_per_query(&self) -> usize {
+        if self.ty == vk::QueryType::PIPELINE_STATISTICS {
+            self.statistics.as_raw().count_ones() as usize
+        } else {
+            1
+        }
+    }
+
+    /// Must be recorded outside of a render pass before the queries are used again
+    pub fn reset(&self, device: &Device, cmd: vk::CommandBuffer, first: u32, count: u32) {
+        unsafe { device.cmd_reset_query_pool(cmd, self.raw, first, count) };
+    }
+
+    pub fn begin(&self, device: &Device, cmd: vk::CommandBuffer, query: u32) {
+        unsafe { device.cmd_begin_query(cmd, self.raw, query, vk::QueryControlFlags::empty()) };
+    }
+
+    pub fn end(&self, device: &Device, cmd: vk::CommandBuffer, query: u32) {
+        unsafe { device.cmd_end_query(cmd, self.raw, query) };
+    }
+
+    pub fn write_timestamp(&self, device: &Device, cmd: vk::CommandBuffer, stage: vk::PipelineStageFlags, query: u32) {
+        unsafe { device.cmd_write_timestamp(cmd, stage, self.raw, query) };
+    }
+
+    /// Results of `count` queries without waiting, `None` while any of them is not available yet
+    pub fn results(&self, device: &Device, first: u32, count: u32) -> VulkanResult<Option<Vec<u64>>> {
+
+        let (result, data) = self.read(device, first, count, vk::QueryResultFlags::empty());
+
+        match result {
+            vk::Result::SUCCESS => Ok(Some(data)),
+            vk::Result::NOT_READY => Ok(None),
+            e => Err(VulkanError::Query(QueryError::GetResultsFailed(e))),
+        }
+    }
+
+    /// Results of `count` queries without waiting, `None` for each query not available yet
+    pub fn available_results(&self, device: &Device, first: u32, count: u32) -> VulkanResult<Vec<Option<Vec<u64>>>> {
+
+        let (result, data) = self.read(device, first, count, vk::QueryResultFlags::WITH_AVAILABILITY);
+
+        if result != vk::Result::SUCCESS && result != vk::Result::NOT_READY {
+            return Err(VulkanError::Query(QueryError::GetResultsFailed(result)));
+        }
+
+        // The availability word follows the values of each query
+        let values = self.values_per_query();
+
+        Ok(data.chunks_exact(values + 1)
+            .map(|query| (query[values] != 0).then(|| query[..values].to_vec()))
+            .collect())
+    }
+
+    /// `get_query_pool_results` takes the stride from the element type, so call it with an explicit one
+    fn read(&self, device: &Device, first: u32, count: u32, flags: vk::QueryResultFlags) -> (vk::Result, Vec<u64>) {
+
+        let stride = self.values_per_query() + flags.contains(vk::QueryResultFlags::WITH_AVAILABILITY) as usize;
+        let mut data = vec![0u64; count as usize * stride];
+
+        let result = unsafe {
+            (device.fp_v1_0().get_query_pool_results)(
+                device.handle(),
+                self.raw,
+                first,
+                count,
+                size_of_val(data.as_slice()),
+                data.as_mut_ptr().cast(),
+                (stride * size_of::<u64>()) as vk::DeviceSize,
+                flags | vk::QueryResultFlags::TYPE_64,
+            )
+        };
+
+        (result, data)
+    }
+}
+
+pub struct QueryPoolBuilder<'a> {
+    device: &'a Device,
+    create_info: vk::QueryPoolCreateInfo<'static>,
+}
+
+impl<'a> QueryPoolBuilder<'a> {
+
+    /// Samples passing the depth and stencil tests
+    pub fn occlusion(device: &'a Device, count: u32) -> Self {
+        Self {
+            device,
+            create_info: vk::QueryPoolCreateInfo::default()
+                .query_type(vk::QueryType::OCCLUSION)
+                .query_count(count),
+        }
+    }
+
+    /// Needs `DeviceFeatures::pipeline_statistics_query`
+    pub fn pipeline_statistics(device: &'a Device, count: u32, statistics: vk::QueryPipelineStatisticFlags) -> Self {
+        Self {
+            device,
+            create_info: vk::QueryPoolCreateInfo::default()
+                .query_type(vk::QueryType::PIPELINE_STATISTICS)
+                .pipeline_statistics(statistics)
+                .query_count(count),
+        }
+    }
+
+    /// Ticks are converted with `VkPhysicalDeviceLimits::timestampPeriod`
+    pub fn timestamps(device: &'a Device, count: u32) -> Self {
+        Self {
+            device,
+            create_info: vk::QueryPoolCreateInfo::default()
+                .query_type(vk::QueryType::TIMESTAMP)
+                .query_count(count),
+        }
+    }
+
+    pub fn build(self) -> VulkanResult<QueryPool> {
+
+        if self.create_info.query_type == vk::QueryType::PIPELINE_STATISTICS && !self.device.features().pipeline_statistics_query {
+            return Err(VulkanError::Query(QueryError::StatisticsUnsupported));
+        }
+
+        let pool = unsafe {
+            self.device.create_query_pool(&self.create_info, None)
+                .map_err(|e| VulkanError::Query(QueryError::PoolCreationFailed(e)))
+        }?;
+
+        debug!("Create QueryPool {:?} with {} queries", self.create_info.query_type, self.create_info.query_count);
+
+        Ok(QueryPool {
+            raw: pool,
+            ty: self.create_info.query_type,
+            count: self.create_info.query_count,
+            statistics: self.create_info.pipeline_statistics,
+            deletion: self.device.register(pool)
+        })
+    }
+}
@@ -0,0 +1,836 @@
+use std::{collections::{BTreeMap, HashMap}, path::Path};
+
+use ash::vk;
+use log::{debug, warn};
+
+use crate::{AttributeDescriptions, DescriptorSetLayoutBuilder, Device, PipelineLayoutBuilder, ReflectionError, VulkanError, VulkanResult};
+use super::shader::load_spv;
+
+// ----------------- SPIR-V constants ---------
+const MAGIC: u32 = 0x07230203;
+
+const OP_ENTRY_POINT: u32 = 15;
+const OP_TYPE_BOOL: u32 = 20;
+const OP_TYPE_INT: u32 = 21;
+const OP_TYPE_FLOAT: u32 = 22;
+const OP_TYPE_VECTOR: u32 = 23;
+const OP_TYPE_MATRIX: u32 = 24;
+const OP_TYPE_IMAGE: u32 = 25;
+const OP_TYPE_SAMPLER: u32 = 26;
+const OP_TYPE_SAMPLED_IMAGE: u32 = 27;
+const OP_TYPE_ARRAY: u32 = 28;
+const OP_TYPE_RUNTIME_ARRAY: u32 = 29;
+const OP_TYPE_STRUCT: u32 = 30;
+const OP_TYPE_POINTER: u32 = 32;
+const OP_CONSTANT: u32 = 43;
+const OP_SPEC_CONSTANT: u32 = 50;
+const OP_VARIABLE: u32 = 59;
+const OP_DECORATE: u32 = 71;
+const OP_MEMBER_DECORATE: u32 = 72;
+const OP_TYPE_ACCELERATION_STRUCTURE: u32 = 5341;
+
+const DECORATION_BLOCK: u32 = 2;
+const DECORATION_BUFFER_BLOCK: u32 = 3;
+const DECORATION_ARRAY_STRIDE: u32 = 6;
+const DECORATION_MATRIX_STRIDE: u32 = 7;
+const DECORATION_BUILT_IN: u32 = 11;
+const DECORATION_LOCATION: u32 = 30;
+const DECORATION_BINDING: u32 = 33;
+const DECORATION_DESCRIPTOR_SET: u32 = 34;
+const DECORATION_OFFSET: u32 = 35;
+
+const STORAGE_UNIFORM_CONSTANT: u32 = 0;
+const STORAGE_INPUT: u32 = 1;
+const STORAGE_UNIFORM: u32 = 2;
+const STORAGE_PUSH_CONSTANT: u32 = 9;
+const STORAGE_STORAGE_BUFFER: u32 = 12;
+
+const DIM_BUFFER: u32 = 5;
+const DIM_SUBPASS_DATA: u32 = 6;
+// ----------------- End ------------------------------------
+
+/// Descriptor count used for runtime sized arrays (`textures[]`)
+pub const DEFAULT_RUNTIME_ARRAY_COUNT: u32 = 1024;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ReflectedBinding {
+    pub set: u32,
+    pub binding: u32,
+    pub descriptor_type: vk::DescriptorType,
+    /// `DEFAULT_RUNTIME_ARRAY_COUNT` for runtime sized arrays
+    pub count: u32,
+    pub stages: vk::ShaderStageFlags,
+    /// Declared without a size, bound partially when descriptor indexing is enabled
+    pub runtime_array: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ReflectedInput {
+    pub location: u32,
+    pub format: vk::Format,
+    pub size: u32,
+}
+
+/// Resources used by a single shader stage
+#[derive(Clone, Debug, Default)]
+pub struct ShaderReflection {
+    pub stage: vk::ShaderStageFlags,
+    pub entry_point: String,
+    pub bindings: Vec<ReflectedBinding>,
+    /// Offset of the first member of the push constant block
+    pub push_constant_offset: u32,
+    /// Bytes from `push_constant_offset` to the end of the block
+    pub push_constant_size: Option<u32>,
+    /// Vertex inputs, empty for every stage but the vertex stage
+    pub inputs: Vec<ReflectedInput>,
+}
+
+enum Type {
+    Bool,
+    Int { width: u32, signed: bool },
+    Float { width: u32 },
+    Vector { component: u32, count: u32 },
+    Matrix { column: u32, count: u32 },
+    Image { dim: u32, sampled: u32 },
+    Sampler,
+    SampledImage,
+    AccelerationStructure,
+    Array { element: u32, length: u32 },
+    RuntimeArray { element: u32 },
+    Struct { members: Vec<u32> },
+    Pointer { pointee: u32 },
+}
+
+#[derive(Default)]
+struct Decorations {
+    set: Option<u32>,
+    binding: Option<u32>,
+    location: Option<u32>,
+    array_stride: Option<u32>,
+    built_in: bool,
+    block: bool,
+    buffer_block: bool,
+}
+
+#[derive(Default)]
+struct MemberDecorations {
+    offset: Option<u32>,
+    matrix_stride: Option<u32>,
+}
+
+#[derive(Default)]
+struct Module {
+    entry_point: Option<(u32, String)>,
+    types: HashMap<u32, Type>,
+    constants: HashMap<u32, u32>,
+    variables: Vec<(u32, u32, u32)>,
+    decorations: HashMap<u32, Decorations>,
+    members: HashMap<(u32, u32), MemberDecorations>,
+}
+
+fn arg(ops: &[u32], index: usize) -> Result<u32, ReflectionError> {
+    ops.get(index).copied().ok_or(ReflectionError::Malformed("instruction is too short"))
+}
+
+fn literal_string(words: &[u32]) -> String {
+    let bytes = words.iter()
+        .flat_map(|w| w.to_le_bytes())
+        .take_while(|&b| b != 0)
+        .collect::<Vec<_>>();
+
+    String::from_utf8_lossy(&bytes).into_owned()
+}
+
+impl Module {
+
+    fn parse(words: &[u32]) -> Result<Self, ReflectionError> {
+
+        if words.len() < 5 || words[0] != MAGIC {
+            return Err(ReflectionError::Malformed("missing SPIR-V header"));
+        }
+
+        let mut module = Module::default();
+        let mut body = &words[5..];
+
+        while !body.is_empty() {
+            let word_count = (body[0] >> 16) as usize;
+            let opcode = body[0] & 0xffff;
+
+            if word_count == 0 || word_count > body.len() {
+                return Err(ReflectionError::Malformed("truncated instruction"));
+            }
+
+            let ops = &body[1..word_count];
+            body = &body[word_count..];
+
+            match opcode {
+                OP_ENTRY_POINT if module.entry_point.is_none() => {
+                    let model = arg(ops, 0)?;
+                    module.entry_point = Some((model, literal_string(ops.get(2..).unwrap_or(&[]))));
+                }
+                OP_TYPE_BOOL => {
+                    module.types.insert(arg(ops, 0)?, Type::Bool);
+                }
+                OP_TYPE_INT => {
+                    module.types.insert(arg(ops, 0)?, Type::Int { width: arg(ops, 1)?, signed: arg(ops, 2)? == 1 });
+                }
+                OP_TYPE_FLOAT => {
+                    module.types.insert(arg(ops, 0)?, Type::Float { width: arg(ops, 1)? });
+                }
+                OP_TYPE_VECTOR => {
+                    module.types.insert(arg(ops, 0)?, Type::Vector { component: arg(ops, 1)?, count: arg(ops, 2)? });
+                }
+                OP_TYPE_MATRIX => {
+                    module.types.insert(arg(ops, 0)?, Type::Matrix { column: arg(ops, 1)?, count: arg(ops, 2)? });
+                }
+                OP_TYPE_IMAGE => {
+                    module.types.insert(arg(ops, 0)?, Type::Image { dim: arg(ops, 2)?, sampled: arg(ops, 6)? });
+                }
+                OP_TYPE_SAMPLER => {
+                    module.types.insert(arg(ops, 0)?, Type::Sampler);
+                }
+                OP_TYPE_SAMPLED_IMAGE => {
+                    module.types.insert(arg(ops, 0)?, Type::SampledImage);
+                }
+                OP_TYPE_ACCELERATION_STRUCTURE => {
+                    module.types.insert(arg(ops, 0)?, Type::AccelerationStructure);
+                }
+                OP_TYPE_ARRAY => {
+                    module.types.insert(arg(ops, 0)?, Type::Array { element: arg(ops, 1)?, length: arg(ops, 2)? });
+                }
+                OP_TYPE_RUNTIME_ARRAY => {
+                    module.types.insert(arg(ops, 0)?, Type::RuntimeArray { element: arg(ops, 1)? });
+                }
+                OP_TYPE_STRUCT => {
+                    module.types.insert(arg(ops, 0)?, Type::Struct { members: ops.get(1..).unwrap_or(&[]).to_vec() });
+                }
+                OP_TYPE_POINTER => {
+                    module.types.insert(arg(ops, 0)?, Type::Pointer { pointee: arg(ops, 2)? });
+                }
+                OP_CONSTANT | OP_SPEC_CONSTANT => {
+                    module.constants.insert(arg(ops, 1)?, arg(ops, 2)?);
+                }
+                OP_VARIABLE => {
+                    module.variables.push((arg(ops, 0)?, arg(ops, 1)?, arg(ops, 2)?));
+                }
+                OP_DECORATE => {
+                    let decorations = module.decorations.entry(arg(ops, 0)?).or_default();
+
+                    match arg(ops, 1)? {
+                        DECORATION_BLOCK => decorations.block = true,
+                        DECORATION_BUFFER_BLOCK => decorations.buffer_block = true,
+                        DECORATION_BUILT_IN => decorations.built_in = true,
+                        DECORATION_ARRAY_STRIDE => decorations.array_stride = Some(arg(ops, 2)?),
+                        DECORATION_LOCATION => decorations.location = Some(arg(ops, 2)?),
+                        DECORATION_BINDING => decorations.binding = Some(arg(ops, 2)?),
+                        DECORATION_DESCRIPTOR_SET => decorations.set = Some(arg(ops, 2)?),
+                        _ => {}
+                    }
+                }
+                OP_MEMBER_DECORATE => {
+                    let member = module.members.entry((arg(ops, 0)?, arg(ops, 1)?)).or_default();
+
+                    match arg(ops, 2)? {
+                        DECORATION_OFFSET => member.offset = Some(arg(ops, 3)?),
+                        DECORATION_MATRIX_STRIDE => member.matrix_stride = Some(arg(ops, 3)?),
+                        _ => {}
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(module)
+    }
+
+    fn ty(&self, id: u32) -> Result<&Type, ReflectionError> {
+        self.types.get(&id).ok_or(ReflectionError::Malformed("reference to an unknown type"))
+    }
+
+    /// Byte size of a type laid out with explicit offsets and strides
+    fn size_of(&self, id: u32, matrix_stride: Option<u32>) -> Result<u32, ReflectionError> {
+        let size = match self.ty(id)? {
+            Type::Bool => 4,
+            Type::Int { width, .. } | Type::Float { width } => width / 8,
+            Type::Vector { component, count } => self.size_of(*component, None)? * count,
+            Type::Matrix { column, count } => match matrix_stride {
+                Some(stride) => stride * count,
+                None => self.size_of(*column, None)? * count,
+            },
+            Type::Array { element, length } => {
+                let length = self.constants.get(length).copied().unwrap_or(0);
+                let stride = match self.decorations.get(&id).and_then(|d| d.array_stride) {
+                    Some(stride) => stride,
+                    None => self.size_of(*element, matrix_stride)?,
+                };
+                stride * length
+            }
+            Type::Struct { members } => {
+                let mut size = 0;
+
+                for (index, &member) in members.iter().enumerate() {
+                    let decorations = self.members.get(&(id, index as u32));
+                    let offset = decorations.and_then(|d| d.offset).unwrap_or(size);
+                    let member_size = self.size_of(member, decorations.and_then(|d| d.matrix_stride))?;
+                    size = size.max(offset + member_size);
+                }
+
+                size
+            }
+            _ => 0,
+        };
+
+        Ok(size)
+    }
+
+    fn descriptor_type(&self, id: u32, storage: u32) -> Result<vk::DescriptorType, ReflectionError> {
+        let ty = match (self.ty(id)?, storage) {
+            (Type::SampledImage, _) => vk::DescriptorType::COMBINED_IMAGE_SAMPLER,
+            (Type::Sampler, _) => vk::DescriptorType::SAMPLER,
+            (Type::AccelerationStructure, _) => vk::DescriptorType::ACCELERATION_STRUCTURE_KHR,
+            (Type::Image { dim: DIM_SUBPASS_DATA, .. }, _) => vk::DescriptorType::INPUT_ATTACHMENT,
+            (Type::Image { dim: DIM_BUFFER, sampled: 2 }, _) => vk::DescriptorType::STORAGE_TEXEL_BUFFER,
+            (Type::Image { dim: DIM_BUFFER, .. }, _) => vk::DescriptorType::UNIFORM_TEXEL_BUFFER,
+            (Type::Image { sampled: 2, .. }, _) => vk::DescriptorType::STORAGE_IMAGE,
+            (Type::Image { .. }, _) => vk::DescriptorType::SAMPLED_IMAGE,
+            (Type::Struct { .. }, STORAGE_STORAGE_BUFFER) => vk::DescriptorType::STORAGE_BUFFER,
+            (Type::Struct { .. }, _) => {
+                match self.decorations.get(&id) {
+                    Some(d) if d.buffer_block => vk::DescriptorType::STORAGE_BUFFER,
+                    Some(d) if d.block => vk::DescriptorType::UNIFORM_BUFFER,
+                    _ => return Err(ReflectionError::Malformed("buffer struct without Block decoration")),
+                }
+            }
+            _ => return Err(ReflectionError::Malformed("unsupported descriptor type")),
+        };
+
+        Ok(ty)
+    }
+
+    /// Format and size of every location a vertex input occupies, one per column for matrices
+    fn input_formats(&self, id: u32) -> Result<Vec<(vk::Format, u32)>, ReflectionError> {
+        if let Type::Matrix { column, count } = self.ty(id)? {
+            let column = self.input_formats(*column)?;
+            return Ok(column.iter().copied().cycle().take(column.len() * *count as usize).collect());
+        }
+
+        let (component, count) = match self.ty(id)? {
+            Type::Vector { component, count } => (*component, *count),
+            _ => (id, 1),
+        };
+
+        let formats = match self.ty(component)? {
+            Type::Float { width: 16 } => [vk::Format::R16_SFLOAT, vk::Format::R16G16_SFLOAT, vk::Format::R16G16B16_SFLOAT, vk::Format::R16G16B16A16_SFLOAT],
+            Type::Float { width: 32 } => [vk::Format::R32_SFLOAT, vk::Format::R32G32_SFLOAT, vk::Format::R32G32B32_SFLOAT, vk::Format::R32G32B32A32_SFLOAT],
+            Type::Float { width: 64 } => [vk::Format::R64_SFLOAT, vk::Format::R64G64_SFLOAT, vk::Format::R64G64B64_SFLOAT, vk::Format::R64G64B64A64_SFLOAT],
+            Type::Int { width: 8, signed: true } => [vk::Format::R8_SINT, vk::Format::R8G8_SINT, vk::Format::R8G8B8_SINT, vk::Format::R8G8B8A8_SINT],
+            Type::Int { width: 8, signed: false } => [vk::Format::R8_UINT, vk::Format::R8G8_UINT, vk::Format::R8G8B8_UINT, vk::Format::R8G8B8A8_UINT],
+            Type::Int { width: 16, signed: true } => [vk::Format::R16_SINT, vk::Format::R16G16_SINT, vk::Format::R16G16B16_SINT, vk::Format::R16G16B16A16_SINT],
+            Type::Int { width: 16, signed: false } => [vk::Format::R16_UINT, vk::Format::R16G16_UINT, vk::Format::R16G16B16_UINT, vk::Format::R16G16B16A16_UINT],
+            Type::Int { width: 32, signed: true } => [vk::Format::R32_SINT, vk::Format::R32G32_SINT, vk::Format::R32G32B32_SINT, vk::Format::R32G32B32A32_SINT],
+            Type::Int { width: 32, signed: false } => [vk::Format::R32_UINT, vk::Format::R32G32_UINT, vk::Format::R32G32B32_UINT, vk::Format::R32G32B32A32_UINT],
+            Type::Int { width: 64, signed: true } => [vk::Format::R64_SINT, vk::Format::R64G64_SINT, vk::Format::R64G64B64_SINT, vk::Format::R64G64B64A64_SINT],
+            Type::Int { width: 64, signed: false } => [vk::Format::R64_UINT, vk::Format::R64G64_UINT, vk::Format::R64G64B64_UINT, vk::Format::R64G64B64A64_UINT],
+            _ => return Err(ReflectionError::Malformed("unsupported vertex input type")),
+        };
+
+        let format = formats.get(count as usize - 1)
+            .ok_or(ReflectionError::Malformed("vertex input with more than 4 components"))?;
+
+        Ok(vec![(*format, self.size_of(id, None)?)])
+    }
+
+    /// Lowest member offset of a block, where its push constant range starts
+    fn block_offset(&self, id: u32) -> u32 {
+        match self.types.get(&id) {
+            Some(Type::Struct { members }) => (0..members.len() as u32)
+                .filter_map(|index| self.members.get(&(id, index)).and_then(|d| d.offset))
+                .min()
+                .unwrap_or(0),
+            _ => 0,
+        }
+    }
+}
+
+fn stage_from_model(model: u32) -> Result<vk::ShaderStageFlags, ReflectionError> {
+    let stage = match model {
+        0 => vk::ShaderStageFlags::VERTEX,
+        1 => vk::ShaderStageFlags::TESSELLATION_CONTROL,
+        2 => vk::ShaderStageFlags::TESSELLATION_EVALUATION,
+        3 => vk::ShaderStageFlags::GEOMETRY,
+        4 => vk::ShaderStageFlags::FRAGMENT,
+        5 => vk::ShaderStageFlags::COMPUTE,
+        5313 => vk::ShaderStageFlags::RAYGEN_KHR,
+        5314 => vk::ShaderStageFlags::INTERSECTION_KHR,
+        5315 => vk::ShaderStageFlags::ANY_HIT_KHR,
+        5316 => vk::ShaderStageFlags::CLOSEST_HIT_KHR,
+        5317 => vk::ShaderStageFlags::MISS_KHR,
+        5318 => vk::ShaderStageFlags::CALLABLE_KHR,
+        5364 => vk::ShaderStageFlags::TASK_EXT,
+        5365 => vk::ShaderStageFlags::MESH_EXT,
+        other => return Err(ReflectionError::UnsupportedStage(other)),
+    };
+
+    Ok(stage)
+}
+
+impl ShaderReflection {
+
+    pub fn from_spv(words: &[u32]) -> Result<Self, ReflectionError> {
+
+        puffin::profile_scope!("ShaderReflection");
+
+        let module = Module::parse(words)?;
+
+        let (model, entry_point) = module.entry_point.clone()
+            .ok_or(ReflectionError::Malformed("no entry point"))?;
+        let stage = stage_from_model(model)?;
+
+        let mut bindings = vec![];
+        let mut push_constant_offset = 0;
+        let mut push_constant_size = None;
+        let mut inputs = vec![];
+
+        for &(pointer, id, storage) in &module.variables {
+
+            let pointee = match module.ty(pointer)? {
+                Type::Pointer { pointee } => *pointee,
+                _ => return Err(ReflectionError::Malformed("variable is not a pointer")),
+            };
+            let decorations = module.decorations.get(&id);
+
+            match storage {
+                STORAGE_UNIFORM_CONSTANT | STORAGE_UNIFORM | STORAGE_STORAGE_BUFFER => {
+                    let (Some(set), Some(binding)) = (
+                        decorations.and_then(|d| d.set),
+                        decorations.and_then(|d| d.binding)
+                    ) else {
+                        continue;
+                    };
+
+                    let (element, count, runtime_array) = match module.ty(pointee)? {
+                        Type::Array { element, length } => (*element, module.constants.get(length).copied().unwrap_or(1), false),
+                        Type::RuntimeArray { element } => (*element, DEFAULT_RUNTIME_ARRAY_COUNT, true),
+                        _ => (pointee, 1, false),
+                    };
+
+                    // Leave the binding out instead of failing the whole shader
+                    let descriptor_type = match module.descriptor_type(element, storage) {
+                        Ok(ty) => ty,
+                        Err(e) => {
+                            warn!("Skipping reflection of set {} binding {}: {}", set, binding, e);
+                            continue;
+                        }
+                    };
+
+                    bindings.push(ReflectedBinding {
+                        set,
+                        binding,
+                        descriptor_type,
+                        count,
+                        stages: stage,
+                        runtime_array,
+                    });
+                }
+                STORAGE_PUSH_CONSTANT => {
+                    push_constant_offset = module.block_offset(pointee);
+                    push_constant_size = Some(module.size_of(pointee, None)? - push_constant_offset);
+                }
+                STORAGE_INPUT if stage == vk::ShaderStageFlags::VERTEX => {
+                    let Some(location) = decorations.filter(|d| !d.built_in).and_then(|d| d.location) else {
+                        continue;
+                    };
+
+                    match module.input_formats(pointee) {
+                        Ok(formats) => {
+                            for (i, (format, size)) in formats.into_iter().enumerate() {
+                                inputs.push(ReflectedInput { location: location + i as u32, format, size });
+                            }
+                        }
+                        Err(e) => warn!("Skipping reflection of vertex input at location {}: {}", location, e),
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        bindings.sort_by_key(|b| (b.set, b.binding));
+        inputs.sort_by_key(|i| i.location);
+
+        Ok(ShaderReflection {
+            stage,
+            entry_point,
+            bindings,
+            push_constant_offset,
+            push_constant_size,
+            inputs,
+        })
+    }
+
+    pub fn from_file<P: AsRef<Path>>(path: P) -> VulkanResult<Self> {
+        let code = load_spv(path)?;
+        Self::from_spv(&code).map_err(VulkanError::Reflection)
+    }
+}
+
+/// Resources of all stages of a pipeline merged together
+#[derive(Clone, Debug)]
+pub struct PipelineReflection {
+    sets: BTreeMap<u32, BTreeMap<u32, ReflectedBinding>>,
+    push_constants: Option<vk::PushConstantRange>,
+    inputs: Vec<ReflectedInput>,
+    runtime_array_count: u32,
+}
+
+impl PipelineReflection {
+
+    pub fn new(stages: &[ShaderReflection]) -> VulkanResult<Self> {
+
+        let mut sets: BTreeMap<u32, BTreeMap<u32, ReflectedBinding>> = BTreeMap::new();
+        let mut push_constants: Option<vk::PushConstantRange> = None;
+        let mut inputs = vec![];
+
+        for stage in stages {
+
+            for binding in &stage.bindings {
+                let set = sets.entry(binding.set).or_default();
+
+                match set.get_mut(&binding.binding) {
+                    Some(merged) if merged.descriptor_type != binding.descriptor_type => {
+                        return Err(VulkanError::Reflection(ReflectionError::BindingMismatch {
+                            set: binding.set,
+                            binding: binding.binding,
+                            first: merged.descriptor_type,
+                            second: binding.descriptor_type,
+                        }));
+                    }
+                    Some(merged) => {
+                        merged.stages |= binding.stages;
+                        merged.count = merged.count.max(binding.count);
+                        merged.runtime_array |= binding.runtime_array;
+                    }
+                    None => {
+                        set.insert(binding.binding, *binding);
+                    }
+                }
+            }
+
+            // One range visible to every stage that declares the block
+            if let Some(size) = stage.push_constant_size {
+                let offset = stage.push_constant_offset;
+
+                let range = push_constants.get_or_insert(vk::PushConstantRange { offset, ..Default::default() });
+                let end = (range.offset + range.size).max(offset + size);
+                range.offset = range.offset.min(offset);
+                range.size = end - range.offset;
+                range.stage_flags |= stage.stage;
+            }
+
+            if stage.stage == vk::ShaderStageFlags::VERTEX {
+                inputs = stage.inputs.clone();
+            }
+        }
+
+        debug!("Reflected sets: {:?}, push constants: {:?}", sets, push_constants);
+
+        Ok(Self {
+            sets,
+            push_constants,
+            inputs,
+            runtime_array_count: DEFAULT_RUNTIME_ARRAY_COUNT
+        })
+    }
+
+    pub fn from_files<P: AsRef<Path>>(paths: &[P]) -> VulkanResult<Self> {
+        let stages = paths.iter()
+            .map(ShaderReflection::from_file)
+            .collect::<VulkanResult<Vec<_>>>()?;
+
+        Self::new(&stages)
+    }
+
+    pub fn runtime_array_count(mut self, count: u32) -> Self {
+        self.runtime_array_count = count;
+        self
+    }
+
+    /// Number of set layouts the pipeline layout needs, unused sets in between included
+    pub fn set_count(&self) -> u32 {
+        self.sets.keys().next_back().map_or(0, |set| set + 1)
+    }
+
+    pub fn bindings(&self, set: u32) -> Vec<vk::DescriptorSetLayoutBinding<'static>> {
+        let Some(bindings) = self.sets.get(&set) else {
+            return vec![];
+        };
+
+        bindings.values()
+            .map(|b| {
+                vk::DescriptorSetLayoutBinding::default()
+                    .binding(b.binding)
+                    .descriptor_type(b.descriptor_type)
+                    .descriptor_count(if b.runtime_array { self.runtime_array_count } else { b.count })
+                    .stage_flags(b.stages)
+            })
+            .collect()
+    }
+
+    pub fn push_constant_ranges(&self) -> Vec<vk::PushConstantRange> {
+        self.push_constants.into_iter().collect()
+    }
+
+    /// Attributes of the vertex stage packed tightly in location order
+    pub fn vertex_attributes(&self, binding: u32) -> Vec<vk::VertexInputAttributeDescription> {
+        let mut offset = 0;
+
+        self.inputs.iter()
+            .map(|input| {
+                let attribute = vk::VertexInputAttributeDescription {
+                    location: input.location,
+                    binding,
+                    format: input.format,
+                    offset,
+                };
+                offset += input.size;
+                attribute
+            })
+            .collect()
+    }
+
+    pub fn vertex_stride(&self) -> u32 {
+        self.inputs.iter().map(|input| input.size).sum()
+    }
+
+    /// Check that `V` provides every attribute the vertex shader reads with the same format
+    pub fn check_vertex<V: AttributeDescriptions>(&self) -> VulkanResult<()> {
+        let attributes = V::attr_desc();
+
+        for input in &self.inputs {
+            let vertex = attributes.iter()
+                .find(|a| a.location == input.location)
+                .map(|a| a.format);
+
+            if vertex != Some(input.format) {
+                return Err(VulkanError::Reflection(ReflectionError::VertexInputMismatch {
+                    location: input.location,
+                    shader: input.format,
+                    vertex,
+                }));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Runtime arrays are partially bound when descriptor indexing is enabled
+    pub fn set_layout<'a>(&self, device: &'a Device, set: u32) -> DescriptorSetLayoutBuilder<'a> {
+        let builder = DescriptorSetLayoutBuilder::new(device).bindings(self.bindings(set));
+
+        let Some(bindings) = self.sets.get(&set) else {
+            return builder;
+        };
+
+        if !device.features().descriptor_indexing || !bindings.values().any(|b| b.runtime_array) {
+            return builder;
+        }
+
+        builder.binding_flags(
+            bindings.values()
+                .map(|b| match b.runtime_array {
+                    true => vk::DescriptorBindingFlags::PARTIALLY_BOUND,
+                    false => vk::DescriptorBindingFlags::empty(),
+                })
+                .collect()
+        )
+    }
+
+    pub fn pipeline_layout<'a>(&self, device: &'a Device, set_layouts: Vec<vk::DescriptorSetLayout>) -> PipelineLayoutBuilder<'a> {
+        PipelineLayoutBuilder::new(device)
+            .set_layouts(set_layouts)
+            .push_constant(self.push_constant_ranges())
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use ash::vk;
+
+    use super::*;
+
+    const SPV_DIR: &str = "src/shared/shaders/spv";
+
+    fn reflect(name: &str) -> ShaderReflection {
+        ShaderReflection::from_file(Path::new(SPV_DIR).join(name)).unwrap()
+    }
+
+    fn op(opcode: u32, operands: &[u32]) -> Vec<u32> {
+        let mut words = vec![((operands.len() as u32 + 1) << 16) | opcode];
+        words.extend_from_slice(operands);
+        words
+    }
+
+    /// Vertex shader with a double at location 0, a mat4 at location 2, an unsupported bool input at 6,
+    /// a runtime array of combined image samplers and a push constant block starting at offset 16
+    fn synthetic_vertex_module() -> Vec<u32> {
+        let main = u32::from_le_bytes(*b"main");
+
+        [
+            vec![MAGIC, 0x0001_0000, 0, 32, 0],
+            op(OP_ENTRY_POINT, &[0, 31, main, 0]),
+            op(OP_TYPE_FLOAT, &[1, 32]),
+            op(OP_TYPE_VECTOR, &[2, 1, 4]),
+            op(OP_TYPE_MATRIX, &[3, 2, 4]),
+            op(OP_TYPE_POINTER, &[4, STORAGE_INPUT, 3]),
+            op(OP_VARIABLE, &[4, 5, STORAGE_INPUT]),
+            op(OP_DECORATE, &[5, DECORATION_LOCATION, 2]),
+            op(OP_TYPE_STRUCT, &[6, 1]),
+            op(OP_DECORATE, &[6, DECORATION_BLOCK]),
+            op(OP_MEMBER_DECORATE, &[6, 0, DECORATION_OFFSET, 16]),
+            op(OP_TYPE_POINTER, &[7, STORAGE_PUSH_CONSTANT, 6]),
+            op(OP_VARIABLE, &[7, 8, STORAGE_PUSH_CONSTANT]),
+            op(OP_TYPE_IMAGE, &[9, 1, 1, 0, 0, 0, 1, 0]),
+            op(OP_TYPE_SAMPLED_IMAGE, &[10, 9]),
+            op(OP_TYPE_RUNTIME_ARRAY, &[11, 10]),
+            op(OP_TYPE_POINTER, &[12, STORAGE_UNIFORM_CONSTANT, 11]),
+            op(OP_VARIABLE, &[12, 13, STORAGE_UNIFORM_CONSTANT]),
+            op(OP_DECORATE, &[13, DECORATION_DESCRIPTOR_SET, 0]),
+            op(OP_DECORATE, &[13, DECORATION_BINDING, 0]),
+            op(OP_TYPE_FLOAT, &[14, 64]),
+            op(OP_TYPE_POINTER, &[15, STORAGE_INPUT, 14]),
+            op(OP_VARIABLE, &[15, 16, STORAGE_INPUT]),
+            op(OP_DECORATE, &[16, DECORATION_LOCATION, 0]),
+            op(OP_TYPE_BOOL, &[17]),
+            op(OP_TYPE_POINTER, &[18, STORAGE_INPUT, 17]),
+            op(OP_VARIABLE, &[18, 19, STORAGE_INPUT]),
+            op(OP_DECORATE, &[19, DECORATION_LOCATION, 6]),
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn every_checked_in_shader_reflects() {
+        for entry in std::fs::read_dir(SPV_DIR).unwrap() {
+            let path = entry.unwrap().path();
+            let reflection = ShaderReflection::from_file(&path).unwrap();
+            assert_eq!(reflection.entry_point, "main", "{:?}", path);
+        }
+    }
+
+    #[test]
+    fn bindless_vertex_shader() {
+        let reflection = reflect("pbr_bindless-vert.spv");
+
+        assert_eq!(reflection.stage, vk::ShaderStageFlags::VERTEX);
+        assert_eq!(reflection.push_constant_offset, 0);
+        assert_eq!(reflection.push_constant_size, Some(16));
+
+        let bindings = reflection.bindings.iter()
+            .map(|b| (b.set, b.binding, b.descriptor_type, b.count))
+            .collect::<Vec<_>>();
+
+        assert_eq!(bindings, [
+            (0, 0, vk::DescriptorType::COMBINED_IMAGE_SAMPLER, 512),
+            (0, 1, vk::DescriptorType::STORAGE_IMAGE, 128),
+            (0, 2, vk::DescriptorType::UNIFORM_BUFFER, 2048),
+            (0, 3, vk::DescriptorType::UNIFORM_BUFFER, 1),
+            (0, 4, vk::DescriptorType::STORAGE_BUFFER, 256),
+        ]);
+
+        let locations = reflection.inputs.iter().map(|i| (i.location, i.format)).collect::<Vec<_>>();
+        assert_eq!(locations, [
+            (0, vk::Format::R32G32B32A32_SFLOAT),
+            (1, vk::Format::R32G32B32A32_SFLOAT),
+            (2, vk::Format::R32G32_SFLOAT),
+            (3, vk::Format::R32G32B32A32_SFLOAT),
+            (4, vk::Format::R32G32B32A32_SFLOAT),
+        ]);
+    }
+
+    #[test]
+    fn stages_are_merged() {
+        let pipeline = PipelineReflection::new(&[reflect("pbr_bindless-vert.spv"), reflect("pbr_bindless-frag.spv")]).unwrap();
+
+        assert_eq!(pipeline.set_count(), 1);
+        assert!(pipeline.bindings(0).iter().all(|b| b.stage_flags == vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT));
+
+        let ranges = pipeline.push_constant_ranges();
+        assert_eq!(ranges.len(), 1);
+        assert_eq!((ranges[0].offset, ranges[0].size), (0, 16));
+        assert_eq!(ranges[0].stage_flags, vk::ShaderStageFlags::VERTEX | vk::ShaderStageFlags::FRAGMENT);
+    }
+
+    #[test]
+    fn unused_sets_are_counted() {
+        let pipeline = PipelineReflection::new(&[reflect("fullscreen_quad-vert.spv"), reflect("fullscreen_quad-frag.spv")]).unwrap();
+
+        assert_eq!(pipeline.set_count(), 2);
+        assert!(pipeline.bindings(0).is_empty());
+        assert_eq!(pipeline.bindings(1).len(), 3);
+    }
+
+    #[test]
+    fn vertex_attributes_are_packed() {
+        let pipeline = PipelineReflection::new(&[reflect("texture-vert.spv")]).unwrap();
+
+        let attributes = pipeline.vertex_attributes(0).iter()
+            .map(|a| (a.location, a.format, a.offset))
+            .collect::<Vec<_>>();
+
+        assert_eq!(attributes, [
+            (0, vk::Format::R32G32_SFLOAT, 0),
+            (1, vk::Format::R32G32B32_SFLOAT, 8),
+        ]);
+        assert_eq!(pipeline.vertex_stride(), 20);
+    }
+
+    #[test]
+    fn matrix_and_double_inputs() {
+        let reflection = ShaderReflection::from_spv(&synthetic_vertex_module()).unwrap();
+
+        let locations = reflection.inputs.iter().map(|i| (i.location, i.format, i.size)).collect::<Vec<_>>();
+
+        // The bool input is skipped instead of failing the shader
+        assert_eq!(locations, [
+            (0, vk::Format::R64_SFLOAT, 8),
+            (2, vk::Format::R32G32B32A32_SFLOAT, 16),
+            (3, vk::Format::R32G32B32A32_SFLOAT, 16),
+            (4, vk::Format::R32G32B32A32_SFLOAT, 16),
+            (5, vk::Format::R32G32B32A32_SFLOAT, 16),
+        ]);
+    }
+
+    #[test]
+    fn push_constant_offset() {
+        let reflection = ShaderReflection::from_spv(&synthetic_vertex_module()).unwrap();
+        assert_eq!(reflection.push_constant_offset, 16);
+        assert_eq!(reflection.push_constant_size, Some(4));
+
+        let pipeline = PipelineReflection::new(&[reflection]).unwrap();
+        let range = pipeline.push_constant_ranges()[0];
+        assert_eq!((range.offset, range.size), (16, 4));
+    }
+
+    #[test]
+    fn runtime_arrays_get_a_count() {
+        let reflection = ShaderReflection::from_spv(&synthetic_vertex_module()).unwrap();
+
+        assert!(reflection.bindings[0].runtime_array);
+        assert_eq!(reflection.bindings[0].count, DEFAULT_RUNTIME_ARRAY_COUNT);
+
+        let pipeline = PipelineReflection::new(&[reflection]).unwrap().runtime_array_count(64);
+        assert_eq!(pipeline.bindings(0)[0].descriptor_count, 64);
+    }
+
+    #[test]
+    fn malformed_modules_are_rejected() {
+        assert!(ShaderReflection::from_spv(&[MAGIC, 0, 0]).is_err());
+
+        let mut words = synthetic_vertex_module();
+        words.truncate(words.len() - 1);
+        assert!(ShaderReflection::from_spv(&words).is_err());
+
+        // No OpEntryPoint
+        assert!(ShaderReflection::from_spv(&[MAGIC, 0x0001_0000, 0, 1, 0]).is_err());
+    }
+}
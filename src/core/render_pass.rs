@@ -1,22 +1,22 @@
-use crate::{Subpass, SubpassDesc, VulkanResult};
+use crate::{RenderPassError, Subpass, SubpassDesc, VulkanError, VulkanResult};
 use ash::vk;
+use crate::core::{Deferred, DeletionQueue};
 use log::debug;
 use crate::core::device::Device;
 
 pub struct RenderPass {
     pub(crate) raw: vk::RenderPass,
+    deletion: DeletionQueue,
 }
 
-impl RenderPass {
-    pub fn destroy(&self, device: &Device) {
-        unsafe {
-            device.destroy_render_pass(self.raw, None);
-        }
+impl Drop for RenderPass {
+    fn drop(&mut self) {
+        self.deletion.push(Deferred::RenderPass(self.raw));
     }
 }
 
 pub struct RenderPassBuilder<'a> {
-    pub device: &'a ash::Device,
+    pub device: &'a Device,
     pub attachments: Option<Vec<vk::AttachmentDescription>>,
     pub dependencies: Option<Vec<vk::SubpassDependency>>,
     pub subpasses: Option<Vec<Subpass>>,
@@ -24,7 +24,7 @@ pub struct RenderPassBuilder<'a> {
 
 impl<'a> RenderPassBuilder<'a> {
 
-    pub fn default(device: &'a ash::Device, color: vk::Format, depth: vk::Format) -> Self {
+    pub fn default(device: &'a Device, color: vk::Format, depth: vk::Format) -> Self {
 
         let subpass = Subpass::new(
             SubpassDesc::empty()
@@ -83,7 +83,12 @@ impl<'a> RenderPassBuilder<'a> {
     pub fn build(self) -> VulkanResult<RenderPass> {
 
         let device = self.device;
-        let raw_subpasses = self.subpasses.unwrap().iter().map(|x| x.raw).collect::<Vec<_>>();
+        let raw_subpasses = self.subpasses
+            .filter(|x| !x.is_empty())
+            .ok_or(VulkanError::RenderPass(RenderPassError::MissingSubpasses))?
+            .iter()
+            .map(|x| x.raw)
+            .collect::<Vec<_>>();
         let binding1 = self.attachments.unwrap_or(vec![]);
         let binding2 = self.dependencies.unwrap_or(vec![]);
         
@@ -93,9 +98,14 @@ impl<'a> RenderPassBuilder<'a> {
             .subpasses(&raw_subpasses);
 
         debug!("Render Pass: {:?}", create_info);
-        let render_pass = unsafe { device.create_render_pass(&create_info, None).unwrap() };
+        let render_pass = unsafe { device.create_render_pass(&create_info, None).map_err(|e| {
+            VulkanError::RenderPass(RenderPassError::CreateRenderPass(e))
+        })}?;
 
-        Ok(RenderPass { raw: render_pass })
+        Ok(RenderPass {
+            raw: render_pass,
+            deletion: device.register(render_pass)
+        })
     }
 }
 
@@ -0,0 +1,104 @@
+use ash::vk;
+
+use crate::Device;
+
+impl Device {
+
+    /// `vkCmdBeginRendering` from Vulkan 1.3 or `VK_KHR_dynamic_rendering`
+    pub fn begin_rendering(&self, cmd: vk::CommandBuffer, info: &vk::RenderingInfo) {
+        debug_assert!(self.features.dynamic_rendering, "Dynamic rendering is not enabled");
+
+        unsafe {
+            match &self.dynamic_rendering_ext {
+                Some(ext) => ext.cmd_begin_rendering(cmd, info),
+                None => self.raw.cmd_begin_rendering(cmd, info),
+            }
+        }
+    }
+
+    pub fn end_rendering(&self, cmd: vk::CommandBuffer) {
+        unsafe {
+            match &self.dynamic_rendering_ext {
+                Some(ext) => ext.cmd_end_rendering(cmd),
+                None => self.raw.cmd_end_rendering(cmd),
+            }
+        }
+    }
+}
+
+/// Attachments of a dynamic rendering scope, replaces a render pass and frame buffer pair
+pub struct RenderingBuilder<'a> {
+    device: &'a Device,
+    area: vk::Rect2D,
+    layer_count: u32,
+    color_attachments: Vec<vk::RenderingAttachmentInfo<'static>>,
+    depth_attachment: Option<vk::RenderingAttachmentInfo<'static>>,
+}
+
+impl<'a> RenderingBuilder<'a> {
+
+    pub fn new(device: &'a Device, extent: vk::Extent2D) -> Self {
+        Self {
+            device,
+            area: vk::Rect2D {
+                offset: vk::Offset2D { x: 0, y: 0 },
+                extent
+            },
+            layer_count: 1,
+            color_attachments: vec![],
+            depth_attachment: None
+        }
+    }
+
+    pub fn layers(mut self, count: u32) -> Self {
+        self.layer_count = count;
+        self
+    }
+
+    /// Color attachment in `COLOR_ATTACHMENT_OPTIMAL`, cleared on load when `clear` is set
+    pub fn color_attachment(mut self, view: vk::ImageView, clear: Option<[f32; 4]>) -> Self {
+
+        let attachment = vk::RenderingAttachmentInfo::default()
+            .image_view(view)
+            .image_layout(vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+            .load_op(if clear.is_some() { vk::AttachmentLoadOp::CLEAR } else { vk::AttachmentLoadOp::LOAD })
+            .store_op(vk::AttachmentStoreOp::STORE)
+            .clear_value(vk::ClearValue {
+                color: vk::ClearColorValue { float32: clear.unwrap_or_default() }
+            });
+
+        self.color_attachments.push(attachment);
+        self
+    }
+
+    /// Depth attachment in `DEPTH_STENCIL_ATTACHMENT_OPTIMAL`, which needs no separateDepthStencilLayouts, cleared to `clear` and not stored
+    pub fn depth_attachment(mut self, view: vk::ImageView, clear: f32) -> Self {
+
+        let attachment = vk::RenderingAttachmentInfo::default()
+            .image_view(view)
+            .image_layout(vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL)
+            .load_op(vk::AttachmentLoadOp::CLEAR)
+            .store_op(vk::AttachmentStoreOp::DONT_CARE)
+            .clear_value(vk::ClearValue {
+                depth_stencil: vk::ClearDepthStencilValue { depth: clear, stencil: 0 }
+            });
+
+        self.depth_attachment = Some(attachment);
+        self
+    }
+
+    /// Record `vkCmdBeginRendering`, close the scope with [`Device::end_rendering`]
+    pub fn begin(self, cmd: vk::CommandBuffer) {
+
+        let mut info = vk::RenderingInfo::default()
+            .render_area(self.area)
+            .layer_count(self.layer_count)
+            .color_attachments(&self.color_attachments);
+
+        if let Some(depth) = &self.depth_attachment {
+            info = info.depth_attachment(depth);
+        }
+
+        self.device.begin_rendering(cmd, &info);
+    }
+}
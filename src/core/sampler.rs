@@ -1,34 +1,271 @@
 
 
+use std::hash::{Hash, Hasher};
+
 use ash::vk;
+use crate::core::{Deferred, DeletionQueue};
 
-use crate::{Device, VulkanError, VulkanResult};
+use crate::{Device, SamplerError, VulkanError, VulkanResult};
 
 pub struct Sampler {
-    pub raw: vk::Sampler
+    pub raw: vk::Sampler,
+    deletion: DeletionQueue,
+}
+
+impl Drop for Sampler {
+    fn drop(&mut self) {
+        self.deletion.push(Deferred::Sampler(self.raw));
+    }
+}
+
+/// Owned description of a [`vk::Sampler`], usable as a cache key
+#[derive(Clone, Copy, Debug)]
+pub struct SamplerDesc {
+    pub mag_filter: vk::Filter,
+    pub min_filter: vk::Filter,
+    pub mipmap_mode: vk::SamplerMipmapMode,
+    pub address_mode_u: vk::SamplerAddressMode,
+    pub address_mode_v: vk::SamplerAddressMode,
+    pub address_mode_w: vk::SamplerAddressMode,
+    pub mip_lod_bias: f32,
+    pub max_anisotropy: Option<f32>,
+    pub compare_op: Option<vk::CompareOp>,
+    pub min_lod: f32,
+    pub max_lod: f32,
+    pub border_color: vk::BorderColor,
+    pub unnormalized_coordinates: bool,
+}
+
+impl Default for SamplerDesc {
+    fn default() -> Self {
+        Self {
+            mag_filter: vk::Filter::LINEAR,
+            min_filter: vk::Filter::LINEAR,
+            mipmap_mode: vk::SamplerMipmapMode::LINEAR,
+            address_mode_u: vk::SamplerAddressMode::REPEAT,
+            address_mode_v: vk::SamplerAddressMode::REPEAT,
+            address_mode_w: vk::SamplerAddressMode::REPEAT,
+            mip_lod_bias: 0.0,
+            max_anisotropy: None,
+            compare_op: None,
+            min_lod: 0.0,
+            max_lod: vk::LOD_CLAMP_NONE,
+            border_color: vk::BorderColor::FLOAT_TRANSPARENT_BLACK,
+            unnormalized_coordinates: false,
+        }
+    }
+}
+
+impl SamplerDesc {
+
+    pub fn linear() -> Self {
+        Self::default()
+    }
+
+    pub fn nearest() -> Self {
+        Self {
+            mag_filter: vk::Filter::NEAREST,
+            min_filter: vk::Filter::NEAREST,
+            mipmap_mode: vk::SamplerMipmapMode::NEAREST,
+            ..Default::default()
+        }
+    }
+
+    /// Depth comparison sampler for shadow maps
+    pub fn shadow() -> Self {
+        Self {
+            compare_op: Some(vk::CompareOp::LESS_OR_EQUAL),
+            border_color: vk::BorderColor::FLOAT_OPAQUE_WHITE,
+            ..Self::default().address_mode(vk::SamplerAddressMode::CLAMP_TO_BORDER)
+        }
+    }
+
+    pub fn address_mode(mut self, mode: vk::SamplerAddressMode) -> Self {
+        self.address_mode_u = mode;
+        self.address_mode_v = mode;
+        self.address_mode_w = mode;
+        self
+    }
+
+    pub(crate) fn create_info(&self) -> vk::SamplerCreateInfo<'static> {
+        vk::SamplerCreateInfo::default()
+            .mag_filter(self.mag_filter)
+            .min_filter(self.min_filter)
+            .mipmap_mode(self.mipmap_mode)
+            .address_mode_u(self.address_mode_u)
+            .address_mode_v(self.address_mode_v)
+            .address_mode_w(self.address_mode_w)
+            .mip_lod_bias(self.mip_lod_bias)
+            .anisotropy_enable(self.max_anisotropy.is_some())
+            .max_anisotropy(self.max_anisotropy.unwrap_or(1.0))
+            .compare_enable(self.compare_op.is_some())
+            .compare_op(self.compare_op.unwrap_or(vk::CompareOp::NEVER))
+            .min_lod(self.min_lod)
+            .max_lod(self.max_lod)
+            .border_color(self.border_color)
+            .unnormalized_coordinates(self.unnormalized_coordinates)
+    }
+}
+
+// Floats are compared by bits so identical descriptions always hash the same
+impl PartialEq for SamplerDesc {
+    fn eq(&self, other: &Self) -> bool {
+        self.mag_filter == other.mag_filter
+            && self.min_filter == other.min_filter
+            && self.mipmap_mode == other.mipmap_mode
+            && self.address_mode_u == other.address_mode_u
+            && self.address_mode_v == other.address_mode_v
+            && self.address_mode_w == other.address_mode_w
+            && self.mip_lod_bias.to_bits() == other.mip_lod_bias.to_bits()
+            && self.max_anisotropy.map(f32::to_bits) == other.max_anisotropy.map(f32::to_bits)
+            && self.compare_op == other.compare_op
+            && self.min_lod.to_bits() == other.min_lod.to_bits()
+            && self.max_lod.to_bits() == other.max_lod.to_bits()
+            && self.border_color == other.border_color
+            && self.unnormalized_coordinates == other.unnormalized_coordinates
+    }
+}
+
+impl Eq for SamplerDesc {}
+
+impl Hash for SamplerDesc {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.mag_filter.hash(state);
+        self.min_filter.hash(state);
+        self.mipmap_mode.hash(state);
+        self.address_mode_u.hash(state);
+        self.address_mode_v.hash(state);
+        self.address_mode_w.hash(state);
+        self.mip_lod_bias.to_bits().hash(state);
+        self.max_anisotropy.map(f32::to_bits).hash(state);
+        self.compare_op.hash(state);
+        self.min_lod.to_bits().hash(state);
+        self.max_lod.to_bits().hash(state);
+        self.border_color.hash(state);
+        self.unnormalized_coordinates.hash(state);
+    }
 }
 
 pub struct SamplerBuilder<'a> {
-    sampler_info: vk::SamplerCreateInfo<'static>,
+    desc: SamplerDesc,
     device: &'a Device
 }
 
 impl<'a> SamplerBuilder<'a> {
 
+    /// Starts from `SamplerDesc::default`: linear filtering, repeat, every mip level
     pub fn default(device: &'a Device) -> Self {
         Self {
-            sampler_info: vk::SamplerCreateInfo::default(),
+            desc: SamplerDesc::default(),
             device
         }
     }
 
+    pub fn from_desc(device: &'a Device, desc: SamplerDesc) -> Self {
+        Self { desc, device }
+    }
+
+    pub fn filter(mut self, mag: vk::Filter, min: vk::Filter) -> Self {
+        self.desc.mag_filter = mag;
+        self.desc.min_filter = min;
+        self
+    }
+
+    pub fn mipmap_mode(mut self, mode: vk::SamplerMipmapMode) -> Self {
+        self.desc.mipmap_mode = mode;
+        self
+    }
+
+    pub fn address_mode(mut self, mode: vk::SamplerAddressMode) -> Self {
+        self.desc = self.desc.address_mode(mode);
+        self
+    }
+
+    pub fn address_mode_uvw(mut self, u: vk::SamplerAddressMode, v: vk::SamplerAddressMode, w: vk::SamplerAddressMode) -> Self {
+        self.desc.address_mode_u = u;
+        self.desc.address_mode_v = v;
+        self.desc.address_mode_w = w;
+        self
+    }
+
+    pub fn anisotropy(mut self, max_anisotropy: f32) -> Self {
+        self.desc.max_anisotropy = Some(max_anisotropy);
+        self
+    }
+
+    pub fn lod(mut self, min_lod: f32, max_lod: f32, bias: f32) -> Self {
+        self.desc.min_lod = min_lod;
+        self.desc.max_lod = max_lod;
+        self.desc.mip_lod_bias = bias;
+        self
+    }
+
+    pub fn border_color(mut self, color: vk::BorderColor) -> Self {
+        self.desc.border_color = color;
+        self
+    }
+
+    pub fn compare_op(mut self, op: vk::CompareOp) -> Self {
+        self.desc.compare_op = Some(op);
+        self
+    }
+
+    pub fn unnormalized_coordinates(mut self, enable: bool) -> Self {
+        self.desc.unnormalized_coordinates = enable;
+        self
+    }
+
     pub fn build(self) -> VulkanResult<Sampler> {
 
+        let sampler_info = self.desc.create_info();
+
         let sampler = unsafe {
-            self.device.create_sampler(&self.sampler_info, None)
-                .map_err(|e| VulkanError::Unknown(e))?
+            self.device.create_sampler(&sampler_info, None)
+                .map_err(|e| VulkanError::Sampler(SamplerError::SamplerCreationFailed(e)))?
         };
 
-        Ok(Sampler { raw: sampler })
+        Ok(Sampler {
+            raw: sampler,
+            deletion: self.device.register(sampler)
+        })
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::hash_map::DefaultHasher;
+    use super::*;
+
+    fn hash(desc: &SamplerDesc) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        desc.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn equal_descriptions_share_a_key() {
+        let a = SamplerDesc::nearest().address_mode(vk::SamplerAddressMode::CLAMP_TO_EDGE);
+        let b = SamplerDesc::nearest().address_mode(vk::SamplerAddressMode::CLAMP_TO_EDGE);
+
+        assert_eq!(a, b);
+        assert_eq!(hash(&a), hash(&b));
+    }
+
+    #[test]
+    fn different_descriptions_differ() {
+        let base = SamplerDesc::nearest();
+
+        let variants = [
+            SamplerDesc::linear(),
+            base.address_mode(vk::SamplerAddressMode::CLAMP_TO_EDGE),
+            SamplerDesc { max_lod: 0.0, ..base },
+            SamplerDesc { max_anisotropy: Some(16.0), ..base },
+            SamplerDesc { compare_op: Some(vk::CompareOp::LESS), ..base },
+        ];
+
+        for variant in &variants {
+            assert_ne!(&base, variant);
+            assert_ne!(hash(&base), hash(variant));
+        }
+    }
+}
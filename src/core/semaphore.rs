@@ -1,10 +1,18 @@
 
 use ash::vk;
+use crate::core::{Deferred, DeletionQueue};
 
-use crate::{Device, VulkanError, VulkanResult};
+use crate::{Device, SyncError, VulkanError, VulkanResult};
 
 pub struct Semaphore {
     pub(crate) raw: vk::Semaphore,
+    deletion: DeletionQueue,
+}
+
+impl Drop for Semaphore {
+    fn drop(&mut self) {
+        self.deletion.push(Deferred::Semaphore(self.raw));
+    }
 }
 
 pub struct SemaphoreBuilder<'a> {
@@ -21,12 +29,106 @@ impl<'a> SemaphoreBuilder<'a> {
 
         let sem = unsafe {
             self.device.create_semaphore(&self.create_info, None).map_err(|e| {
-                VulkanError::Unknown(e)
+                VulkanError::Sync(SyncError::SemaphoreCreationFailed(e))
             })
         }?;
 
-        Ok(Semaphore {  
-            raw: sem
+        Ok(Semaphore {
+            raw: sem,
+            deletion: self.device.register(sem)
+        })
+    }
+}
+
+/// Semaphore with a monotonic 64-bit counter, Vulkan 1.2 core
+pub struct TimelineSemaphore {
+    pub(crate) raw: vk::Semaphore,
+    deletion: DeletionQueue,
+}
+
+impl Drop for TimelineSemaphore {
+    fn drop(&mut self) {
+        self.deletion.push(Deferred::Semaphore(self.raw));
+    }
+}
+
+impl TimelineSemaphore {
+
+    pub fn value(&self, device: &Device) -> VulkanResult<u64> {
+        unsafe {
+            device.get_semaphore_counter_value(self.raw)
+                .map_err(|e| VulkanError::Sync(SyncError::GetCounterValueFailed(e)))
+        }
+    }
+
+    /// Block until the counter reaches `value`, `false` on timeout
+    pub fn wait(&self, device: &Device, value: u64, timeout: u64) -> VulkanResult<bool> {
+        puffin::profile_scope!("TimelineSemaphore::wait");
+
+        let semaphores = [self.raw];
+        let values = [value];
+
+        let wait_info = vk::SemaphoreWaitInfo::default()
+            .semaphores(&semaphores)
+            .values(&values);
+
+        match unsafe { device.wait_semaphores(&wait_info, timeout) } {
+            Ok(()) => Ok(true),
+            Err(vk::Result::TIMEOUT) => Ok(false),
+            Err(e) => Err(VulkanError::Sync(SyncError::WaitFailed(e))),
+        }
+    }
+
+    /// Set the counter from the host, `value` must be greater than the current one
+    pub fn signal(&self, device: &Device, value: u64) -> VulkanResult<()> {
+
+        let signal_info = vk::SemaphoreSignalInfo::default()
+            .semaphore(self.raw)
+            .value(value);
+
+        unsafe {
+            device.signal_semaphore(&signal_info)
+                .map_err(|e| VulkanError::Sync(SyncError::SignalFailed(e)))
+        }
+    }
+}
+
+pub struct TimelineSemaphoreBuilder<'a> {
+    device: &'a Device,
+    initial_value: u64,
+}
+
+impl<'a> TimelineSemaphoreBuilder<'a> {
+    pub fn new(device: &'a Device) -> Self {
+        Self { device, initial_value: 0 }
+    }
+
+    pub fn initial_value(mut self, value: u64) -> Self {
+        self.initial_value = value;
+        self
+    }
+
+    pub fn build(self) -> VulkanResult<TimelineSemaphore> {
+
+        if !self.device.features().timeline_semaphore {
+            return Err(VulkanError::Sync(SyncError::TimelineSemaphoreNotSupported));
+        }
+
+        let mut type_info = vk::SemaphoreTypeCreateInfo::default()
+            .semaphore_type(vk::SemaphoreType::TIMELINE)
+            .initial_value(self.initial_value);
+
+        let create_info = vk::SemaphoreCreateInfo::default()
+            .push_next(&mut type_info);
+
+        let sem = unsafe {
+            self.device.create_semaphore(&create_info, None)
+                .map_err(|e| VulkanError::Sync(SyncError::SemaphoreCreationFailed(e)))
+        }?;
+
+        Ok(TimelineSemaphore {
+            raw: sem,
+            deletion: self.device.register(sem)
         })
     }
-}
\ No newline at end of file
+}
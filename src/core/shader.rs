@@ -1,9 +1,25 @@
-use std::{error::Error, io::Read, path::Path};
-use crate::{VulkanError, VulkanResult, core::device::Device};
+use std::path::Path;
+use crate::{ShaderError, ShaderReflection, VulkanError, VulkanResult, core::device::Device};
 use ash::vk;
+use log::warn;
+use crate::core::{Deferred, DeletionQueue};
 
 pub struct ShaderModule {
-    pub(crate) raw: vk::ShaderModule
+    pub(crate) raw: vk::ShaderModule,
+    reflection: ShaderReflection,
+    deletion: DeletionQueue,
+}
+
+impl ShaderModule {
+    pub fn reflection(&self) -> &ShaderReflection {
+        &self.reflection
+    }
+}
+
+impl Drop for ShaderModule {
+    fn drop(&mut self) {
+        self.deletion.push(Deferred::ShaderModule(self.raw));
+    }
 }
 
 pub struct ShaderBuilder<'a, S: AsRef<Path>> {
@@ -16,34 +32,51 @@ impl<'a, S: AsRef<Path>> ShaderBuilder<'a, S> {
         
         puffin::profile_scope!("vkShaderModule");
 
-        let code = load_spv(path.as_ref());
+        let code = load_spv(path.as_ref())?;
+        // Reflection only fills in layouts, the module itself is still valid
+        let reflection = ShaderReflection::from_spv(&code).unwrap_or_else(|e| {
+            warn!("Failed to reflect {:?}: {}", path.as_ref(), e);
+            ShaderReflection::default()
+        });
         let create_info = vk::ShaderModuleCreateInfo::default()
             .code(&code);
 
         let shader = unsafe { 
             device.create_shader_module(&create_info, None).map_err(|e| {
-                VulkanError::Unknown(e)
+                VulkanError::Shader(ShaderError::ShaderCreationFailed(e))
             })?
         };
 
-        Ok(ShaderModule { raw: shader })
+        Ok(ShaderModule {
+            raw: shader,
+            reflection,
+            deletion: device.register(shader)
+        })
     }
 }
 
-pub(crate) fn read_shader_from_bytes(bytes: &[u8]) -> Result<Vec<u32>, Box<dyn Error>> {
+pub(crate) fn read_shader_from_bytes(bytes: &[u8]) -> std::io::Result<Vec<u32>> {
     let mut cursor = std::io::Cursor::new(bytes);
-    Ok(ash::util::read_spv(&mut cursor)?)
+    ash::util::read_spv(&mut cursor)
 }
 
-pub(crate) fn load_spv<T: AsRef<Path>>(path: T) -> Vec<u32> {
+pub(crate) fn load_spv<T: AsRef<Path>>(path: T) -> VulkanResult<Vec<u32>> {
+
+    let path = path.as_ref();
+    let invalid = |reason: &str| VulkanError::Shader(ShaderError::InvalidSpirv(path.to_path_buf(), reason.to_string()));
 
-    let mut file = std::fs::File::open(path).unwrap();
-    let mut text = Vec::new();
-    file.read_to_end(&mut text).unwrap();
+    let text = std::fs::read(path).map_err(|e| {
+        VulkanError::Shader(ShaderError::Io(path.to_path_buf(), e))
+    })?;
 
-    assert_eq!(text.len() % 4, 0);
-    assert_eq!(0x07230203, u32::from_le_bytes([text[0], text[1], text[2], text[3]]));
+    if text.len() < 4 || text.len() % 4 != 0 {
+        return Err(invalid("size is not a multiple of 4 bytes"));
+    }
+
+    if u32::from_le_bytes([text[0], text[1], text[2], text[3]]) != 0x07230203 {
+        return Err(invalid("wrong magic number"));
+    }
 
-    read_shader_from_bytes(&text).unwrap()
+    read_shader_from_bytes(&text).map_err(|e| invalid(&e.to_string()))
 }
 
@@ -15,7 +15,7 @@ impl Subpass {
         let input_attachments = desc.input_attachments.into_boxed_slice();
 
         let mut raw = vk::SubpassDescription::default();
-        raw.pipeline_bind_point = desc.bind_point.unwrap();
+        raw.pipeline_bind_point = desc.bind_point.unwrap_or(vk::PipelineBindPoint::GRAPHICS);
         raw.flags = desc.flags.unwrap_or(vk::SubpassDescriptionFlags::empty());
 
         if !color_attachments.is_empty() {
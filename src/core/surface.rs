@@ -1,7 +1,7 @@
 use ash::vk;
 use winit::raw_window_handle::{HasRawDisplayHandle, HasRawWindowHandle};
 
-use crate::{App, Instance, VulkanError, VulkanResult};
+use crate::{App, Instance, SurfaceError, VulkanError, VulkanResult};
 
 pub struct Surface {
     pub(crate) raw: vk::SurfaceKHR,
@@ -30,11 +30,15 @@ impl<'a> SurfaceBuilder<'a> {
             ash_window::create_surface(
                 &self.app.entry,
                 &self.instance.raw,
-                self.window.raw_display_handle().unwrap(),
-                self.window.raw_window_handle().unwrap(),
+                self.window.raw_display_handle().map_err(|e| {
+                    VulkanError::Surface(SurfaceError::WindowHandle(e.to_string()))
+                })?,
+                self.window.raw_window_handle().map_err(|e| {
+                    VulkanError::Surface(SurfaceError::WindowHandle(e.to_string()))
+                })?,
                 None,
             )
-            .map_err(|e| VulkanError::Unknown(e))?
+            .map_err(|e| VulkanError::Surface(SurfaceError::CreateSurface(e)))?
         };
 
         let loader = ash::khr::surface::Instance::new(&self.app.entry, &self.instance.raw);
@@ -43,31 +47,57 @@ impl<'a> SurfaceBuilder<'a> {
     }
 }
 
-impl Surface {
-
-    pub fn destroy(&self) {
+/// Must be dropped after the swapchain and before the [`Instance`]
+impl Drop for Surface {
+    fn drop(&mut self) {
         unsafe { self.loader.destroy_surface(self.raw, None) };
     }
+}
+
+impl Surface {
 
     pub fn get_physical_device_surface_capabilities(
         &self,
         phys_dev: &vk::PhysicalDevice,
-    ) -> vk::SurfaceCapabilitiesKHR {
+    ) -> VulkanResult<vk::SurfaceCapabilitiesKHR> {
         unsafe {
             self.loader
                 .get_physical_device_surface_capabilities(*phys_dev, self.raw)
-                .unwrap()
+                .map_err(|e| VulkanError::Surface(SurfaceError::GetSurfaceCapabilities(e)))
+        }
+    }
+
+    pub fn get_physical_device_surface_support(
+        &self,
+        phys_dev: &vk::PhysicalDevice,
+        queue_family_index: u32,
+    ) -> VulkanResult<bool> {
+        unsafe {
+            self.loader
+                .get_physical_device_surface_support(*phys_dev, queue_family_index, self.raw)
+                .map_err(|e| VulkanError::Surface(SurfaceError::GetSurfaceSupport(e)))
         }
     }
 
     pub fn get_physical_device_surface_formats(
         &self,
         phys_dev: &vk::PhysicalDevice,
-    ) -> Vec<vk::SurfaceFormatKHR> {
+    ) -> VulkanResult<Vec<vk::SurfaceFormatKHR>> {
         unsafe {
             self.loader
                 .get_physical_device_surface_formats(*phys_dev, self.raw)
-                .unwrap()
+                .map_err(|e| VulkanError::Surface(SurfaceError::GetSurfaceFormats(e)))
+        }
+    }
+
+    pub fn get_physical_device_surface_present_modes(
+        &self,
+        phys_dev: &vk::PhysicalDevice,
+    ) -> VulkanResult<Vec<vk::PresentModeKHR>> {
+        unsafe {
+            self.loader
+                .get_physical_device_surface_present_modes(*phys_dev, self.raw)
+                .map_err(|e| VulkanError::Surface(SurfaceError::GetPresentModes(e)))
         }
     }
 }
@@ -1,10 +1,121 @@
 use ash::vk;
 
-use crate::{Device, Instance, Surface, VulkanError, VulkanResult, debug};
+use crate::{Device, Instance, Surface, SwapchainError, VulkanError, VulkanResult, core::{Deferred, DeletionQueue}, debug};
 
 pub struct Swapchain {
     pub(crate) raw: vk::SwapchainKHR,
     pub(crate) loader: ash::khr::swapchain::Device,
+    format: vk::SurfaceFormatKHR,
+    present_mode: vk::PresentModeKHR,
+    extent: vk::Extent2D,
+    deletion: DeletionQueue,
+}
+
+impl Drop for Swapchain {
+    fn drop(&mut self) {
+        self.deletion.push(Deferred::Swapchain(self.raw, self.loader.clone()));
+    }
+}
+
+/// Preferences the swapchain is negotiated against, the first supported entry of each list wins
+#[derive(Clone, Debug)]
+pub struct SwapchainConfig {
+    /// FIFO when set, otherwise the first supported of `present_modes`
+    pub vsync: bool,
+    pub present_modes: Vec<vk::PresentModeKHR>,
+    pub formats: Vec<vk::SurfaceFormatKHR>,
+    /// Clamped to the surface capabilities
+    pub image_count: u32,
+}
+
+impl Default for SwapchainConfig {
+    fn default() -> Self {
+        Self {
+            vsync: true,
+            present_modes: vec![
+                vk::PresentModeKHR::MAILBOX,
+                vk::PresentModeKHR::IMMEDIATE,
+                vk::PresentModeKHR::FIFO_RELAXED,
+            ],
+            formats: vec![
+                surface_format(vk::Format::B8G8R8A8_SRGB, vk::ColorSpaceKHR::SRGB_NONLINEAR),
+                surface_format(vk::Format::R8G8B8A8_SRGB, vk::ColorSpaceKHR::SRGB_NONLINEAR),
+            ],
+            image_count: 3,
+        }
+    }
+}
+
+impl SwapchainConfig {
+
+    /// Prefer HDR10 (PQ, BT.2020), the color space needs VK_EXT_swapchain_colorspace
+    pub fn hdr10(mut self) -> Self {
+        self.formats.insert(0, surface_format(vk::Format::A2B10G10R10_UNORM_PACK32, vk::ColorSpaceKHR::HDR10_ST2084_EXT));
+        self
+    }
+
+    /// Prefer linear extended sRGB in half floats, the color space needs VK_EXT_swapchain_colorspace
+    pub fn scrgb(mut self) -> Self {
+        self.formats.insert(0, surface_format(vk::Format::R16G16B16A16_SFLOAT, vk::ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT));
+        self
+    }
+
+    pub fn vsync(mut self, vsync: bool) -> Self {
+        self.vsync = vsync;
+        self
+    }
+
+    pub fn choose_format(&self, available: &[vk::SurfaceFormatKHR]) -> Option<vk::SurfaceFormatKHR> {
+
+        // A single UNDEFINED entry means the surface takes any format
+        if let [only] = available && only.format == vk::Format::UNDEFINED {
+            return self.formats.first().copied().or(Some(surface_format(vk::Format::B8G8R8A8_SRGB, only.color_space)));
+        }
+
+        self.formats.iter()
+            .find(|preferred| available.contains(preferred))
+            .or_else(|| available.first())
+            .copied()
+    }
+
+    /// FIFO is the only mode every surface supports
+    pub fn choose_present_mode(&self, available: &[vk::PresentModeKHR]) -> vk::PresentModeKHR {
+        if self.vsync {
+            return vk::PresentModeKHR::FIFO;
+        }
+
+        self.present_modes.iter()
+            .find(|mode| available.contains(mode))
+            .copied()
+            .unwrap_or(vk::PresentModeKHR::FIFO)
+    }
+
+    pub fn choose_image_count(&self, caps: &vk::SurfaceCapabilitiesKHR) -> u32 {
+        let count = self.image_count.max(caps.min_image_count);
+
+        // Zero means no limit
+        if caps.max_image_count > 0 {
+            count.min(caps.max_image_count)
+        } else {
+            count
+        }
+    }
+}
+
+fn surface_format(format: vk::Format, color_space: vk::ColorSpaceKHR) -> vk::SurfaceFormatKHR {
+    vk::SurfaceFormatKHR { format, color_space }
+}
+
+/// Extent of the surface, or `fallback` clamped to the caps when the window decides the size
+pub fn choose_extent(caps: &vk::SurfaceCapabilitiesKHR, fallback: vk::Extent2D) -> vk::Extent2D {
+    if caps.current_extent.width != u32::MAX {
+        return caps.current_extent;
+    }
+
+    vk::Extent2D {
+        width: fallback.width.clamp(caps.min_image_extent.width, caps.max_image_extent.width),
+        height: fallback.height.clamp(caps.min_image_extent.height, caps.max_image_extent.height),
+    }
 }
 
 pub struct SwapchainBuilder<'a> {
@@ -17,7 +128,7 @@ pub struct SwapchainBuilder<'a> {
 impl<'a> SwapchainBuilder<'a> {
 
     pub fn default(instance: &'a Instance, device: &'a Device, surface: &'a Surface) -> Self {
-        SwapchainBuilder { 
+        SwapchainBuilder {
             surface,
             instance,
             device,
@@ -32,6 +143,48 @@ impl<'a> SwapchainBuilder<'a> {
         }
     }
 
+    /// Query the surface and pick format, present mode, image count, extent and transform from `config`
+    pub fn negotiate(
+        instance: &'a Instance,
+        device: &'a Device,
+        surface: &'a Surface,
+        phys_dev: vk::PhysicalDevice,
+        config: &SwapchainConfig,
+        extent: vk::Extent2D,
+    ) -> VulkanResult<Self> {
+
+        let caps = surface.get_physical_device_surface_capabilities(&phys_dev)?;
+        let formats = surface.get_physical_device_surface_formats(&phys_dev)?;
+        let present_modes = surface.get_physical_device_surface_present_modes(&phys_dev)?;
+
+        let format = config.choose_format(&formats)
+            .ok_or(VulkanError::Swapchain(SwapchainError::NoSurfaceFormats))?;
+
+        let present_mode = config.choose_present_mode(&present_modes);
+
+        // Some compositors only accept pre-multiplied or inherited alpha
+        let composite_alpha = [
+            vk::CompositeAlphaFlagsKHR::OPAQUE,
+            vk::CompositeAlphaFlagsKHR::PRE_MULTIPLIED,
+            vk::CompositeAlphaFlagsKHR::POST_MULTIPLIED,
+            vk::CompositeAlphaFlagsKHR::INHERIT,
+        ]
+        .into_iter()
+        .find(|alpha| caps.supported_composite_alpha.contains(*alpha))
+        .unwrap_or(vk::CompositeAlphaFlagsKHR::OPAQUE);
+
+        debug!("Negotiated swapchain: {:?} {:?} {:?}", format, present_mode, composite_alpha);
+
+        Ok(Self::default(instance, device, surface)
+            .format(format.format)
+            .color_space(format.color_space)
+            .present_mode(present_mode)
+            .min_image_count(config.choose_image_count(&caps))
+            .pre_transform(caps.current_transform)
+            .composite_alpha(composite_alpha)
+            .extent(choose_extent(&caps, extent)))
+    }
+
     pub fn old_swapchain(mut self, swapchian: vk::SwapchainKHR) -> Self {
         self.create_info = self.create_info.old_swapchain(swapchian);
         self
@@ -42,6 +195,35 @@ impl<'a> SwapchainBuilder<'a> {
         self
     }
 
+    pub fn color_space(mut self, color_space: vk::ColorSpaceKHR) -> Self {
+        self.create_info = self.create_info.image_color_space(color_space);
+        self
+    }
+
+    pub fn surface_format(&self) -> vk::SurfaceFormatKHR {
+        surface_format(self.create_info.image_format, self.create_info.image_color_space)
+    }
+
+    pub fn present_mode(mut self, present_mode: vk::PresentModeKHR) -> Self {
+        self.create_info = self.create_info.present_mode(present_mode);
+        self
+    }
+
+    pub fn min_image_count(mut self, count: u32) -> Self {
+        self.create_info = self.create_info.min_image_count(count);
+        self
+    }
+
+    pub fn pre_transform(mut self, transform: vk::SurfaceTransformFlagsKHR) -> Self {
+        self.create_info = self.create_info.pre_transform(transform);
+        self
+    }
+
+    pub fn composite_alpha(mut self, alpha: vk::CompositeAlphaFlagsKHR) -> Self {
+        self.create_info = self.create_info.composite_alpha(alpha);
+        self
+    }
+
     pub fn extent(mut self, extent: vk::Extent2D) -> Self {
         self.create_info = self.create_info.image_extent(extent);
         self
@@ -58,12 +240,19 @@ impl<'a> SwapchainBuilder<'a> {
         let swapchain = unsafe {
             swapchain_loader
                 .create_swapchain(&create_info, None)
-                .map_err(|e| VulkanError::Unknown(e))
+                .map_err(|e| VulkanError::Swapchain(SwapchainError::SwapchainCreationFailed(e)))
         }?;
 
         Ok(Swapchain {
             raw: swapchain,
             loader: swapchain_loader,
+            format: vk::SurfaceFormatKHR {
+                format: create_info.image_format,
+                color_space: create_info.image_color_space,
+            },
+            present_mode: create_info.present_mode,
+            extent: create_info.image_extent,
+            deletion: self.device.register(swapchain)
         })
     }
 }
@@ -71,14 +260,18 @@ impl<'a> SwapchainBuilder<'a> {
 impl Swapchain {
 
     pub fn get_swapchain_images(&self) -> VulkanResult<Vec<vk::Image>> {
-        unsafe { self.loader.get_swapchain_images(self.raw).map_err(|e| crate::VulkanError::Unknown(e)) }
+        unsafe { self.loader.get_swapchain_images(self.raw).map_err(|e| VulkanError::Swapchain(SwapchainError::GetSwapchainImages(e))) }
     }
-    
-    pub fn destroy(&self) {
-        unsafe {
-            self.loader.destroy_swapchain(self.raw, None);
-        }
+
+    pub fn format(&self) -> vk::SurfaceFormatKHR {
+        self.format
     }
-}
 
+    pub fn present_mode(&self) -> vk::PresentModeKHR {
+        self.present_mode
+    }
 
+    pub fn extent(&self) -> vk::Extent2D {
+        self.extent
+    }
+}
@@ -0,0 +1,349 @@
+use ash::vk;
+
+use crate::{Device, SyncError, VulkanError, VulkanResult};
+
+/// Pipeline stage and memory access on one side of a dependency
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Access {
+    pub stage: vk::PipelineStageFlags2,
+    pub access: vk::AccessFlags2,
+}
+
+impl Access {
+
+    pub const NONE: Access = Access::new(vk::PipelineStageFlags2::NONE, vk::AccessFlags2::NONE);
+
+    pub const TRANSFER_READ: Access = Access::new(vk::PipelineStageFlags2::TRANSFER, vk::AccessFlags2::TRANSFER_READ);
+    pub const TRANSFER_WRITE: Access = Access::new(vk::PipelineStageFlags2::TRANSFER, vk::AccessFlags2::TRANSFER_WRITE);
+
+    pub const VERTEX_SHADER_READ: Access = Access::new(vk::PipelineStageFlags2::VERTEX_SHADER, vk::AccessFlags2::SHADER_READ);
+    pub const FRAGMENT_SHADER_READ: Access = Access::new(vk::PipelineStageFlags2::FRAGMENT_SHADER, vk::AccessFlags2::SHADER_READ);
+    pub const COMPUTE_SHADER_READ: Access = Access::new(vk::PipelineStageFlags2::COMPUTE_SHADER, vk::AccessFlags2::SHADER_READ);
+    pub const COMPUTE_SHADER_WRITE: Access = Access::new(vk::PipelineStageFlags2::COMPUTE_SHADER, vk::AccessFlags2::SHADER_WRITE);
+
+    pub const COLOR_ATTACHMENT_WRITE: Access = Access::new(vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT, vk::AccessFlags2::COLOR_ATTACHMENT_WRITE);
+    pub const DEPTH_ATTACHMENT_WRITE: Access = Access::new(
+        vk::PipelineStageFlags2::from_raw(vk::PipelineStageFlags2::EARLY_FRAGMENT_TESTS.as_raw() | vk::PipelineStageFlags2::LATE_FRAGMENT_TESTS.as_raw()),
+        vk::AccessFlags2::from_raw(vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_READ.as_raw() | vk::AccessFlags2::DEPTH_STENCIL_ATTACHMENT_WRITE.as_raw())
+    );
+
+    /// Presentation engine, no memory access needed
+    pub const PRESENT: Access = Access::new(vk::PipelineStageFlags2::BOTTOM_OF_PIPE, vk::AccessFlags2::NONE);
+
+    pub const fn new(stage: vk::PipelineStageFlags2, access: vk::AccessFlags2) -> Self {
+        Self { stage, access }
+    }
+}
+
+/// Stages only known to synchronization2 folded into the legacy ones
+fn legacy_stage(stage: vk::PipelineStageFlags2, empty: vk::PipelineStageFlags) -> vk::PipelineStageFlags {
+    use vk::PipelineStageFlags2 as S2;
+
+    let raw = stage.as_raw();
+    let mut legacy = vk::PipelineStageFlags::from_raw((raw & u32::MAX as u64) as u32);
+
+    if stage.intersects(S2::COPY | S2::RESOLVE | S2::BLIT | S2::CLEAR) {
+        legacy |= vk::PipelineStageFlags::TRANSFER;
+    }
+
+    if stage.intersects(S2::INDEX_INPUT | S2::VERTEX_ATTRIBUTE_INPUT) {
+        legacy |= vk::PipelineStageFlags::VERTEX_INPUT;
+    }
+
+    if stage.intersects(S2::PRE_RASTERIZATION_SHADERS) {
+        legacy |= vk::PipelineStageFlags::VERTEX_SHADER
+            | vk::PipelineStageFlags::TESSELLATION_CONTROL_SHADER
+            | vk::PipelineStageFlags::TESSELLATION_EVALUATION_SHADER
+            | vk::PipelineStageFlags::GEOMETRY_SHADER;
+    }
+
+    if legacy.is_empty() { empty } else { legacy }
+}
+
+fn legacy_access(access: vk::AccessFlags2) -> vk::AccessFlags {
+    use vk::AccessFlags2 as A2;
+
+    let raw = access.as_raw();
+    let mut legacy = vk::AccessFlags::from_raw((raw & u32::MAX as u64) as u32);
+
+    if access.intersects(A2::SHADER_SAMPLED_READ | A2::SHADER_STORAGE_READ) {
+        legacy |= vk::AccessFlags::SHADER_READ;
+    }
+
+    if access.intersects(A2::SHADER_STORAGE_WRITE) {
+        legacy |= vk::AccessFlags::SHADER_WRITE;
+    }
+
+    legacy
+}
+
+/// Pipeline barrier recorded with `vkCmdPipelineBarrier2` when synchronization2 is enabled,
+/// otherwise translated to `vkCmdPipelineBarrier`
+pub struct BarrierBuilder<'a> {
+    device: &'a Device,
+    memory: Vec<vk::MemoryBarrier2<'static>>,
+    buffers: Vec<vk::BufferMemoryBarrier2<'static>>,
+    images: Vec<vk::ImageMemoryBarrier2<'static>>,
+}
+
+impl<'a> BarrierBuilder<'a> {
+
+    pub fn new(device: &'a Device) -> Self {
+        Self {
+            device,
+            memory: vec![],
+            buffers: vec![],
+            images: vec![]
+        }
+    }
+
+    pub fn memory(mut self, src: Access, dst: Access) -> Self {
+        self.memory.push(
+            vk::MemoryBarrier2::default()
+                .src_stage_mask(src.stage)
+                .src_access_mask(src.access)
+                .dst_stage_mask(dst.stage)
+                .dst_access_mask(dst.access)
+        );
+        self
+    }
+
+    pub fn buffer(mut self, buffer: vk::Buffer, offset: u64, size: u64, src: Access, dst: Access) -> Self {
+        self.buffers.push(
+            vk::BufferMemoryBarrier2::default()
+                .src_stage_mask(src.stage)
+                .src_access_mask(src.access)
+                .dst_stage_mask(dst.stage)
+                .dst_access_mask(dst.access)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .buffer(buffer)
+                .offset(offset)
+                .size(size)
+        );
+        self
+    }
+
+    /// Layout transition of `range`, `UNDEFINED` as old layout discards the content
+    pub fn image(
+        mut self,
+        image: vk::Image,
+        range: vk::ImageSubresourceRange,
+        old_layout: vk::ImageLayout,
+        new_layout: vk::ImageLayout,
+        src: Access,
+        dst: Access
+    ) -> Self {
+        self.images.push(
+            vk::ImageMemoryBarrier2::default()
+                .src_stage_mask(src.stage)
+                .src_access_mask(src.access)
+                .dst_stage_mask(dst.stage)
+                .dst_access_mask(dst.access)
+                .old_layout(old_layout)
+                .new_layout(new_layout)
+                .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
+                .image(image)
+                .subresource_range(range)
+        );
+        self
+    }
+
+    pub fn record(self, cmd: vk::CommandBuffer) {
+
+        if self.memory.is_empty() && self.buffers.is_empty() && self.images.is_empty() {
+            return;
+        }
+
+        if self.device.features().synchronization2 {
+
+            let dependency = vk::DependencyInfo::default()
+                .memory_barriers(&self.memory)
+                .buffer_memory_barriers(&self.buffers)
+                .image_memory_barriers(&self.images);
+
+            unsafe {
+                match &self.device.synchronization2_ext {
+                    Some(ext) => ext.cmd_pipeline_barrier2(cmd, &dependency),
+                    None => self.device.raw.cmd_pipeline_barrier2(cmd, &dependency),
+                }
+            }
+
+            return;
+        }
+
+        // Legacy barriers share one stage mask for the whole call
+        let mut src_stage = vk::PipelineStageFlags2::NONE;
+        let mut dst_stage = vk::PipelineStageFlags2::NONE;
+
+        let memory = self.memory
+            .iter()
+            .map(|barrier| {
+                src_stage |= barrier.src_stage_mask;
+                dst_stage |= barrier.dst_stage_mask;
+
+                vk::MemoryBarrier::default()
+                    .src_access_mask(legacy_access(barrier.src_access_mask))
+                    .dst_access_mask(legacy_access(barrier.dst_access_mask))
+            })
+            .collect::<Vec<_>>();
+
+        let buffers = self.buffers
+            .iter()
+            .map(|barrier| {
+                src_stage |= barrier.src_stage_mask;
+                dst_stage |= barrier.dst_stage_mask;
+
+                vk::BufferMemoryBarrier::default()
+                    .src_access_mask(legacy_access(barrier.src_access_mask))
+                    .dst_access_mask(legacy_access(barrier.dst_access_mask))
+                    .src_queue_family_index(barrier.src_queue_family_index)
+                    .dst_queue_family_index(barrier.dst_queue_family_index)
+                    .buffer(barrier.buffer)
+                    .offset(barrier.offset)
+                    .size(barrier.size)
+            })
+            .collect::<Vec<_>>();
+
+        let images = self.images
+            .iter()
+            .map(|barrier| {
+                src_stage |= barrier.src_stage_mask;
+                dst_stage |= barrier.dst_stage_mask;
+
+                vk::ImageMemoryBarrier::default()
+                    .src_access_mask(legacy_access(barrier.src_access_mask))
+                    .dst_access_mask(legacy_access(barrier.dst_access_mask))
+                    .old_layout(barrier.old_layout)
+                    .new_layout(barrier.new_layout)
+                    .src_queue_family_index(barrier.src_queue_family_index)
+                    .dst_queue_family_index(barrier.dst_queue_family_index)
+                    .image(barrier.image)
+                    .subresource_range(barrier.subresource_range)
+            })
+            .collect::<Vec<_>>();
+
+        unsafe {
+            self.device.cmd_pipeline_barrier(
+                cmd,
+                legacy_stage(src_stage, vk::PipelineStageFlags::TOP_OF_PIPE),
+                legacy_stage(dst_stage, vk::PipelineStageFlags::BOTTOM_OF_PIPE),
+                vk::DependencyFlags::empty(),
+                &memory,
+                &buffers,
+                &images
+            )
+        };
+    }
+}
+
+/// Queue submission with binary and timeline semaphores,
+/// `vkQueueSubmit2` when synchronization2 is enabled
+pub struct SubmitBuilder<'a> {
+    device: &'a Device,
+    command_buffers: Vec<vk::CommandBuffer>,
+    waits: Vec<vk::SemaphoreSubmitInfo<'static>>,
+    signals: Vec<vk::SemaphoreSubmitInfo<'static>>,
+}
+
+impl<'a> SubmitBuilder<'a> {
+
+    pub fn new(device: &'a Device) -> Self {
+        Self {
+            device,
+            command_buffers: vec![],
+            waits: vec![],
+            signals: vec![]
+        }
+    }
+
+    pub fn command_buffers(mut self, buffers: &[vk::CommandBuffer]) -> Self {
+        self.command_buffers.extend_from_slice(buffers);
+        self
+    }
+
+    pub fn wait(mut self, semaphore: vk::Semaphore, stage: vk::PipelineStageFlags2) -> Self {
+        self.waits.push(semaphore_info(semaphore, 0, stage));
+        self
+    }
+
+    /// Wait until the timeline reaches `value`
+    pub fn wait_timeline(mut self, semaphore: vk::Semaphore, value: u64, stage: vk::PipelineStageFlags2) -> Self {
+        self.waits.push(semaphore_info(semaphore, value, stage));
+        self
+    }
+
+    pub fn signal(mut self, semaphore: vk::Semaphore, stage: vk::PipelineStageFlags2) -> Self {
+        self.signals.push(semaphore_info(semaphore, 0, stage));
+        self
+    }
+
+    /// Set the timeline to `value` once the work completes
+    pub fn signal_timeline(mut self, semaphore: vk::Semaphore, value: u64, stage: vk::PipelineStageFlags2) -> Self {
+        self.signals.push(semaphore_info(semaphore, value, stage));
+        self
+    }
+
+    pub fn submit(self, queue: vk::Queue, fence: vk::Fence) -> VulkanResult<()> {
+        puffin::profile_scope!("vkQueueSubmit");
+
+        if self.device.features().synchronization2 {
+
+            let buffers = self.command_buffers
+                .iter()
+                .map(|&buffer| vk::CommandBufferSubmitInfo::default().command_buffer(buffer))
+                .collect::<Vec<_>>();
+
+            let submit = vk::SubmitInfo2::default()
+                .wait_semaphore_infos(&self.waits)
+                .command_buffer_infos(&buffers)
+                .signal_semaphore_infos(&self.signals);
+
+            return unsafe {
+                match &self.device.synchronization2_ext {
+                    Some(ext) => ext.queue_submit2(queue, &[submit], fence),
+                    None => self.device.raw.queue_submit2(queue, &[submit], fence),
+                }
+                .map_err(|e| VulkanError::Sync(SyncError::QueueSubmitFailed(e)))
+            };
+        }
+
+        let wait_semaphores = self.waits.iter().map(|info| info.semaphore).collect::<Vec<_>>();
+        let wait_values = self.waits.iter().map(|info| info.value).collect::<Vec<_>>();
+        let wait_stages = self.waits
+            .iter()
+            .map(|info| legacy_stage(info.stage_mask, vk::PipelineStageFlags::TOP_OF_PIPE))
+            .collect::<Vec<_>>();
+
+        let signal_semaphores = self.signals.iter().map(|info| info.semaphore).collect::<Vec<_>>();
+        let signal_values = self.signals.iter().map(|info| info.value).collect::<Vec<_>>();
+
+        let mut submit = vk::SubmitInfo::default()
+            .wait_semaphores(&wait_semaphores)
+            .wait_dst_stage_mask(&wait_stages)
+            .command_buffers(&self.command_buffers)
+            .signal_semaphores(&signal_semaphores);
+
+        // Values of binary semaphores are ignored
+        let mut timeline_info = vk::TimelineSemaphoreSubmitInfo::default()
+            .wait_semaphore_values(&wait_values)
+            .signal_semaphore_values(&signal_values);
+
+        if self.device.features().timeline_semaphore {
+            submit = submit.push_next(&mut timeline_info);
+        }
+
+        unsafe {
+            self.device.queue_submit(queue, &[submit], fence)
+                .map_err(|e| VulkanError::Sync(SyncError::QueueSubmitFailed(e)))
+        }
+    }
+}
+
+fn semaphore_info(semaphore: vk::Semaphore, value: u64, stage: vk::PipelineStageFlags2) -> vk::SemaphoreSubmitInfo<'static> {
+    vk::SemaphoreSubmitInfo::default()
+        .semaphore(semaphore)
+        .value(value)
+        .stage_mask(stage)
+}
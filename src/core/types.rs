@@ -1,6 +1,7 @@
-
 use ash::vk;
 
+pub use banana_derive::VertexAttributes;
+
 pub trait AttributeDescriptions {
     fn attr_desc() -> Vec<vk::VertexInputAttributeDescription>;
 }
@@ -10,39 +11,19 @@ pub trait BindingDescriptions {
 }
 
 #[repr(C)]
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, VertexAttributes)]
 pub struct Vertex {
     pub pos: [f32; 3],
     pub color: [f32; 3],
 }
 
-impl AttributeDescriptions for Vertex {
-    fn attr_desc() -> Vec<vk::VertexInputAttributeDescription> {
-        let attributes = vec![
-            vk::VertexInputAttributeDescription {
-                location: 0,
-                binding: 0,
-                format: vk::Format::R32G32B32_SFLOAT,
-                offset: std::mem::offset_of!(Vertex, pos) as u32,
-            },
-            vk::VertexInputAttributeDescription {
-                binding: 0,
-                location: 1,
-                format: vk::Format::R32G32B32_SFLOAT,
-                offset: std::mem::offset_of!(Vertex, color) as u32,
-            },
-        ];
-
-        attributes
-    }
+/// Vertex of `pbr.vert` and `pbr_bindless.vert`
+#[repr(C)]
+#[derive(Clone, Copy, VertexAttributes)]
+pub struct PBRVertex {
+    pub pos: [f32; 4],
+    pub normal: [f32; 4],
+    pub uv: [f32; 2],
+    pub color: [f32; 4],
+    pub tangent: [f32; 4],
 }
-
-impl BindingDescriptions for Vertex {
-    fn bind_desc() -> Vec<vk::VertexInputBindingDescription> {
-        vec![vk::VertexInputBindingDescription {
-            binding: 0,
-            stride: std::mem::size_of::<Self>() as u32,
-            input_rate: vk::VertexInputRate::VERTEX,
-        }]
-    }
-}
\ No newline at end of file
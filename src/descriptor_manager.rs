@@ -1,41 +1,383 @@
-use crate::{DescriptorPool, DescriptorPoolBuilder, Device, VulkanResult};
+use std::collections::HashMap;
+
+use crate::{DescriptorError, DescriptorPool, DescriptorPoolBuilder, Device, VulkanError, VulkanResult};
 use ash::vk;
+use log::debug;
+
+/// Sets in the first pool of an allocator, every new pool doubles it
+const INITIAL_SETS: u32 = 64;
+const MAX_SETS: u32 = 4096;
+
+/// Descriptors of each type per set reserved in every pool
+const POOL_RATIOS: &[(vk::DescriptorType, f32)] = &[
+    (vk::DescriptorType::SAMPLER, 0.5),
+    (vk::DescriptorType::COMBINED_IMAGE_SAMPLER, 4.0),
+    (vk::DescriptorType::SAMPLED_IMAGE, 4.0),
+    (vk::DescriptorType::STORAGE_IMAGE, 1.0),
+    (vk::DescriptorType::UNIFORM_BUFFER, 2.0),
+    (vk::DescriptorType::UNIFORM_BUFFER_DYNAMIC, 1.0),
+    (vk::DescriptorType::STORAGE_BUFFER, 2.0),
+    (vk::DescriptorType::STORAGE_BUFFER_DYNAMIC, 0.5),
+    (vk::DescriptorType::INPUT_ATTACHMENT, 0.5),
+];
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PoolStats {
+    pub pools: usize,
+    /// Sets allocated and not yet freed or reset
+    pub sets: u32,
+    /// Sets the existing pools were created for
+    pub capacity: u32,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DescriptorStats {
+    pub persistent: PoolStats,
+    /// Pools of the current frame slot
+    pub frame: PoolStats,
+}
+
+/// Vulkan side of a `PoolChain`
+trait Pool: Sized {
+    type Device: ?Sized;
+
+    fn create(device: &Self::Device, sizes: &[vk::DescriptorPoolSize], max_sets: u32, flags: vk::DescriptorPoolCreateFlags) -> VulkanResult<Self>;
+    fn allocate(&self, device: &Self::Device, layouts: &[vk::DescriptorSetLayout]) -> VulkanResult<Vec<vk::DescriptorSet>>;
+    fn free(&self, device: &Self::Device, sets: &[vk::DescriptorSet]) -> VulkanResult<()>;
+    fn reset(&self, device: &Self::Device) -> VulkanResult<()>;
+}
+
+impl Pool for DescriptorPool {
+    type Device = Device;
+
+    fn create(device: &Device, sizes: &[vk::DescriptorPoolSize], max_sets: u32, flags: vk::DescriptorPoolCreateFlags) -> VulkanResult<Self> {
+        DescriptorPoolBuilder::new(device)
+            .pool_sizes(sizes)
+            .max_sets(max_sets)
+            .flags(flags)
+            .build()
+    }
+
+    fn allocate(&self, device: &Device, layouts: &[vk::DescriptorSetLayout]) -> VulkanResult<Vec<vk::DescriptorSet>> {
+        self.create_descriptor_set(device, layouts)
+    }
+
+    fn free(&self, device: &Device, sets: &[vk::DescriptorSet]) -> VulkanResult<()> {
+        self.free_descriptor_sets(device, sets)
+    }
+
+    fn reset(&self, device: &Device) -> VulkanResult<()> {
+        DescriptorPool::reset(self, device)
+    }
+}
+
+/// List of pools that grows when the last one runs out of memory
+struct PoolChain<P: Pool = DescriptorPool> {
+    flags: vk::DescriptorPoolCreateFlags,
+    pools: Vec<(P, u32)>,
+    /// First pool that may still have room
+    current: usize,
+    sets: u32,
+}
+
+impl<P: Pool> PoolChain<P> {
+
+    fn new(flags: vk::DescriptorPoolCreateFlags) -> Self {
+        Self {
+            flags,
+            pools: vec![],
+            current: 0,
+            sets: 0,
+        }
+    }
 
+    fn create_pool(&mut self, device: &P::Device) -> VulkanResult<()> {
+
+        let max_sets = self.pools.last()
+            .map(|(_, sets)| (sets * 2).min(MAX_SETS))
+            .unwrap_or(INITIAL_SETS);
+
+        let sizes = POOL_RATIOS.iter()
+            .map(|(ty, ratio)| {
+                vk::DescriptorPoolSize::default()
+                    .ty(*ty)
+                    .descriptor_count(((max_sets as f32 * ratio) as u32).max(1))
+            })
+            .collect::<Vec<_>>();
+
+        let pool = P::create(device, &sizes, max_sets, self.flags)?;
+
+        debug!("Create DescriptorPool #{} for {} sets", self.pools.len(), max_sets);
+
+        self.pools.push((pool, max_sets));
+        Ok(())
+    }
+
+    /// Returns the sets and the index of the pool they came from
+    fn allocate(&mut self, device: &P::Device, layouts: &[vk::DescriptorSetLayout]) -> VulkanResult<(Vec<vk::DescriptorSet>, usize)> {
+
+        loop {
+            let created = self.current == self.pools.len();
+
+            if created {
+                self.create_pool(device)?;
+            }
+
+            let (pool, max_sets) = &self.pools[self.current];
+
+            match pool.allocate(device, layouts) {
+                Ok(sets) => {
+                    self.sets += sets.len() as u32;
+                    return Ok((sets, self.current));
+                }
+                // Move on to the next pool, which is bigger when it has to be created.
+                // Give up once a pool of the maximum size fails right after creation
+                Err(VulkanError::Descriptor(DescriptorError::AllocateSetsFailed(
+                    vk::Result::ERROR_OUT_OF_POOL_MEMORY | vk::Result::ERROR_FRAGMENTED_POOL
+                ))) if !created || *max_sets < MAX_SETS => {
+                    self.current += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn free(&mut self, device: &P::Device, pool: usize, sets: &[vk::DescriptorSet]) -> VulkanResult<()> {
+        self.pools[pool].0.free(device, sets)?;
+        self.sets -= sets.len() as u32;
+
+        // Freed memory may make room in an earlier pool
+        self.current = self.current.min(pool);
+        Ok(())
+    }
+
+    fn reset(&mut self, device: &P::Device) -> VulkanResult<()> {
+        for (pool, _) in &self.pools {
+            pool.reset(device)?;
+        }
+
+        self.current = 0;
+        self.sets = 0;
+        Ok(())
+    }
+
+    fn stats(&self) -> PoolStats {
+        PoolStats {
+            pools: self.pools.len(),
+            sets: self.sets,
+            capacity: self.pools.iter().map(|(_, sets)| sets).sum(),
+        }
+    }
+}
+
+/// Allocates descriptor sets from pools that grow on demand.
+///
+/// Persistent sets live until they are freed, frame sets until the frame slot
+/// they were allocated in comes around again
 pub struct DescriptorManager {
-    pool: DescriptorPool,
+    persistent: PoolChain,
+    /// Pool each persistent set was allocated from
+    owners: HashMap<vk::DescriptorSet, usize>,
+    frames: Vec<PoolChain>,
+    slot: usize,
 }
 
 impl DescriptorManager {
     pub fn new(device: &Device) -> VulkanResult<Self> {
 
-        let pool = DescriptorPoolBuilder::new(device)
-            .pool_sizes(&[
-                vk::DescriptorPoolSize::default()
-                    .descriptor_count(500)
-                    .ty(vk::DescriptorType::SAMPLER),
-                vk::DescriptorPoolSize::default()
-                    .descriptor_count(5000)
-                    .ty(vk::DescriptorType::COMBINED_IMAGE_SAMPLER),
-                vk::DescriptorPoolSize::default()
-                    .descriptor_count(5000)
-                    .ty(vk::DescriptorType::UNIFORM_BUFFER),
-                vk::DescriptorPoolSize::default()
-                    .descriptor_count(500)
-                    .ty(vk::DescriptorType::STORAGE_BUFFER),
-                vk::DescriptorPoolSize::default()
-                    .descriptor_count(500)
-                    .ty(vk::DescriptorType::STORAGE_IMAGE)
-            ])
-            .max_sets(100000)
-            .build()?;
+        let mut persistent = PoolChain::new(vk::DescriptorPoolCreateFlags::FREE_DESCRIPTOR_SET);
+        persistent.create_pool(device)?;
+
+        Ok(DescriptorManager {
+            persistent,
+            owners: HashMap::new(),
+            frames: vec![],
+            slot: 0,
+        })
+    }
 
-        Ok(DescriptorManager { pool })
+    /// Reset the pools of `slot`, call once the slot's fence has signaled
+    pub fn begin_frame(&mut self, device: &Device, slot: usize) -> VulkanResult<()> {
+        puffin::profile_scope!("DescriptorManager::begin_frame");
+
+        self.slot = slot;
+        self.frame().reset(device)
+    }
+
+    fn frame(&mut self) -> &mut PoolChain {
+        if self.frames.len() <= self.slot {
+            self.frames.resize_with(self.slot + 1, || PoolChain::new(vk::DescriptorPoolCreateFlags::empty()));
+        }
+
+        &mut self.frames[self.slot]
+    }
+
+    pub fn stats(&self) -> DescriptorStats {
+        DescriptorStats {
+            persistent: self.persistent.stats(),
+            frame: self.frames.get(self.slot).map(PoolChain::stats).unwrap_or_default(),
+        }
     }
 }
 
 
 impl DescriptorManager {
-    pub fn create_descriptor_set(&self, device: &Device, layouts: &[vk::DescriptorSetLayout]) -> Vec<vk::DescriptorSet> {
-        self.pool.create_descriptor_set(device, layouts)
+    /// Long-lived sets, valid until passed to `free_descriptor_sets`
+    pub fn create_descriptor_set(&mut self, device: &Device, layouts: &[vk::DescriptorSetLayout]) -> VulkanResult<Vec<vk::DescriptorSet>> {
+        let (sets, pool) = self.persistent.allocate(device, layouts)?;
+
+        for set in &sets {
+            self.owners.insert(*set, pool);
+        }
+
+        Ok(sets)
+    }
+
+    /// Sets valid for the current frame only
+    pub fn create_frame_descriptor_set(&mut self, device: &Device, layouts: &[vk::DescriptorSetLayout]) -> VulkanResult<Vec<vk::DescriptorSet>> {
+        let (sets, _) = self.frame().allocate(device, layouts)?;
+        Ok(sets)
+    }
+
+    /// Return persistent sets to their pools, the GPU must be done with them
+    pub fn free_descriptor_sets(&mut self, device: &Device, sets: &[vk::DescriptorSet]) -> VulkanResult<()> {
+        let mut by_pool: HashMap<usize, Vec<vk::DescriptorSet>> = HashMap::new();
+
+        for set in sets {
+            if let Some(pool) = self.owners.remove(set) {
+                by_pool.entry(pool).or_default().push(*set);
+            }
+        }
+
+        for (pool, sets) in by_pool {
+            self.persistent.free(device, pool, &sets)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use super::*;
+
+    /// Pool that hands out fake sets up to its capacity
+    struct FakePool {
+        max_sets: u32,
+        used: Cell<u32>,
+    }
+
+    impl Pool for FakePool {
+        type Device = ();
+
+        fn create(_: &(), _: &[vk::DescriptorPoolSize], max_sets: u32, _: vk::DescriptorPoolCreateFlags) -> VulkanResult<Self> {
+            Ok(FakePool { max_sets, used: Cell::new(0) })
+        }
+
+        fn allocate(&self, _: &(), layouts: &[vk::DescriptorSetLayout]) -> VulkanResult<Vec<vk::DescriptorSet>> {
+            let count = layouts.len() as u32;
+
+            if self.used.get() + count > self.max_sets {
+                return Err(VulkanError::Descriptor(DescriptorError::AllocateSetsFailed(vk::Result::ERROR_OUT_OF_POOL_MEMORY)));
+            }
+
+            self.used.set(self.used.get() + count);
+            Ok(vec![vk::DescriptorSet::null(); layouts.len()])
+        }
+
+        fn free(&self, _: &(), sets: &[vk::DescriptorSet]) -> VulkanResult<()> {
+            self.used.set(self.used.get() - sets.len() as u32);
+            Ok(())
+        }
+
+        fn reset(&self, _: &()) -> VulkanResult<()> {
+            self.used.set(0);
+            Ok(())
+        }
     }
-}
\ No newline at end of file
+
+    fn chain() -> PoolChain<FakePool> {
+        PoolChain::new(vk::DescriptorPoolCreateFlags::empty())
+    }
+
+    fn layouts(count: usize) -> Vec<vk::DescriptorSetLayout> {
+        vec![vk::DescriptorSetLayout::null(); count]
+    }
+
+    #[test]
+    fn pool_sizes_cover_every_type() {
+        for ty in [vk::DescriptorType::INPUT_ATTACHMENT, vk::DescriptorType::STORAGE_BUFFER_DYNAMIC] {
+            assert!(POOL_RATIOS.iter().any(|(t, _)| *t == ty), "{:?} missing", ty);
+        }
+    }
+
+    #[test]
+    fn grows_by_doubling() {
+        let mut chain = chain();
+
+        let (_, pool) = chain.allocate(&(), &layouts(INITIAL_SETS as usize)).unwrap();
+        assert_eq!(pool, 0);
+
+        let (_, pool) = chain.allocate(&(), &layouts(1)).unwrap();
+        assert_eq!(pool, 1);
+
+        let stats = chain.stats();
+        assert_eq!(stats.pools, 2);
+        assert_eq!(stats.sets, INITIAL_SETS + 1);
+        assert_eq!(stats.capacity, INITIAL_SETS * 3);
+    }
+
+    #[test]
+    fn pool_size_is_capped() {
+        let mut chain = chain();
+
+        while chain.stats().capacity < MAX_SETS * 2 {
+            chain.allocate(&(), &layouts(1)).unwrap();
+            chain.current = chain.pools.len();
+        }
+
+        assert!(chain.pools.iter().all(|(_, sets)| *sets <= MAX_SETS));
+        assert_eq!(chain.pools.last().unwrap().1, MAX_SETS);
+    }
+
+    #[test]
+    fn oversized_allocation_fails() {
+        let mut chain = chain();
+        assert!(chain.allocate(&(), &layouts(MAX_SETS as usize + 1)).is_err());
+    }
+
+    #[test]
+    fn free_rewinds_to_the_pool() {
+        let mut chain = chain();
+
+        let (sets, first) = chain.allocate(&(), &layouts(INITIAL_SETS as usize)).unwrap();
+        chain.allocate(&(), &layouts(1)).unwrap();
+        assert_eq!(chain.current, 1);
+
+        chain.free(&(), first, &sets[..2]).unwrap();
+        assert_eq!(chain.current, 0);
+        assert_eq!(chain.stats().sets, INITIAL_SETS - 1);
+
+        // The freed room in the first pool is used again without a new pool
+        let (_, pool) = chain.allocate(&(), &layouts(2)).unwrap();
+        assert_eq!(pool, 0);
+        assert_eq!(chain.stats().pools, 2);
+    }
+
+    #[test]
+    fn reset_keeps_the_pools() {
+        let mut chain = chain();
+
+        chain.allocate(&(), &layouts(INITIAL_SETS as usize + 1)).unwrap();
+        chain.reset(&()).unwrap();
+
+        let stats = chain.stats();
+        assert_eq!(chain.current, 0);
+        assert_eq!(stats.sets, 0);
+        assert_eq!(stats.pools, 2);
+
+        let (_, pool) = chain.allocate(&(), &layouts(1)).unwrap();
+        assert_eq!(pool, 0);
+    }
+}
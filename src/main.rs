@@ -45,6 +45,15 @@ pub use renderer::*;
 mod descriptor_manager;
 pub use descriptor_manager::*;
 
+mod uniform_ring;
+pub use uniform_ring::*;
+
+mod pass_stats;
+pub use pass_stats::*;
+
+mod shader_reload;
+pub use shader_reload::*;
+
 
 fn main() {
 
@@ -84,6 +93,9 @@ fn main() {
                         PhysicalKey::Code(winit::keyboard::KeyCode::Escape) => {
                             active_ev.exit();
                         }
+                        PhysicalKey::Code(winit::keyboard::KeyCode::KeyV) if event.state.is_pressed() && !event.repeat => {
+                            world.toggle_vsync();
+                        }
                         _ => {}
                     }
                 },
@@ -91,6 +103,9 @@ fn main() {
                     let (width, height) = (size.width, size.height);
                     world.reszie(width, height);
                 }
+                winit::event::WindowEvent::CursorMoved { position, .. } => {
+                    world.set_mouse_pos(position.x as f32, position.y as f32);
+                }
                 winit::event::WindowEvent::RedrawRequested => {
                     world.draw_frame();
                 }
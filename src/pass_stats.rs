@@ -0,0 +1,235 @@
+use ash::vk;
+use log::warn;
+
+use crate::{Device, QueryPool, QueryPoolBuilder, VulkanResult, PASS_STATISTICS};
+
+/// GPU statistics of one pass, read back once its frame slot comes around again
+#[derive(Clone, Debug, Default)]
+pub struct PassStats {
+    pub name: String,
+    /// `None` when the queue doesn't support timestamps
+    pub gpu_time_ms: Option<f64>,
+    pub vertex_invocations: u64,
+    pub clipping_primitives: u64,
+    pub fragment_invocations: u64,
+    pub compute_invocations: u64,
+    /// Samples that passed per occlusion query the pass asked for
+    pub occlusion: Vec<u64>,
+}
+
+/// Query pools of one frame slot, reset and reused every time the slot is recorded
+struct SlotQueries {
+    timestamps: Option<QueryPool>,
+    statistics: Option<QueryPool>,
+    occlusion: Option<QueryPool>,
+    recorded: bool,
+}
+
+/// Per-pass timestamps, pipeline statistics and occlusion queries of a `RenderGraph`
+pub(crate) struct PassQueries {
+    slots: Vec<SlotQueries>,
+    /// First occlusion query and count of every pass
+    occlusion: Vec<(u32, u32)>,
+    timestamp_period: f64,
+    /// `VkQueueFamilyProperties::timestampValidBits` of the queue the passes run on
+    timestamp_valid_bits: u32,
+    stats: Vec<PassStats>,
+}
+
+impl PassQueries {
+
+    pub(crate) fn new(device: &Device, frames: usize, names: Vec<String>, occlusion_counts: &[u32], timestamp_period: Option<f32>, timestamp_valid_bits: u32) -> VulkanResult<Self> {
+
+        let passes = names.len() as u32;
+
+        let mut occlusion = Vec::with_capacity(occlusion_counts.len());
+        let mut total = 0;
+
+        for &count in occlusion_counts {
+            occlusion.push((total, count));
+            total += count;
+        }
+
+        let mut slots = Vec::with_capacity(frames);
+
+        for _ in 0..frames {
+
+            let timestamps = match timestamp_period {
+                Some(_) => Some(QueryPoolBuilder::timestamps(device, passes * 2).build()?),
+                None => None,
+            };
+
+            let statistics = match device.features().pipeline_statistics_query {
+                true => Some(QueryPoolBuilder::pipeline_statistics(device, passes, PASS_STATISTICS).build()?),
+                false => None,
+            };
+
+            let occlusion = match total {
+                0 => None,
+                total => Some(QueryPoolBuilder::occlusion(device, total).build()?),
+            };
+
+            slots.push(SlotQueries { timestamps, statistics, occlusion, recorded: false });
+        }
+
+        let stats = names.into_iter()
+            .zip(occlusion_counts)
+            .map(|(name, &count)| PassStats {
+                name,
+                occlusion: vec![0; count as usize],
+                ..Default::default()
+            })
+            .collect();
+
+        Ok(Self {
+            slots,
+            occlusion,
+            timestamp_period: timestamp_period.unwrap_or(0.0) as f64,
+            timestamp_valid_bits,
+            stats,
+        })
+    }
+
+    pub(crate) fn stats(&self) -> &[PassStats] {
+        &self.stats
+    }
+
+    /// Read what `slot` recorded the last time, call once the slot's fence has signaled
+    pub(crate) fn collect(&mut self, device: &Device, slot: usize) {
+        puffin::profile_scope!("PassQueries::collect");
+
+        let queries = &mut self.slots[slot];
+
+        if !queries.recorded {
+            return;
+        }
+
+        queries.recorded = false;
+
+        let passes = self.stats.len() as u32;
+
+        if let Some(pool) = &queries.timestamps {
+            match pool.results(device, 0, passes * 2) {
+                Ok(Some(ticks)) => {
+                    for (stats, ticks) in self.stats.iter_mut().zip(ticks.chunks_exact(2)) {
+                        let ns = tick_delta(ticks[0], ticks[1], self.timestamp_valid_bits) as f64 * self.timestamp_period;
+                        stats.gpu_time_ms = Some(ns / 1_000_000.0);
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => warn!("Failed to read pass timestamps: {}", e),
+            }
+        }
+
+        if let Some(pool) = &queries.statistics {
+            match pool.results(device, 0, passes) {
+                Ok(Some(values)) => {
+                    // Ordered by flag bit: vertex, clipping, fragment, compute
+                    for (stats, values) in self.stats.iter_mut().zip(values.chunks_exact(4)) {
+                        stats.vertex_invocations = values[0];
+                        stats.clipping_primitives = values[1];
+                        stats.fragment_invocations = values[2];
+                        stats.compute_invocations = values[3];
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => warn!("Failed to read pass statistics: {}", e),
+            }
+        }
+
+        if let Some(pool) = &queries.occlusion {
+            // Passes skipped this frame leave their queries unavailable, so read each pass on its own
+            for (stats, &(first, count)) in self.stats.iter_mut().zip(&self.occlusion) {
+
+                if count == 0 {
+                    continue;
+                }
+
+                match pool.available_results(device, first, count) {
+                    Ok(samples) => {
+                        for (value, sample) in stats.occlusion.iter_mut().zip(samples) {
+                            if let Some(sample) = sample {
+                                *value = sample[0];
+                            }
+                        }
+                    }
+                    Err(e) => warn!("Failed to read occlusion queries of {}: {}", stats.name, e),
+                }
+            }
+        }
+    }
+
+    /// Reset the pass' queries, then start its timestamp and statistics, outside of any render pass
+    pub(crate) fn begin_pass(&mut self, device: &Device, cmd: vk::CommandBuffer, slot: usize, pass: usize) {
+
+        let queries = &mut self.slots[slot];
+        let index = pass as u32;
+        queries.recorded = true;
+
+        if let Some(pool) = &queries.occlusion {
+            let (first, count) = self.occlusion[pass];
+
+            if count > 0 {
+                pool.reset(device, cmd, first, count);
+            }
+        }
+
+        if let Some(pool) = &queries.timestamps {
+            pool.reset(device, cmd, index * 2, 2);
+            pool.write_timestamp(device, cmd, vk::PipelineStageFlags::TOP_OF_PIPE, index * 2);
+        }
+
+        if let Some(pool) = &queries.statistics {
+            pool.reset(device, cmd, index, 1);
+            pool.begin(device, cmd, index);
+        }
+    }
+
+    /// Stop the pass' statistics and timestamp, after its render pass ended
+    pub(crate) fn end_pass(&self, device: &Device, cmd: vk::CommandBuffer, slot: usize, pass: usize) {
+
+        let queries = &self.slots[slot];
+        let index = pass as u32;
+
+        if let Some(pool) = &queries.statistics {
+            pool.end(device, cmd, index);
+        }
+
+        if let Some(pool) = &queries.timestamps {
+            pool.write_timestamp(device, cmd, vk::PipelineStageFlags::BOTTOM_OF_PIPE, index * 2 + 1);
+        }
+    }
+
+    /// Pool and range of the pass' occlusion queries
+    pub(crate) fn occlusion(&self, slot: usize, pass: usize) -> Option<(vk::QueryPool, u32, u32)> {
+        let pool = self.slots[slot].occlusion.as_ref()?;
+        let (first, count) = self.occlusion[pass];
+        Some((pool.raw, first, count))
+    }
+}
+
+/// Ticks between two timestamps, ignoring the bits the queue leaves undefined and wrapping with the counter
+fn tick_delta(start: u64, end: u64, valid_bits: u32) -> u64 {
+    let mask = match valid_bits {
+        64.. => u64::MAX,
+        bits => (1 << bits) - 1,
+    };
+
+    (end & mask).wrapping_sub(start & mask) & mask
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tick_delta_ignores_invalid_bits() {
+        assert_eq!(tick_delta(0xdead_0000_0010, 0xbeef_0000_0030, 32), 0x20);
+        assert_eq!(tick_delta(10, 30, 64), 20);
+    }
+
+    #[test]
+    fn tick_delta_wraps_with_the_counter() {
+        assert_eq!(tick_delta(0xffff_fff0, 0x10, 32), 0x20);
+    }
+}
@@ -2,44 +2,32 @@ use crate::GraphicsDevice;
 use ash::vk;
 
 pub struct QueuePool {
-    queues: Vec<Vec<vk::Queue>>,
+    /// Family index and first queue of every family the device was created with
+    queues: Vec<(u32, vk::Queue)>,
     props: Vec<vk::QueueFamilyProperties>
 }
 
 impl QueuePool {
     pub fn get_queue(&self, flags: vk::QueueFlags) -> Option<vk::Queue> {
-
-        let mut family_index = None;
-
-        for (index, prop) in self.props.iter().enumerate() {
-            if prop.queue_flags.contains(flags) {
-                family_index = Some(index);
-            }
-        }
-
-        if let Some(index) = family_index {
-            return Some(self.queues[index][0])
-        }
-
-        None
+        self.queues
+            .iter()
+            .find(|(family, _)| self.props[*family as usize].queue_flags.contains(flags))
+            .map(|(_, queue)| *queue)
     }
 }
 
 impl QueuePool {
-    pub fn new(device: &ash::Device, props: &Vec<vk::QueueFamilyProperties>) -> Self {
+    /// `families` must be the families passed to `vkCreateDevice`, each with at least one queue
+    pub fn new(device: &ash::Device, families: &[u32], props: &[vk::QueueFamilyProperties]) -> Self {
 
         let mut queues = vec![];
 
-        for (i, prop) in props.iter().enumerate() {
-            let mut v = vec![];
-            for j in 0..prop.queue_count {
-                let queue = unsafe { device.get_device_queue(i as u32, j) };
-                log::debug!("Queue Family: {} Queue: {} Flags: {:?}", i, j, prop.queue_flags);
-                v.push(queue);
-            }
-            queues.push(v);
+        for &family in families {
+            let queue = unsafe { device.get_device_queue(family, 0) };
+            log::debug!("Queue Family: {} Flags: {:?}", family, props[family as usize].queue_flags);
+            queues.push((family, queue));
         }
 
-        QueuePool { queues, props: props.clone()  }
+        QueuePool { queues, props: props.to_vec() }
     }
-}
\ No newline at end of file
+}
@@ -1,11 +1,17 @@
-use log::info;
-use crate::{App, AppBuilder, Device, DeviceBuilder, Fence, FenceBuilder, FrameBuffer, FrameBufferBuilder, Image, ImageBuilder, ImageView, ImageViewBuilder, Instance, InstanceBuilder, QueuePool, RenderPass, RenderPassBuilder, Semaphore, SemaphoreBuilder, Surface, SurfaceBuilder, Swapchain, SwapchainBuilder, VulkanResult};
+use std::path::PathBuf;
+
+use log::{info, warn};
+use crate::{App, AppBuilder, Device, DeviceBuilder, DeviceSelector, Fence, FenceBuilder, FrameBuffer, FrameBufferBuilder, Image, ImageBuilder, ImageView, ImageViewBuilder, Instance, InstanceBuilder, PhysicalDevice, PhysicalDeviceBuilder, PipelineCache, PipelineCacheBuilder, PipelineTarget, default_cache_dir, QueuePool, RenderPass, RenderPassBuilder, Semaphore, SemaphoreBuilder, Surface, SurfaceBuilder, Swapchain, SwapchainBuilder, SwapchainConfig, SwapchainError, SurfaceError, ValidationConfig, DebugPolicy, VulkanError, VulkanResult};
 use ash::vk;
+use winit::raw_window_handle::HasRawDisplayHandle;
+
 
+/// Frames the CPU may record ahead of the GPU, independent of the swapchain image count
+/// so per-slot resources survive swapchain recreation
+pub const MAX_FRAMES_IN_FLIGHT: usize = 2;
 
 pub struct FrameSync {
     pub image_available: Semaphore,
-    pub render_finished: Semaphore,
     pub in_flight_fence: Fence
 }
 
@@ -13,26 +19,84 @@ impl FrameSync {
     pub fn new(device: &Device) -> VulkanResult<FrameSync> {
         Ok(FrameSync { 
             image_available: SemaphoreBuilder::new(device).build()?,
-            render_finished: SemaphoreBuilder::new(device).build()?,
             in_flight_fence: FenceBuilder::signaled(device).build()?
         })
     }
 }
 pub struct WindowManager {
     pub(crate) resolution: vk::Extent2D,
+    /// One per frame slot
     pub(crate) frame_sync: Vec<FrameSync>,
+    /// One per swapchain image, presentation of an image waits on its semaphore
+    pub(crate) render_finished: Vec<Semaphore>,
+    /// Empty when rendering dynamically
     pub(crate) frame_buffers: Vec<FrameBuffer>,
+    pub(crate) images: Vec<vk::Image>,
     pub(crate) image_views: Vec<ImageView>,
     pub(crate) current_frame: usize,
+    pub(crate) color_format: vk::Format,
+    pub(crate) depth_format: vk::Format,
     pub(crate) depth_image: Image,
     pub(crate) depth_view: ImageView,
-    pub(crate) render_pass: RenderPass,
-    pub(crate) surface: Surface,
+    /// `None` when the device supports dynamic rendering
+    pub(crate) render_pass: Option<RenderPass>,
     pub(crate) swapchain: Swapchain,
+    /// Preferences the swapchain is negotiated against on every recreation
+    pub(crate) config: SwapchainConfig,
 }
 
 impl WindowManager {
-    pub fn resize(&mut self, device: &GraphicsDevice, width: u32, height: u32) {
+
+    pub fn dynamic_rendering(&self) -> bool {
+        self.render_pass.is_none()
+    }
+
+    /// Number of swapchain images, may change when the swapchain is recreated
+    pub fn image_count(&self) -> usize {
+        self.image_views.len()
+    }
+
+    /// Target for pipelines drawing into the swapchain
+    pub fn pipeline_target(&self) -> PipelineTarget {
+        match &self.render_pass {
+            Some(render_pass) => PipelineTarget::RenderPass(render_pass.raw),
+            None => PipelineTarget::Rendering {
+                color_formats: vec![self.color_format],
+                depth_format: Some(self.depth_format)
+            },
+        }
+    }
+
+    /// Names shown in validation messages, debuggers and the leak report
+    fn name_objects(&self, device: &Device) {
+        device.set_object_name(self.swapchain.raw, "Swapchain");
+        device.set_object_name(self.depth_image.raw, "Depth image");
+        device.set_object_name(self.depth_view.raw, "Depth view");
+
+        if let Some(render_pass) = &self.render_pass {
+            device.set_object_name(render_pass.raw, "Swapchain render pass");
+        }
+
+        for (i, view) in self.image_views.iter().enumerate() {
+            device.set_object_name(view.raw, &format!("Swapchain view {}", i));
+        }
+
+        for (i, frame_buffer) in self.frame_buffers.iter().enumerate() {
+            device.set_object_name(frame_buffer.raw, &format!("Swapchain framebuffer {}", i));
+        }
+
+        for (i, sync) in self.frame_sync.iter().enumerate() {
+            device.set_object_name(sync.image_available.raw, &format!("Image available {}", i));
+            device.set_object_name(sync.in_flight_fence.raw, &format!("In flight fence {}", i));
+        }
+
+        for (i, semaphore) in self.render_finished.iter().enumerate() {
+            device.set_object_name(semaphore.raw, &format!("Render finished {}", i));
+        }
+    }
+
+    /// Recreate the swapchain and its attachments, keeping the surface format it was created with
+    pub fn resize(&mut self, device: &GraphicsDevice, width: u32, height: u32) -> VulkanResult<()> {
 
         info!("New size: {:?}", (width, height));
 
@@ -40,67 +104,88 @@ impl WindowManager {
             let _ = device.device_wait_idle();
         }
 
-        let caps = self
-            .surface
-            .get_physical_device_surface_capabilities(&device.phys_dev);
+        // The render pass, offscreen targets and pipelines were built for the first format
+        let format = self.swapchain.format();
+        let config = SwapchainConfig { formats: vec![format], ..self.config.clone() };
+
+        let swapchain = SwapchainBuilder::negotiate(
+                &device.instance,
+                &device.device,
+                &device.surface,
+                device.phys_dev.raw,
+                &config,
+                vk::Extent2D { width, height }
+            )?;
+
+        if swapchain.surface_format() != format {
+            return Err(VulkanError::Swapchain(SwapchainError::FormatUnsupported(format)));
+        }
 
-        let swapchain = SwapchainBuilder::default(&device.instance, &device.device, &self.surface)
+        let swapchain = swapchain
             .old_swapchain(self.swapchain.raw)
-            .extent(caps.current_extent)
-            .format(vk::Format::R8G8B8A8_SRGB)
-            .build()
-            .unwrap();
+            .build()?;
+
+        let extent = swapchain.extent();
 
-        let depth_image = ImageBuilder::depth(&device, vk::Format::D32_SFLOAT, caps.current_extent).build().unwrap();
-        let depth_view = ImageViewBuilder::depth(&device, vk::Format::D32_SFLOAT, depth_image.raw).build().unwrap();
+        let depth_image = ImageBuilder::depth(device, self.depth_format, extent).build()?;
+        let depth_view = ImageViewBuilder::depth(device, self.depth_format, depth_image.raw).build()?;
+
+        let images = swapchain.get_swapchain_images()?;
 
         let mut image_views = vec![];
-        for i in swapchain.get_swapchain_images().unwrap() {
-            let image_view = ImageViewBuilder::new_2d(&device, vk::Format::R8G8B8A8_SRGB, i).build().unwrap();
+        for &i in &images {
+            let image_view = ImageViewBuilder::new_2d(device, self.color_format, i).build()?;
             image_views.push(image_view);
         }
 
         let mut frame_buffers = vec![];
 
-        for i in &image_views {
+        if let Some(render_pass) = &self.render_pass {
+            for i in &image_views {
 
-            let frame_buffer = FrameBufferBuilder::new(&device, self.render_pass.raw)
-                .add_attachment(i.raw)
-                .add_attachment(depth_view.raw)
-                .extent(caps.current_extent)
-                .layers(1)
-                .build()
-                .unwrap();
+                let frame_buffer = FrameBufferBuilder::new(device, render_pass.raw)
+                    .add_attachment(i.raw)
+                    .add_attachment(depth_view.raw)
+                    .extent(extent)
+                    .layers(1)
+                    .build()?;
 
-            frame_buffers.push(frame_buffer);
+                frame_buffers.push(frame_buffer);
+            }
         }
-        
-        self.depth_image.destory(&device);
-        self.depth_image = depth_image;
-
-        self.depth_view.destroy(&device);
-        self.depth_view = depth_view;
 
-        for i in &self.image_views {
-            i.destroy(&device);
+        if self.render_finished.len() != images.len() {
+            self.render_finished = render_finished_semaphores(device, images.len())?;
         }
-        self.image_views = image_views;
-
 
+        // Old objects go to the deletion queue
+        self.depth_image = depth_image;
+        self.depth_view = depth_view;
+        self.image_views = image_views;
+        self.images = images;
         self.frame_buffers = frame_buffers;
-        self.resolution = caps.current_extent;
-
-        self.swapchain.destroy();
+        self.resolution = extent;
         self.swapchain = swapchain;
+
+        self.name_objects(device);
+
+        Ok(())
     }
 }
 
+fn render_finished_semaphores(device: &Device, count: usize) -> VulkanResult<Vec<Semaphore>> {
+    (0..count).map(|_| SemaphoreBuilder::new(device).build()).collect()
+}
+
+/// Fields are dropped in declaration order: device before surface, surface before instance
 pub struct GraphicsDevice {
-    app: App,
+    pub(crate) device: Device,
     pub(crate) queue_pool: QueuePool,
-    phys_dev: vk::PhysicalDevice,
+    pub(crate) phys_dev: PhysicalDevice,
+    pub(crate) surface: Surface,
     instance: Instance,
-    pub(crate) device: Device,
+    /// Only held so the entry outlives the instance
+    _app: App,
 }
 
 impl std::ops::Deref for GraphicsDevice {
@@ -110,106 +195,210 @@ impl std::ops::Deref for GraphicsDevice {
     }
 }
 
+/// Engine level settings applied when creating a [`RenderContext`]
+#[derive(Default)]
+pub struct RenderConfig {
+    /// Force a physical device instead of the best ranked one
+    pub device: Option<DeviceSelector>,
+    /// Where the pipeline cache is kept, the per-user cache directory by default
+    pub cache_dir: Option<PathBuf>,
+    pub swapchain: SwapchainConfig,
+    pub validation: ValidationConfig,
+    pub debug: DebugPolicy,
+}
 
+/// Window resources are dropped first, their destruction is deferred until the device is dropped.
+/// With `runtime-check` the device then reports every object that is still alive
 pub struct RenderContext {
     pub(crate) window: WindowManager,
+    pub(crate) pipeline_cache: PipelineCache,
     pub(crate) device: GraphicsDevice,
 }
 
+impl Drop for RenderContext {
+    fn drop(&mut self) {
+        if let Err(e) = self.pipeline_cache.save(&self.device) {
+            warn!("Failed to save pipeline cache: {}", e);
+        }
+    }
+}
+
 impl RenderContext {
+
+    /// Wait until the current frame slot is free again and destroy the objects dropped
+    /// the last time it was recorded, returns the slot index
+    pub fn begin_frame(&mut self) -> usize {
+        puffin::profile_scope!("begin_frame");
+
+        let slot = self.window.current_frame % MAX_FRAMES_IN_FLIGHT;
+        let fence = &self.window.frame_sync[slot].in_flight_fence;
+
+        unsafe {
+            self.device.wait_for_fences(&[fence.raw], true, u64::MAX).expect("Error wait for fences");
+            self.device.reset_fences(&[fence.raw]).expect("Error wait for fences");
+        }
+
+        self.device.device.begin_frame(slot);
+
+        // The slot's previous submit has finished, so its validation errors were reported by now
+        if let Some(debug) = self.device.instance.debug_callback() {
+            let errors = debug.end_frame();
+
+            if errors > 0 {
+                warn!("{} Vulkan validation errors since the last frame", errors);
+            }
+        }
+
+        slot
+    }
+
+    /// Recreate the swapchain with FIFO when `vsync` is set, otherwise the first supported
+    /// of the configured present modes
+    pub fn set_vsync(&mut self, vsync: bool) -> VulkanResult<()> {
+
+        if self.window.config.vsync == vsync {
+            return Ok(());
+        }
+
+        self.window.config.vsync = vsync;
+
+        let resolution = self.window.resolution;
+        self.window.resize(&self.device, resolution.width, resolution.height)
+    }
+
+    pub fn vsync(&self) -> bool {
+        self.window.config.vsync
+    }
+
+    /// Engine-wide cache every pipeline should be built with
+    pub fn pipeline_cache(&self) -> vk::PipelineCache {
+        self.pipeline_cache.raw
+    }
+
     pub fn new(window: &winit::window::Window) -> VulkanResult<Self> {
+        Self::with_config(window, RenderConfig::default())
+    }
+
+    pub fn with_config(window: &winit::window::Window, config: RenderConfig) -> VulkanResult<Self> {
 
         let app = AppBuilder::default().build()?;
-        let instance = InstanceBuilder::default(&app).build()?;
+        let instance = InstanceBuilder::default(&app)
+            .display_handle(window.raw_display_handle().map_err(|e| {
+                VulkanError::Surface(SurfaceError::WindowHandle(e.to_string()))
+            })?)
+            .validation(config.validation)
+            .debug_policy(config.debug)
+            .build()?;
         let surface = SurfaceBuilder::new(&app, &instance, window).build()?;
 
-        let phys_dev = unsafe { instance.raw.enumerate_physical_devices().unwrap() };
-        let phys_dev = phys_dev[0];
+        let phys_dev = PhysicalDeviceBuilder::default(&instance)
+            .surface(&surface)
+            .select(config.device)
+            .build()?;
 
         let device = DeviceBuilder::default(&instance, &phys_dev).build()?;
 
-        let caps = surface.get_physical_device_surface_capabilities(&phys_dev);
+        let size = window.inner_size();
 
-        let swapchain = SwapchainBuilder::default(&instance, &device, &surface)
-            .extent(caps.current_extent)
-            .format(vk::Format::R8G8B8A8_SRGB)
+        let swapchain = SwapchainBuilder::negotiate(
+                &instance,
+                &device,
+                &surface,
+                phys_dev.raw,
+                &config.swapchain,
+                vk::Extent2D { width: size.width, height: size.height }
+            )?
             .build()?;
 
-        let render_pass = RenderPassBuilder::default(&device, vk::Format::R8G8B8A8_SRGB, vk::Format::D32_SFLOAT).build()?;
+        let extent = swapchain.extent();
+        let color_format = swapchain.format().format;
+        let depth_format = vk::Format::D32_SFLOAT;
+
+        info!("Swapchain {:?} {:?}, {:?}", swapchain.format().format, swapchain.format().color_space, swapchain.present_mode());
 
-        let depth_image = ImageBuilder::depth(&device, vk::Format::D32_SFLOAT, caps.current_extent).build()?;
-        let depth_view = ImageViewBuilder::depth(&device, vk::Format::D32_SFLOAT, depth_image.raw).build()?;
+        // Vulkan 1.0 devices and devices without the extension keep the render pass path
+        let render_pass = if device.features().dynamic_rendering {
+            info!("Use dynamic rendering");
+            None
+        } else {
+            Some(RenderPassBuilder::default(&device, color_format, depth_format).build()?)
+        };
 
+        let depth_image = ImageBuilder::depth(&device, depth_format, extent).build()?;
+        let depth_view = ImageViewBuilder::depth(&device, depth_format, depth_image.raw).build()?;
+
+        let images = swapchain.get_swapchain_images()?;
         let mut image_views = vec![];
 
-        for i in swapchain.get_swapchain_images().unwrap() {
-            let image_view = ImageViewBuilder::new_2d(&device, vk::Format::R8G8B8A8_SRGB, i).build()?;
+        for &i in &images {
+            let image_view = ImageViewBuilder::new_2d(&device, color_format, i).build()?;
             image_views.push(image_view);
         }
 
         let mut frame_buffers = vec![];
 
-        for i in &image_views {
+        if let Some(render_pass) = &render_pass {
+            for i in &image_views {
+
+                let frame_buffer = FrameBufferBuilder::new(&device, render_pass.raw)
+                    .add_attachment(i.raw)
+                    .add_attachment(depth_view.raw)
+                    .extent(extent)
+                    .layers(1)
+                    .build()?;
+
+                frame_buffers.push(frame_buffer);
+            }
+        }
 
-            let frame_buffer = FrameBufferBuilder::new(&device, render_pass.raw)
-                .add_attachment(i.raw)
-                .add_attachment(depth_view.raw)
-                .extent(caps.current_extent)
-                .layers(1)
-                .build()?;
+        let mut pipeline_cache = PipelineCacheBuilder::new(&device, &phys_dev);
 
-            frame_buffers.push(frame_buffer);
+        match config.cache_dir.or_else(default_cache_dir) {
+            Some(dir) => pipeline_cache = pipeline_cache.path(dir.join("pipeline_cache.bin")),
+            None => warn!("No cache directory, pipelines are compiled on every start"),
         }
 
-        let pool = QueuePool::new(&device.raw, &device.queue_family_props);
+        let pipeline_cache = pipeline_cache.build()?;
+
+        let pool = QueuePool::new(&device.raw, &[device.queue_family()], &device.queue_family_props);
         let mut frame_sync = vec![];
 
-        for _ in 0..frame_buffers.len() {
+        for _ in 0..MAX_FRAMES_IN_FLIGHT {
             frame_sync.push(FrameSync::new(&device)?);
         }
 
+        let render_finished = render_finished_semaphores(&device, images.len())?;
+
+        let window = WindowManager {
+            resolution: extent,
+            frame_sync,
+            render_finished,
+            images,
+            image_views,
+            color_format,
+            depth_format,
+            depth_image: depth_image,
+            frame_buffers,
+            depth_view,
+            current_frame: 0,
+            swapchain, 
+            config: config.swapchain,
+            render_pass 
+        };
+
+        window.name_objects(&device);
+
         Ok(Self {
-            window: WindowManager { 
-                resolution: caps.current_extent,
-                frame_sync,
-                image_views,
-                depth_image: depth_image,
-                frame_buffers,
-                depth_view,
-                current_frame: 0,
-                surface, 
-                swapchain, 
-                render_pass 
-            },
+            window,
+            pipeline_cache,
             device: GraphicsDevice {
-                app,
-                instance,
                 device,
                 queue_pool: pool,
                 phys_dev,
+                surface,
+                instance,
+                _app: app,
             },
         })
     }
 }
-
-impl Drop for RenderContext {
-    fn drop(&mut self) {
-
-        self.window.swapchain.destroy();
-        // self.device.device.destroy_render_pass(&self.window.render_pass);
-        self.window.depth_image.destory(&self.device);
-        self.window.depth_view.destroy(&self.device);
-        
-        for i in &self.window.frame_buffers {
-            i.destroy(&self.device);
-        }
-
-        for i in &self.window.image_views {
-            i.destroy(&self.device);
-        }
-        
-        self.device.device.destroy();
-        self.window.surface.destroy();
-        self.device.instance.destroy();
-    }
-}
@@ -1,7 +1,8 @@
 
-use std::sync::Arc;
+use std::{cell::{RefCell, RefMut}, path::{Path, PathBuf}, sync::Arc};
 
 use ash::vk;
+use log::{error, info};
 use slotmap::{SecondaryMap, SlotMap, new_key_type};
 
 new_key_type! {
@@ -12,70 +13,64 @@ new_key_type! {
     pub struct DescriptorSetHandle;
 }
 
-use crate::{CommandPool, DescriptorManager, DescriptorSetLayout, FrameBufferBuilder, Image, ImageBuilder, ImageView, ImageViewBuilder, RenderContext, Renderable, Sampler, SamplerBuilder, Scene, resources::*};
-use crate::core::{CommandPoolBuilder, Device, FrameBuffer, GraphicsPipeline};
+use crate::{Access, BarrierBuilder, Bindless, CommandRecorder, PassQueries, PassStats, VulkanResult, CommandPool, DescriptorManager, DescriptorSetLayout, DescriptorWriter, FrameBufferBuilder, Image, ImageBuilder, ImageView, ImageViewBuilder, RenderContext, Renderable, RenderingBuilder, SubmitBuilder, SamplerDesc, Scene, MAX_FRAMES_IN_FLIGHT, resources::*};
+use crate::core::{CommandPoolBuilder, Device, FrameBuffer, GraphicsPipeline, PipelineTarget};
 
 type Execute = dyn Fn(&PassContext, &[Renderable]);
 
+/// Rebuilds a pass pipeline after one of its shaders was recompiled,
+/// through the resource manager against the current pipeline cache, pass layout and swapchain target
+pub type PipelineFactory = dyn Fn(&Device, vk::PipelineCache, &mut ResourceManager, vk::PipelineLayout, &PipelineTarget) -> VulkanResult<Arc<GraphicsPipeline>>;
+
 pub struct PassContext<'a> {
-    sets: Vec<vk::DescriptorSet>,
+    /// Set index and set bound with the pipeline
+    sets: Vec<(u32, vk::DescriptorSet)>,
     resolution: vk::Extent2D,
-    device: &'a Device,
     resources: Arc<RenderGraphResources>,
     s: Arc<ResourceManager>,
-    cmd: vk::CommandBuffer,
+    recorder: RefCell<CommandRecorder<'a>>,
     pipeline: Option<vk::Pipeline>,
-    layout: Option<LayoutHandle>
+    layout: Option<LayoutHandle>,
+    /// Pool, first query and count of the pass' occlusion queries
+    occlusion: Option<(vk::QueryPool, u32, u32)>,
 }
 
 impl<'a> PassContext<'a> {
 
-    pub fn bind_pipeline(&self) {
-
-        let pipeline = self.pipeline.expect("Missing Pipeline");
-        let layout = self.s.get_layout(self.layout.unwrap()).unwrap();
-
-        unsafe {
-
-            if !self.sets.is_empty() {
-                self.device.cmd_bind_descriptor_sets(
-                    self.cmd, 
-                    vk::PipelineBindPoint::GRAPHICS, 
-                    layout.raw, 
-                    0, 
-                    &self.sets, 
-                    &[]
-                );
-            }
+    /// Count the samples of the following draws into occlusion query `index`
+    /// of the ones requested with `PassBuilder::occlusion_queries`
+    pub fn begin_occlusion(&self, index: u32) {
+        let (pool, first, count) = self.occlusion.expect("Pass has no occlusion queries");
+        assert!(index < count, "Occlusion query {} out of {}", index, count);
+        self.recorder.borrow_mut().begin_query(pool, first + index);
+    }
 
-            self.device.cmd_bind_pipeline(
-                self.cmd, 
-                vk::PipelineBindPoint::GRAPHICS, 
-                pipeline
-            );
+    pub fn end_occlusion(&self, index: u32) {
+        let (pool, first, _) = self.occlusion.expect("Pass has no occlusion queries");
+        self.recorder.borrow_mut().end_query(pool, first + index);
+    }
 
-            let viewport = vk::Viewport::default()
-                .height(self.resolution.height as f32)
-                .width(self.resolution.width as f32)
-                .x(0.0)
-                .y(0.0)
-                .max_depth(1.0)
-                .min_depth(0.0);
+    /// Recorder of the pass' command buffer, for commands `PassContext` doesn't wrap
+    pub fn recorder(&self) -> RefMut<'_, CommandRecorder<'a>> {
+        self.recorder.borrow_mut()
+    }
 
-            self.device.cmd_set_viewport(self.cmd, 0, &[viewport]);
+    pub fn bind_pipeline(&self) {
 
-            let scissor = vk::Rect2D::default()
-                .extent(self.resolution)
-                .offset(vk::Offset2D { x: 0, y: 0 });
+        let pipeline = self.pipeline.expect("Missing Pipeline");
+        let layout = self.s.get_layout(self.layout.unwrap()).unwrap();
+        let mut recorder = self.recorder.borrow_mut();
 
-            self.device.cmd_set_scissor(self.cmd, 0, &[scissor]);
+        for &(index, set) in &self.sets {
+            recorder.bind_descriptor_sets(vk::PipelineBindPoint::GRAPHICS, layout.raw, index, &[set], &[]);
         }
+
+        recorder.bind_pipeline(vk::PipelineBindPoint::GRAPHICS, pipeline, layout.raw);
+        recorder.set_full_viewport(self.resolution);
     }
 
     pub fn draw(&self, vertex_count: u32) {
-        unsafe {
-            self.device.cmd_draw(self.cmd, vertex_count, 1, 0, 0);
-        }
+        self.recorder.borrow_mut().draw(vertex_count, 1, 0, 0).expect("Invalid draw");
     }
 }
 
@@ -87,22 +82,27 @@ pub struct FrameDesc {
 }
 
 pub struct GraphFrameBuffer {
-    frame: FrameBuffer,
+    /// Only created for the render pass path
+    frame: Option<FrameBuffer>,
     image_view: ImageView,
-    sampler: Sampler,
+    /// Owned by the `ResourceManager` cache
+    sampler: SamplerHandle,
     image: Image
 }
 
 pub struct RenderGraphResources {
     frame_buffer: SecondaryMap<FrameBufferHandle, GraphFrameBuffer>,
-    set: SecondaryMap<DescriptorSetHandle, vk::DescriptorSet>
+    set: SecondaryMap<DescriptorSetHandle, vk::DescriptorSet>,
+    /// Kept alive as long as sets allocated from them are bound
+    set_layout: SecondaryMap<DescriptorSetHandle, DescriptorSetLayout>
 }
 
 impl RenderGraphResources {
     pub fn new() -> Self {
         RenderGraphResources { 
             frame_buffer: SecondaryMap::new(),
-            set: SecondaryMap::new()
+            set: SecondaryMap::new(),
+            set_layout: SecondaryMap::new()
         }
     }
 }
@@ -112,20 +112,37 @@ pub struct RenderGraph {
     pool: CommandPool,
     resources: Arc<RenderGraphResources>,
     passes: Vec<Pass>,
+    queries: PassQueries,
     cmd_bufs: Vec<Vec<vk::CommandBuffer>>
 }
 
 impl RenderGraph {
-    pub fn execute(&self, ctx: &mut RenderContext, scene: &Scene, s: Arc<ResourceManager>) {
+
+    /// Set allocated for `handle`, to fill with a `DescriptorWriter` while no frame uses it
+    pub fn descriptor_set(&self, handle: DescriptorSetHandle) -> Option<vk::DescriptorSet> {
+        self.resources.set.get(handle).copied()
+    }
+
+    /// GPU time and statistics of every pass, as many frames old as there are frame slots
+    /// Whether any pass binds the global bindless set
+    pub fn uses_bindless(&self) -> bool {
+        self.passes.iter().any(|pass| pass.bindless)
+    }
+
+    pub fn pass_stats(&self) -> &[PassStats] {
+        self.queries.stats()
+    }
+
+    /// Record and submit every pass into frame slot `slot`, which `RenderContext::begin_frame` returned.
+    /// `globals` is the dynamic offset of the frame's `GlobalUniforms`
+    pub fn execute(&mut self, ctx: &mut RenderContext, slot: usize, bindless: Option<&Bindless>, globals: u32, scene: &Scene, s: Arc<ResourceManager>) {
 
         let window = &mut ctx.window;
-        let sync = &window.frame_sync[window.current_frame % window.frame_buffers.len()];
+        let sync = &window.frame_sync[slot];
         let device = &ctx.device.device;
 
-        unsafe {
-            device.wait_for_fences(&[sync.in_flight_fence.raw], true, u64::MAX).expect("Error wait for fences");
-            device.reset_fences(&[sync.in_flight_fence.raw]).expect("Error wait for fences");
-        }
+        // The slot's fence signaled, so its queries from last time are done
+        self.queries.collect(device, slot);
 
         let (image_index, _) = unsafe { 
             window.swapchain.loader.acquire_next_image(
@@ -136,7 +153,7 @@ impl RenderGraph {
             ).unwrap()
         };
 
-        let buffers = &self.cmd_bufs[image_index as usize];
+        let buffers = &self.cmd_bufs[slot];
 
         for &buffer in buffers {
             unsafe { 
@@ -149,12 +166,17 @@ impl RenderGraph {
             let pass = &self.passes[index];
             let cbuf = buffers[index];
 
-            let begin_info = vk::CommandBufferBeginInfo::default()
-                .flags(vk::CommandBufferUsageFlags::SIMULTANEOUS_USE);
+            let mut recorder = CommandRecorder::begin(device, cbuf, vk::CommandBufferUsageFlags::SIMULTANEOUS_USE)
+                .expect("Error begin command buffer");
 
-            unsafe { 
-                device.begin_command_buffer(cbuf, &begin_info).expect("Error begin command buffer") 
-            };
+            recorder.begin_label(&pass.name);
+            self.queries.begin_pass(device, cbuf, slot, index);
+
+            if pass.bindless {
+                let layout = s.get_layout(pass.layout).expect("Not found PipelineLayout");
+                let bindless = bindless.expect("Pass uses the bindless set, but descriptor indexing is unsupported");
+                bindless.bind(&mut recorder, vk::PipelineBindPoint::GRAPHICS, layout.raw, globals);
+            }
 
             let clear_values = [
                 vk::ClearValue {
@@ -170,108 +192,172 @@ impl RenderGraph {
                 },
             ];
 
-            let frame_buffer = match pass.target {
+            let (color_image, color_view) = match pass.target {
                 RenderTarget::FrameBuffer(handle) => {
-                    &self.resources.frame_buffer.get(handle).expect("Not found Frame Buffer").frame
+                    let frame = self.resources.frame_buffer.get(handle).expect("Not found Frame Buffer");
+                    (frame.image.raw, frame.image_view.raw)
                 }
                 RenderTarget::Swapchain => {
-                    &window.frame_buffers[image_index as usize]
+                    (window.images[image_index as usize], window.image_views[image_index as usize].raw)
                 }
             };
 
-            let render_pass_begin_info = vk::RenderPassBeginInfo::default()
-                .render_pass(window.render_pass.raw)
-                .framebuffer(frame_buffer.raw)
-                .render_area(vk::Rect2D {
-                    offset: vk::Offset2D { x: 0, y: 0 },
-                    extent: window.resolution,
-                })
-                .clear_values(&clear_values);
+            match &window.render_pass {
+                Some(render_pass) => {
 
-            unsafe { 
-                device.cmd_begin_render_pass(cbuf, &render_pass_begin_info,vk::SubpassContents::INLINE)
-            };
+                    let frame_buffer = match pass.target {
+                        RenderTarget::FrameBuffer(handle) => {
+                            self.resources.frame_buffer.get(handle).expect("Not found Frame Buffer").frame.as_ref().unwrap()
+                        }
+                        RenderTarget::Swapchain => {
+                            &window.frame_buffers[image_index as usize]
+                        }
+                    };
 
-            {
+                    let render_pass_begin_info = vk::RenderPassBeginInfo::default()
+                        .render_pass(render_pass.raw)
+                        .framebuffer(frame_buffer.raw)
+                        .render_area(vk::Rect2D {
+                            offset: vk::Offset2D { x: 0, y: 0 },
+                            extent: window.resolution,
+                        })
+                        .clear_values(&clear_values);
+
+                    recorder.begin_render_pass(&render_pass_begin_info, vk::SubpassContents::INLINE)
+                        .expect("Error begin render pass");
+                }
+                None => {
+                    // Same transitions the render pass does through its initial layouts
+                    let barriers = BarrierBuilder::new(device)
+                        .image(
+                            color_image,
+                            vk::ImageSubresourceRange {
+                                aspect_mask: vk::ImageAspectFlags::COLOR,
+                                base_mip_level: 0,
+                                level_count: 1,
+                                base_array_layer: 0,
+                                layer_count: 1,
+                            },
+                            vk::ImageLayout::UNDEFINED,
+                            vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                            Access::new(vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT, vk::AccessFlags2::NONE),
+                            Access::COLOR_ATTACHMENT_WRITE
+                        )
+                        .image(
+                            window.depth_image.raw,
+                            window.depth_image.full_range(),
+                            vk::ImageLayout::UNDEFINED,
+                            vk::ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+                            Access::DEPTH_ATTACHMENT_WRITE,
+                            Access::DEPTH_ATTACHMENT_WRITE
+                        );
+
+                    recorder.barrier(barriers).expect("Error record barrier");
+
+                    recorder.begin_rendering(
+                        RenderingBuilder::new(device, window.resolution)
+                            .color_attachment(color_view, Some([5.0/255.0, 5.0/255.0, 5.0/255.0, 1.0]))
+                            .depth_attachment(window.depth_view.raw, 1.0)
+                    ).expect("Error begin rendering");
+                }
+            }
+
+            let mut recorder = {
 
                 let mut sets = vec![];
 
                 for i in &pass.bind_sets {
                     let set = *self.resources.set.get(i.set_handle).expect("Not found DescriptorSet");
-                    sets.push(set);
+                    sets.push((i.set, set));
                 }
 
                 let pass_ctx = PassContext { 
-                    device,
+                    recorder: RefCell::new(recorder),
                     sets,
                     s: s.clone(),
                     resolution: window.resolution,
                     resources: self.resources.clone(),
-                    cmd: cbuf, 
                     pipeline: Some(pass.pipeline.raw()), 
                     layout: Some(pass.layout), 
+                    occlusion: self.queries.occlusion(slot, index),
                 };
 
                 let nope = vec![];
                 let renderables = scene.renderables.get(&pass.name).unwrap_or(&nope);
                 (pass.execute)(&pass_ctx, &renderables);
-            }
+                pass_ctx.recorder.into_inner()
+            };
 
-            unsafe { 
-                device.cmd_end_render_pass(cbuf);
+            recorder.end_scope().expect("Error end render pass");
+
+            // The render pass leaves color attachments in PRESENT_SRC
+            let color_layout = if window.dynamic_rendering() {
+                vk::ImageLayout::COLOR_ATTACHMENT_OPTIMAL
+            } else {
+                vk::ImageLayout::PRESENT_SRC_KHR
             };
 
+            self.queries.end_pass(device, cbuf, slot, index);
+
             match pass.target {
                 RenderTarget::FrameBuffer(handle) => {
 
                     let frame_buffer = self.resources.frame_buffer.get(handle).expect("Frame Buffer not found");
 
-                    let image_barrier = vk::ImageMemoryBarrier::default()
-                        .old_layout(vk::ImageLayout::PRESENT_SRC_KHR) 
-                        .new_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
-                        .src_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
-                        .dst_queue_family_index(vk::QUEUE_FAMILY_IGNORED)
-                        .image(frame_buffer.image.raw)
-                        .subresource_range(vk::ImageSubresourceRange {
-                            aspect_mask: vk::ImageAspectFlags::COLOR,
-                            base_mip_level: 0,
-                            level_count: 1,
-                            base_array_layer: 0,
-                            layer_count: 1,
-                        });
-
-                    unsafe { 
-                        device.cmd_pipeline_barrier(
-                        cbuf,
-                        vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
-                        vk::PipelineStageFlags::FRAGMENT_SHADER,
-                        vk::DependencyFlags::empty(),
-                        &[],
-                        &[],
-                        &[image_barrier]
-                        )
-                    };
+                    let barriers = BarrierBuilder::new(device)
+                        .image(
+                            frame_buffer.image.raw,
+                            vk::ImageSubresourceRange {
+                                aspect_mask: vk::ImageAspectFlags::COLOR,
+                                base_mip_level: 0,
+                                level_count: 1,
+                                base_array_layer: 0,
+                                layer_count: 1,
+                            },
+                            color_layout,
+                            vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                            Access::COLOR_ATTACHMENT_WRITE,
+                            Access::FRAGMENT_SHADER_READ
+                        );
+
+                    recorder.barrier(barriers).expect("Error record barrier");
+                }
+                RenderTarget::Swapchain if window.dynamic_rendering() => {
+                    let barriers = BarrierBuilder::new(device)
+                        .image(
+                            color_image,
+                            vk::ImageSubresourceRange {
+                                aspect_mask: vk::ImageAspectFlags::COLOR,
+                                base_mip_level: 0,
+                                level_count: 1,
+                                base_array_layer: 0,
+                                layer_count: 1,
+                            },
+                            color_layout,
+                            vk::ImageLayout::PRESENT_SRC_KHR,
+                            Access::COLOR_ATTACHMENT_WRITE,
+                            Access::PRESENT
+                        );
+
+                    recorder.barrier(barriers).expect("Error record barrier");
                 }
                 _ => {}
             }
 
-            unsafe {
-                let _ = device.end_command_buffer(cbuf);
-            }
+            recorder.end_label();
+            recorder.end().expect("Error end command buffer");
         }
 
-        let sync = &window.frame_sync[window.current_frame % window.frame_buffers.len()];
-        let wait_semaphores = [sync.image_available.raw];
-        let wait_stages = [vk::PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT];
-        let signal_semaphores = [sync.render_finished.raw];
-
-        let submit_info = vk::SubmitInfo::default()
-            .wait_semaphores(&wait_semaphores)
-            .wait_dst_stage_mask(&wait_stages)
-            .command_buffers(&buffers)
-            .signal_semaphores(&signal_semaphores);
+        let sync = &window.frame_sync[slot];
+        let render_finished = window.render_finished[image_index as usize].raw;
+        let signal_semaphores = [render_finished];
 
-        unsafe { device.queue_submit(self.queue, &[submit_info], sync.in_flight_fence.raw).expect("Error submit commands to queue") };
+        SubmitBuilder::new(device)
+            .wait(sync.image_available.raw, vk::PipelineStageFlags2::COLOR_ATTACHMENT_OUTPUT)
+            .command_buffers(buffers)
+            .signal(render_finished, vk::PipelineStageFlags2::ALL_COMMANDS)
+            .submit(self.queue, sync.in_flight_fence.raw)
+            .expect("Error submit commands to queue");
 
         let binding1 = [window.swapchain.raw];
         let binding = [image_index];
@@ -284,6 +370,50 @@ impl RenderGraph {
         let _ = unsafe { window.swapchain.loader.queue_present(self.queue, &present_info) } ;
         window.current_frame += 1;
     }
+
+    /// Rebuild the pipelines of passes using one of the `changed` SPIR-V files,
+    /// call between frames. Passes whose rebuild fails keep the old pipeline
+    pub fn reload_pipelines(&mut self, ctx: &RenderContext, res: &mut ResourceManager, changed: &[PathBuf]) {
+
+        puffin::profile_scope!("reload_pipelines");
+
+        let target = ctx.window.pipeline_target();
+        let changed = changed.iter().map(|p| canonical(p)).collect::<Vec<_>>();
+
+        // Stale pipelines would otherwise be handed back by the factories
+        res.evict_pipelines(|shader| changed.contains(&canonical(shader)));
+
+        for pass in &mut self.passes {
+
+            let (Pipeline::Graphics(pipeline), Some(factory)) = (&pass.pipeline, &pass.factory) else {
+                continue;
+            };
+
+            if !pipeline.shaders().iter().any(|p| changed.contains(&canonical(p))) {
+                continue;
+            }
+
+            let Some(layout) = res.get_layout(pass.layout).map(|layout| layout.raw) else {
+                error!("Failed to reload pipeline of {}: layout not found", pass.name);
+                continue;
+            };
+
+            match factory(&ctx.device, ctx.pipeline_cache(), res, layout, &target) {
+                Ok(pipeline) => {
+                    info!("Reloaded pipeline of {}", pass.name);
+                    // The old pipeline goes to the deletion queue once no pass shares it
+                    pass.pipeline = Pipeline::Graphics(pipeline);
+                }
+                Err(e) => {
+                    error!("Failed to reload pipeline of {}: {}", pass.name, e);
+                }
+            }
+        }
+    }
+}
+
+fn canonical(path: &Path) -> PathBuf {
+    std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
 }
 
 
@@ -337,10 +467,15 @@ impl RenderGraphBuilder {
         });
     }
 
-    pub fn compile(self, ctx: &RenderContext, desc: &DescriptorManager) -> RenderGraph {
+    pub fn compile(self, ctx: &RenderContext, resources: &mut ResourceManager, desc: &mut DescriptorManager) -> RenderGraph {
 
         let mut res= RenderGraphResources::new();
 
+        // Shared by every target, each is sampled at its own resolution with a single mip
+        let sampler = resources
+            .get_or_create_sampler(&ctx.device, SamplerDesc::nearest().address_mode(vk::SamplerAddressMode::CLAMP_TO_EDGE))
+            .unwrap();
+
         for (handle, desc) in self.frame_buffer {
             
             let image = ImageBuilder::new_2d(
@@ -360,15 +495,29 @@ impl RenderGraphBuilder {
             .build()
             .unwrap();
 
-            let frame_buffer = FrameBufferBuilder::new(&ctx.device, ctx.window.render_pass.raw)
-                .add_attachment(image_view.raw)
-                .add_attachment(ctx.window.depth_view.raw)
-                .extent(ctx.window.resolution)
-                .layers(1)
-                .build()
-                .unwrap();
-
-            let sampler = SamplerBuilder::default(&ctx.device).build().unwrap();
+            // Dynamic rendering begins with the views directly
+            let frame_buffer = ctx.window.render_pass.as_ref().map(|render_pass| {
+                FrameBufferBuilder::new(&ctx.device, render_pass.raw)
+                    .add_attachment(image_view.raw)
+                    .add_attachment(ctx.window.depth_view.raw)
+                    .extent(ctx.window.resolution)
+                    .layers(1)
+                    .build()
+                    .unwrap()
+            });
+
+            // Named after the pass rendering to it
+            let name = self.passes.iter()
+                .find(|pass| matches!(pass.target, RenderTarget::FrameBuffer(target) if target == handle))
+                .map(|pass| pass.name.clone())
+                .unwrap_or_else(|| format!("{:?}", handle));
+
+            ctx.device.set_object_name(image.raw, &format!("{} target", name));
+            ctx.device.set_object_name(image_view.raw, &format!("{} target view", name));
+
+            if let Some(frame_buffer) = &frame_buffer {
+                ctx.device.set_object_name(frame_buffer.raw, &format!("{} framebuffer", name));
+            }
 
             let frame = GraphFrameBuffer {
                 frame: frame_buffer,
@@ -381,49 +530,51 @@ impl RenderGraphBuilder {
 
         }
 
-        let mut cmd_bufs = Vec::with_capacity(ctx.window.frame_buffers.len());
+        let mut cmd_bufs = Vec::with_capacity(MAX_FRAMES_IN_FLIGHT);
         let pool = CommandPoolBuilder::reset(&ctx.device).build().unwrap();
 
-        for _ in 0..ctx.window.frame_buffers.len() {
+        for _ in 0..MAX_FRAMES_IN_FLIGHT {
             let buffers = pool.create_command_buffers(&ctx.device, self.passes.len() as u32).unwrap();
             cmd_bufs.push(buffers);
         }
 
         for (handle, layout) in self.set_layout {
-            let set = desc.create_descriptor_set(&ctx.device, &[layout.raw])[0];
+            let set = desc.create_descriptor_set(&ctx.device, &[layout.raw]).unwrap()[0];
             res.set.insert(handle, set);
+            res.set_layout.insert(handle, layout);
         }
 
+        let mut writer = DescriptorWriter::new();
+
         for i in self.binds {
-            
             let frame_buffer = res.frame_buffer.get(i.frame).expect("Not found Frame Buffer");
+            let set = *res.set.get(i.set).expect("Not found DescriptorSet");
+            let sampler = resources.get_sampler(frame_buffer.sampler).expect("Not found Sampler");
+            writer = writer.combined_image_sampler(set, i.bind, &frame_buffer.image_view, sampler);
+        }
 
-            let image_info = vk::DescriptorImageInfo::default()
-                .image_layout(vk::ImageLayout::SHADER_READ_ONLY_OPTIMAL)
-                .image_view(frame_buffer.image_view.raw)
-                .sampler(frame_buffer.sampler.raw);
-
-                let set = res.set.get(i.set).unwrap();
+        writer.update(&ctx.device).expect("Error write graph descriptor sets");
 
-                let bind = &[image_info];
+        let queue = ctx.device.queue_pool.get_queue(vk::QueueFlags::GRAPHICS).unwrap();
 
-                let write = vk::WriteDescriptorSet::default()
-                    .dst_set(*set)
-                    .dst_binding(i.bind)
-                    .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
-                    .image_info(bind);
+        // Timestamps need valid bits on the graphics family
+        let valid_bits = ctx.device.queue_family_props[ctx.device.queue_family() as usize].timestamp_valid_bits;
 
-                unsafe {
-                    ctx.device.raw.update_descriptor_sets(&[write], &[]);
-                }
-        }
-
-        let queue = ctx.device.queue_pool.get_queue(vk::QueueFlags::GRAPHICS).unwrap();
+        let queries = PassQueries::new(
+            &ctx.device,
+            MAX_FRAMES_IN_FLIGHT,
+            self.passes.iter().map(|pass| pass.name.clone()).collect(),
+            &self.passes.iter().map(|pass| pass.occlusion_queries).collect::<Vec<_>>(),
+            (valid_bits > 0).then(|| ctx.device.phys_dev.limits().timestamp_period),
+            valid_bits
+        )
+        .expect("Error create pass queries");
 
         RenderGraph {  
             queue,
             pool,
             cmd_bufs,
+            queries,
             passes: self.passes,
             resources: Arc::new(res)
         }
@@ -436,7 +587,7 @@ pub enum RenderTarget {
 }
 
 pub enum Pipeline {
-    Graphics(GraphicsPipeline),
+    Graphics(Arc<GraphicsPipeline>),
     Compute()
 }
 
@@ -456,7 +607,10 @@ pub struct PassBuilder {
     target: RenderTarget,
     execute: Option<Box<Execute>>,
     pipeline: Option<Pipeline>,
+    factory: Option<Box<PipelineFactory>>,
     bind_sets: Vec<BindSet>,
+    bindless: bool,
+    occlusion_queries: u32,
     pipeline_layout: Option<LayoutHandle>
 }
 
@@ -464,10 +618,13 @@ impl PassBuilder {
     pub fn new<S: Into<String>>(name: S) -> Self {
         PassBuilder { 
             bind_sets: vec![],
+            bindless: false,
+            occlusion_queries: 0,
             name: name.into(), 
             target: RenderTarget::Swapchain,
             execute: None, 
             pipeline: None, 
+            factory: None,
             pipeline_layout: None 
         }
     }
@@ -477,6 +634,19 @@ impl PassBuilder {
         self
     }
 
+    /// Bind the global bindless set at set 0, the pipeline layout must start with `Bindless::layout`
+    pub fn bindless(mut self) -> Self {
+        self.bindless = true;
+        self
+    }
+
+    /// Occlusion queries the pass can wrap draws in through `PassContext::begin_occlusion`,
+    /// results show up in `RenderGraph::pass_stats`
+    pub fn occlusion_queries(mut self, count: u32) -> Self {
+        self.occlusion_queries = count;
+        self
+    }
+
     pub fn target(mut self, target: RenderTarget) -> Self {
         self.target = target;
         self
@@ -493,12 +663,21 @@ impl PassBuilder {
         self
     }
 
+    /// Let the render graph rebuild the pipeline when its shaders change
+    pub fn pipeline_factory(mut self, factory: Box<PipelineFactory>) -> Self {
+        self.factory = Some(factory);
+        self
+    }
+
     pub fn build(self) -> Pass {
         Pass {  
             target: self.target,
             name: self.name,
             bind_sets: self.bind_sets,
+            bindless: self.bindless,
+            occlusion_queries: self.occlusion_queries,
             pipeline: self.pipeline.unwrap(),
+            factory: self.factory,
             layout: self.pipeline_layout.unwrap(),
             execute: self.execute.unwrap()
         }
@@ -508,8 +687,11 @@ impl PassBuilder {
 pub struct Pass {
     name: String,
     bind_sets: Vec<BindSet>,
+    bindless: bool,
+    occlusion_queries: u32,
     target: RenderTarget,
     pipeline: Pipeline,
+    factory: Option<Box<PipelineFactory>>,
     layout: LayoutHandle,
     execute: Box<Execute>
 }
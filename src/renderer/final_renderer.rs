@@ -2,7 +2,7 @@ use std::path::Path;
 
 use ash::vk;
 
-use crate::{DescriptorSetLayoutBuilder, FrameBufferHandle, GraphicsPipelineBuilder, PassBuilder, PassContext, Pipeline, PipelineCache, PipelineLayoutBuilder, RenderContext, RenderGraphBuilder, RenderTarget, ResourceManager, VulkanResult};
+use crate::{DepthStencilState, Device, FrameBufferHandle, GraphicsPipelineDesc, PassBuilder, PassContext, Pipeline, PipelineReflection, PipelineTarget, RenderContext, RenderGraphBuilder, RenderTarget, ResourceManager, VulkanResult};
 
 
 
@@ -37,43 +37,17 @@ impl<'a> FinalRendererBuilder<'a> {
     pub fn build(self) -> VulkanResult<FinalRenderer> {
 
         let device = &self.ctx.device.device;
-        let mut binds = vec![];
-
-        for (bind, _) in &self.binds {
-            binds.push(
-                vk::DescriptorSetLayoutBinding::default()
-                    .binding(*bind)
-                    .descriptor_count(1)
-                    .descriptor_type(vk::DescriptorType::COMBINED_IMAGE_SAMPLER)
-                    .stage_flags(vk::ShaderStageFlags::FRAGMENT)
-            );
-        }
 
-        let set_layout = DescriptorSetLayoutBuilder::new(device)
-            .bindings(binds)
-            .build()?;
-
-        let color_blend = vk::PipelineColorBlendAttachmentState::default()
-            .color_write_mask(
-                vk::ColorComponentFlags::R
-                | vk::ColorComponentFlags::G
-                | vk::ColorComponentFlags::B
-                | vk::ColorComponentFlags::A
-            )
-            .blend_enable(false);
-
-        let layout = PipelineLayoutBuilder::new(&device)
-            .set_layouts(vec![
-                set_layout.raw
-            ])
-            .push_constant(vec![
-                vk::PushConstantRange::default()
-                    .offset(0)
-                    .size(128)
-                    .stage_flags(vk::ShaderStageFlags::VERTEX)
-            ])
-            .build()
-            .unwrap();
+        let spv = Path::new("src/shared/shaders/spv");
+
+        let reflection = PipelineReflection::from_files(&[
+            spv.join("final-vert.spv"),
+            spv.join("final-frag.spv")
+        ])?;
+
+        let set_layout = reflection.set_layout(device, 0).build()?;
+
+        let layout = reflection.pipeline_layout(device, vec![set_layout.raw]).build()?;
 
         let set = self.builder.create_descriptor_set(set_layout);
 
@@ -85,81 +59,17 @@ impl<'a> FinalRendererBuilder<'a> {
             );
         }
 
-        let mut use_cache = false;
-
-        let cache = match PipelineCache::from_file(device, Path::new(r"src\cache\final.bin")) {
-            Ok(cache) => {
-                log::info!("Use cache!!!!");
-                use_cache = true;
-                cache
-            },
-            Err(_) => {
-                log::info!("Don't use cache!!!");
-                PipelineCache::new(device).unwrap()
-            }
+        // Fullscreen triangle generated in the vertex shader, drawn over whatever depth is there
+        let desc = GraphicsPipelineDesc::new(spv.join("final-vert.spv"), spv.join("final-frag.spv"))
+            .depth_stencil(DepthStencilState::disabled());
+
+        let target = self.ctx.window.pipeline_target();
+        let cache = self.ctx.pipeline_cache();
+        let pipeline = self.res.get_or_create_pipeline(device, cache, &desc, layout.raw, &target)?;
+
+        let factory = move |device: &Device, cache: vk::PipelineCache, res: &mut ResourceManager, layout: vk::PipelineLayout, target: &PipelineTarget| {
+            res.get_or_create_pipeline(device, cache, &desc, layout, target)
         };
-    
-        let pipeline = GraphicsPipelineBuilder::new(&device)
-            .vertex_shader_from_file(r"src\shared\shaders\spv\final-vert.spv")
-            .fragment_shader_from_file(r"src\shared\shaders\spv\final-frag.spv")
-            .render_pass(self.ctx.window.render_pass.raw)
-            .pipeline_layout(layout.raw)
-            .viewport(vec![
-                vk::Viewport::default()
-                    .x(0.0)
-                    .y(0.0)
-                    .width(self.ctx.window.resolution.width as f32)
-                    .height(self.ctx.window.resolution.height as f32)
-                    .min_depth(0.0)
-                    .max_depth(1.0)
-            ])
-            .scissors(vec![
-                vk::Rect2D::default()
-                    .offset(vk::Offset2D { x: 0, y: 0 })
-                    .extent(vk::Extent2D { 
-                        width: self.ctx.window.resolution.width, 
-                        height: self.ctx.window.resolution.height 
-                    })
-            ])
-            .input_assembly(
-                vk::PipelineInputAssemblyStateCreateInfo::default()
-                    .topology(vk::PrimitiveTopology::TRIANGLE_LIST)
-                    .primitive_restart_enable(false)
-            )
-            .rasterization(
-                vk::PipelineRasterizationStateCreateInfo::default()
-                    .depth_clamp_enable(false)
-                    .rasterizer_discard_enable(false)
-                    .polygon_mode(vk::PolygonMode::FILL)
-                    .line_width(1.0)
-                    .cull_mode(vk::CullModeFlags::NONE)
-                    .front_face(vk::FrontFace::CLOCKWISE)
-                    .depth_bias_enable(false)
-            )
-            .vertex_input_info(
-                vk::PipelineVertexInputStateCreateInfo::default()
-            )
-            .multisampling(
-                vk::PipelineMultisampleStateCreateInfo::default()
-                    .sample_shading_enable(false)
-                    .rasterization_samples(vk::SampleCountFlags::TYPE_1)
-            )
-            .color_blending(
-                vk::PipelineColorBlendStateCreateInfo::default()
-                    .logic_op_enable(false)
-                    .logic_op(vk::LogicOp::COPY)
-                    .attachments(&[color_blend])
-            )
-            .dynamic_state(vec![
-                vk::DynamicState::VIEWPORT,
-                vk::DynamicState::SCISSOR
-            ])
-            .build()?;
-
-        if !use_cache {
-            cache.save_to_file(device, Path::new(r"src\cache\final.bin")).unwrap();
-            log::info!("Save cache!!!");
-        }
 
         let layout = self.res.add_layout(layout);
 
@@ -167,6 +77,7 @@ impl<'a> FinalRendererBuilder<'a> {
             PassBuilder::new("Final Pass")
                 .bind_descriptor_set(0, set)
                 .use_pipeline(Pipeline::Graphics(pipeline), layout)
+                .pipeline_factory(Box::new(factory))
                 .target(RenderTarget::Swapchain)
                 .execute(Box::new(|ctx: &PassContext<'_>, _: &[crate::Renderable]| {
                     ctx.bind_pipeline();
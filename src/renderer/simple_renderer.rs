@@ -1,9 +1,7 @@
-
 use std::path::Path;
-
 use ash::vk;
 
-use crate::{AttributeDescriptions, BindingDescriptions, FrameBufferHandle, GraphicsPipelineBuilder, LayoutHandle, PassBuilder, PassContext, Pipeline, PipelineCache, PipelineLayoutBuilder, RenderContext, RenderGraphBuilder, RenderTarget, ResourceManager, Vertex};
+use crate::{Device, FrameBufferHandle, GraphicsPipelineDesc, LayoutHandle, PassBuilder, PassContext, Pipeline, PipelineReflection, PipelineTarget, RenderContext, RenderGraphBuilder, RenderTarget, ResourceManager, Vertex, VertexLayout};
 
 pub struct SimpleRenderer {
     pub frame_buffer: Option<FrameBufferHandle>,
@@ -14,120 +12,42 @@ impl SimpleRenderer {
 
     pub fn new(ctx: &RenderContext, res: &mut ResourceManager, builder: &mut RenderGraphBuilder, offscreen: bool) -> Self {
 
-        let bind = Vertex::bind_desc();
-        let attrs = Vertex::attr_desc();
-
-        let color_blend = vk::PipelineColorBlendAttachmentState::default()
-            .color_write_mask(
-                vk::ColorComponentFlags::R
-                | vk::ColorComponentFlags::G
-                | vk::ColorComponentFlags::B
-                | vk::ColorComponentFlags::A
-            )
-            .blend_enable(false);
-
-        let layout = PipelineLayoutBuilder::new(&ctx.device)
-            .set_layouts(vec![])
-            .push_constant(vec![
-                vk::PushConstantRange::default()
-                    .offset(0)
-                    .size(128)
-                    .stage_flags(vk::ShaderStageFlags::VERTEX)
-            ])
-            .build()
-            .unwrap();
+        let spv = Path::new("src/shared/shaders/spv");
+        let (vertex, fragment) = (spv.join("base_simple-vert.spv"), spv.join("base_simple-frag.spv"));
 
-        let mut use_cache = false;
-
-        let cache = match PipelineCache::from_file(&ctx.device, Path::new(r"src\cache\simple.bin")) {
-            Ok(cache) => {
-                log::info!("Use cache!!!!");
-                use_cache = true;
-                cache
-            },
-            Err(_) => {
-                log::info!("Don't use cache!!!");
-                PipelineCache::new(&ctx.device).unwrap()
-            }
-        };
-    
-        let pipeline = GraphicsPipelineBuilder::new(&ctx.device)
-            .cache(cache.raw)
-            .vertex_shader_from_file(r"src\shared\shaders\spv\base_simple-vert.spv")
-            .fragment_shader_from_file(r"src\shared\shaders\spv\base_simple-frag.spv")
-            .render_pass(ctx.window.render_pass.raw)
-            .pipeline_layout(layout.raw)
-            .viewport(vec![
-                vk::Viewport::default()
-                    .x(0.0)
-                    .y(0.0)
-                    .width(ctx.window.resolution.width as f32)
-                    .height(ctx.window.resolution.height as f32)
-                    .min_depth(0.0)
-                    .max_depth(1.0)
-            ])
-            .scissors(vec![
-                vk::Rect2D::default()
-                    .offset(vk::Offset2D { x: 0, y: 0 })
-                    .extent(vk::Extent2D { width: ctx.window.resolution.width, height: ctx.window.resolution.height })
-            ])
-            .input_assembly(
-                vk::PipelineInputAssemblyStateCreateInfo::default()
-                    .topology(vk::PrimitiveTopology::TRIANGLE_LIST)
-                    .primitive_restart_enable(false)
-            )
-            .rasterization(
-                vk::PipelineRasterizationStateCreateInfo::default()
-                    .depth_clamp_enable(false)
-                    .rasterizer_discard_enable(false)
-                    .polygon_mode(vk::PolygonMode::FILL)
-                    .line_width(1.0)
-                    .cull_mode(vk::CullModeFlags::NONE)
-                    .front_face(vk::FrontFace::CLOCKWISE)
-                    .depth_bias_enable(false)
-            )
-            .vertex_input_info(
-                vk::PipelineVertexInputStateCreateInfo::default()
-                    .vertex_binding_descriptions(&bind)
-                    .vertex_attribute_descriptions(&attrs)
-            )
-            .multisampling(
-                vk::PipelineMultisampleStateCreateInfo::default()
-                    .sample_shading_enable(false)
-                    .rasterization_samples(vk::SampleCountFlags::TYPE_1)
-            )
-            .color_blending(
-                vk::PipelineColorBlendStateCreateInfo::default()
-                    .logic_op_enable(false)
-                    .logic_op(vk::LogicOp::COPY)
-                    .attachments(&[color_blend])
-            )
-            .dynamic_state(vec![
-                vk::DynamicState::VIEWPORT,
-                vk::DynamicState::SCISSOR
-            ])
-            .build()
+        let layout = PipelineReflection::from_files(&[&vertex, &fragment])
+            .and_then(|reflection| reflection.pipeline_layout(&ctx.device, vec![]).build())
             .unwrap();
 
-        if !use_cache {
-            cache.save_to_file(&ctx.device, Path::new(r"src\cache\simple.bin")).unwrap();
-            log::info!("Save cache!!!");
-        }
+        let layout_raw = layout.raw;
+        let target = ctx.window.pipeline_target();
+
+        let desc = GraphicsPipelineDesc::new(vertex, fragment)
+            .vertex_layout(VertexLayout::of::<Vertex>());
+
+        let cache = ctx.pipeline_cache();
+        let pipeline = res.get_or_create_pipeline(&ctx.device, cache, &desc, layout_raw, &target).unwrap();
+
+        let factory = move |device: &Device, cache: vk::PipelineCache, res: &mut ResourceManager, layout: vk::PipelineLayout, target: &PipelineTarget| {
+            res.get_or_create_pipeline(device, cache, &desc, layout, target)
+        };
 
-        let layout = res.cache_layout("Layout 1", layout);
+        let layout = res.add_layout(layout);
 
         if offscreen {
 
             let frame_buffer = builder.create_frame_buffer(crate::FrameDesc { 
                 width: ctx.window.resolution.width, 
                 height: ctx.window.resolution.height, 
-                format: vk::Format::R8G8B8A8_SRGB, 
+                // Drawn with the swapchain's render pass and pipeline target
+                format: ctx.window.color_format, 
                 usage: vk::ImageUsageFlags::COLOR_ATTACHMENT | vk::ImageUsageFlags::SAMPLED
             });
 
             builder.add_pass(
                 PassBuilder::new("Simple Pass")
                     .use_pipeline(Pipeline::Graphics(pipeline), layout)
+                    .pipeline_factory(Box::new(factory))
                     .target(RenderTarget::FrameBuffer(frame_buffer))
                     .execute(Box::new(|ctx: &PassContext<'_>, renderables: &[crate::Renderable]| {
                         ctx.bind_pipeline();
@@ -145,6 +65,7 @@ impl SimpleRenderer {
         builder.add_pass(
             PassBuilder::new("Simple Pass")
                 .use_pipeline(Pipeline::Graphics(pipeline), layout)
+                .pipeline_factory(Box::new(factory))
                 .target(RenderTarget::Swapchain)
                 .execute(Box::new(|ctx: &PassContext<'_>, renderables: &[crate::Renderable]| {
                     ctx.bind_pipeline();
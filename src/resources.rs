@@ -1,14 +1,18 @@
 
 use std::collections::HashMap;
+use std::path::Path;
+use std::sync::Arc;
+use ash::vk;
 use slotmap::*;
 use crate::core::PipelineLayout;
+use crate::{Device, GraphicsPipeline, GraphicsPipelineDesc, PipelineTarget, Sampler, SamplerBuilder, SamplerDesc, VulkanResult};
 
 new_key_type! { pub struct LayoutHandle; }
 new_key_type! { pub struct SamplerHandle; }
 
 pub struct ResourceManager {
     layout: SlotMap<LayoutHandle, PipelineLayout>,
-    sampler: SlotMap<SamplerHandle, bool>,
+    sampler: SlotMap<SamplerHandle, Sampler>,
     cache: Cache
 }
 
@@ -18,7 +22,7 @@ impl ResourceManager {
         ResourceManager { 
             sampler: SlotMap::with_key(),
             layout: SlotMap::with_key(),
-            cache: Cache { layout: HashMap::new() } 
+            cache: Cache { layout: HashMap::new(), sampler: HashMap::new(), pipeline: HashMap::new() } 
         }
     }
 
@@ -45,9 +49,67 @@ impl ResourceManager {
         self.cache.layout.insert(name.into(), handle);
         handle
     }
+
+    /// Returns the same handle for identical descriptions, creating the sampler on first use
+    pub fn get_or_create_sampler(&mut self, device: &Device, desc: SamplerDesc) -> VulkanResult<SamplerHandle> {
+
+        if let Some(handle) = self.cache.sampler.get(&desc) {
+            return Ok(*handle);
+        }
+
+        let sampler = SamplerBuilder::from_desc(device, desc).build()?;
+        let handle = self.sampler.insert(sampler);
+        self.cache.sampler.insert(desc, handle);
+
+        Ok(handle)
+    }
+
+    pub fn get_sampler(&self, sampler: SamplerHandle) -> Option<&Sampler> {
+        self.sampler.get(sampler)
+    }
+
+    /// Returns the same pipeline for identical descriptions, layouts and targets
+    pub fn get_or_create_pipeline(
+        &mut self,
+        device: &Device,
+        cache: vk::PipelineCache,
+        desc: &GraphicsPipelineDesc,
+        layout: vk::PipelineLayout,
+        target: &PipelineTarget
+    ) -> VulkanResult<Arc<GraphicsPipeline>> {
+
+        let key = PipelineKey { desc: desc.clone(), layout, target: target.clone() };
+
+        if let Some(pipeline) = self.cache.pipeline.get(&key) {
+            return Ok(pipeline.clone());
+        }
+
+        let pipeline = Arc::new(desc.build(device, cache, layout, target)?);
+        self.cache.pipeline.insert(key, pipeline.clone());
+
+        Ok(pipeline)
+    }
+
+    /// Forgets cached pipelines built from a shader matching `stale`
+    pub fn evict_pipelines<F: Fn(&Path) -> bool>(&mut self, stale: F) {
+        self.cache.pipeline.retain(|key, _| {
+            !std::iter::once(&key.desc.vertex_shader)
+                .chain(&key.desc.fragment_shader)
+                .any(|shader| stale(shader))
+        });
+    }
+}
+
+#[derive(PartialEq, Eq, Hash)]
+struct PipelineKey {
+    desc: GraphicsPipelineDesc,
+    layout: vk::PipelineLayout,
+    target: PipelineTarget,
 }
 
 
 pub struct Cache {
-    layout: HashMap<String, LayoutHandle>
+    layout: HashMap<String, LayoutHandle>,
+    sampler: HashMap<SamplerDesc, SamplerHandle>,
+    pipeline: HashMap<PipelineKey, Arc<GraphicsPipeline>>
 }
\ No newline at end of file
@@ -3,10 +3,11 @@ use crate::GameObject;
 use crate::Renderable;
 
 #[repr(C)]
+#[derive(Clone, Copy)]
 pub struct Camera {
-    view: [[f32; 4]; 4],
-    proj: [[f32; 4]; 4],
-    pos:  [f32; 4]
+    pub view: [[f32; 4]; 4],
+    pub proj: [[f32; 4]; 4],
+    pub pos:  [f32; 4]
 }
 
 pub struct Scene {
@@ -0,0 +1,164 @@
+use std::{collections::HashMap, fs, path::{Path, PathBuf}, process::Command, time::{Duration, Instant, SystemTime}};
+
+use log::{error, info, warn};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Polls the shader sources and recompiles the ones that changed with `glslc`
+pub struct ShaderWatcher {
+    source_dir: PathBuf,
+    spv_dir: PathBuf,
+    modified: HashMap<PathBuf, SystemTime>,
+    last_poll: Instant,
+}
+
+impl ShaderWatcher {
+
+    pub fn new<P: AsRef<Path>>(source_dir: P, spv_dir: P) -> Self {
+        let mut watcher = Self {
+            source_dir: source_dir.as_ref().to_path_buf(),
+            spv_dir: spv_dir.as_ref().to_path_buf(),
+            modified: HashMap::new(),
+            last_poll: Instant::now(),
+        };
+
+        // build.rs already compiled everything, only later edits matter
+        watcher.modified = watcher.scan();
+        watcher
+    }
+
+    fn scan(&self) -> HashMap<PathBuf, SystemTime> {
+        let Ok(entries) = fs::read_dir(&self.source_dir) else {
+            return HashMap::new();
+        };
+
+        entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.is_file())
+            .filter_map(|path| {
+                let modified = fs::metadata(&path).and_then(|m| m.modified()).ok()?;
+                Some((path, modified))
+            })
+            .collect()
+    }
+
+    /// Recompile changed shaders, returns the `.spv` files that were rewritten.
+    /// Shaders that fail to compile are logged and keep their previous `.spv`
+    pub fn poll(&mut self) -> Vec<PathBuf> {
+
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return vec![];
+        }
+
+        puffin::profile_scope!("ShaderWatcher::poll");
+        self.last_poll = Instant::now();
+
+        let current = self.scan();
+        let changed = current.iter()
+            .filter(|(path, modified)| self.modified.get(*path) != Some(modified))
+            .map(|(path, _)| path.clone())
+            .collect::<Vec<_>>();
+
+        self.modified = current;
+
+        if changed.is_empty() {
+            return vec![];
+        }
+
+        let mut sources = vec![];
+
+        for path in &changed {
+            if is_stage(path) {
+                sources.push(path.clone());
+            } else {
+                // Included file, recompile every stage that includes it
+                sources.extend(self.includers(path));
+            }
+        }
+
+        sources.sort();
+        sources.dedup();
+
+        sources.iter()
+            .filter_map(|source| self.compile(source))
+            .collect()
+    }
+
+    fn includers(&self, include: &Path) -> Vec<PathBuf> {
+        self.modified.keys()
+            .filter(|path| is_stage(path))
+            .filter(|path| {
+                let dir = path.parent().unwrap_or(&self.source_dir);
+
+                fs::read_to_string(path)
+                    .map(|text| text.lines().filter_map(include_path).any(|included| {
+                        // Relative to the including file first, then to the `-I` directory
+                        dir.join(included) == include || self.source_dir.join(included) == include
+                    }))
+                    .unwrap_or(false)
+            })
+            .cloned()
+            .collect()
+    }
+
+    fn compile(&self, source: &Path) -> Option<PathBuf> {
+        let stem = source.file_stem()?.to_str()?;
+        let stage = source.extension()?.to_str()?;
+        let output = self.spv_dir.join(format!("{}-{}.spv", stem, stage));
+
+        let result = Command::new("glslc")
+            .arg(source)
+            .arg("-I")
+            .arg(&self.source_dir)
+            .arg("-o")
+            .arg(&output)
+            .output();
+
+        match result {
+            Ok(out) if out.status.success() => {
+                info!("Recompiled shader {:?}", source);
+                Some(output)
+            }
+            Ok(out) => {
+                error!("Failed to compile shader {:?}:\n{}", source, String::from_utf8_lossy(&out.stderr));
+                None
+            }
+            Err(e) => {
+                warn!("Failed to run glslc for {:?}: {}", source, e);
+                None
+            }
+        }
+    }
+}
+
+/// Path of an `#include "file"` or `#include <file>` line
+fn include_path(line: &str) -> Option<&str> {
+    let rest = line.trim_start().strip_prefix('#')?.trim_start().strip_prefix("include")?.trim();
+
+    let (open, close) = match rest.chars().next()? {
+        '"' => ('"', '"'),
+        '<' => ('<', '>'),
+        _ => return None,
+    };
+
+    rest.strip_prefix(open)?.split(close).next().filter(|path| !path.is_empty())
+}
+
+fn is_stage(path: &Path) -> bool {
+    matches!(path.extension().and_then(|e| e.to_str()), Some("vert") | Some("frag"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::include_path;
+
+    #[test]
+    fn include_paths() {
+        assert_eq!(include_path("#include \"common.glsl\""), Some("common.glsl"));
+        assert_eq!(include_path("  # include <lib/noise.glsl> // comment"), Some("lib/noise.glsl"));
+        assert_eq!(include_path("#include \"\""), None);
+        assert_eq!(include_path("#extension GL_GOOGLE_include_directive : require"), None);
+        assert_eq!(include_path("// #include \"common.glsl\""), None);
+    }
+}
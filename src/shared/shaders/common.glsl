@@ -21,6 +21,9 @@ layout(set = 0, binding = 3) uniform GlobalCamera {
     mat4 model;
     mat4 view;
     mat4 proj;
+    vec4 pos;
+    uvec2 resolution;
+    vec2 mouse_pos;
 } camera;
 
 layout(set = 0, binding = 4) readonly buffer AABB {
@@ -0,0 +1,93 @@
+use ash::vk;
+use bytemuck::Pod;
+use log::debug;
+
+use crate::{BufferError, Device, GpuBuffer, GpuBufferBuilder, VulkanError, VulkanResult};
+
+/// Bytes each frame slot can hand out
+pub const DEFAULT_FRAME_SIZE: vk::DeviceSize = 256 * 1024;
+
+/// Per-frame linear allocator for uniform and storage data.
+///
+/// One host visible buffer is split into a region per frame slot. Allocations return
+/// dynamic offsets into the whole buffer, so a `*_DYNAMIC` descriptor written once stays valid.
+/// A region is rewound in `begin_frame`, after the slot's fence signaled
+pub struct UniformRing {
+    buffer: GpuBuffer,
+    frame_size: vk::DeviceSize,
+    alignment: vk::DeviceSize,
+    slot: usize,
+    head: vk::DeviceSize,
+}
+
+impl UniformRing {
+
+    pub fn new(device: &Device, limits: &vk::PhysicalDeviceLimits, frames: usize, frame_size: vk::DeviceSize) -> VulkanResult<Self> {
+
+        let alignment = limits.min_uniform_buffer_offset_alignment
+            .max(limits.min_storage_buffer_offset_alignment)
+            .max(1);
+
+        // Every region starts aligned as well
+        let frame_size = align_up(frame_size, alignment);
+
+        let buffer = GpuBufferBuilder::new(device, frame_size * frames as vk::DeviceSize)
+            .usage(vk::BufferUsageFlags::UNIFORM_BUFFER | vk::BufferUsageFlags::STORAGE_BUFFER)
+            .host_visible()
+            .build()?;
+
+        device.set_object_name(buffer.raw(), "Uniform ring");
+
+        debug!("Create UniformRing with {} regions of {} bytes, alignment {}", frames, frame_size, alignment);
+
+        Ok(Self {
+            buffer,
+            frame_size,
+            alignment,
+            slot: 0,
+            head: 0,
+        })
+    }
+
+    pub fn buffer(&self) -> &GpuBuffer {
+        &self.buffer
+    }
+
+    /// Rewind the region of `slot`, call once the slot's fence has signaled
+    pub fn begin_frame(&mut self, slot: usize) {
+        self.slot = slot;
+        self.head = 0;
+    }
+
+    /// Copy `data` into the current region and return its dynamic offset
+    pub fn push<T: Pod>(&mut self, device: &Device, data: &T) -> VulkanResult<u32> {
+        self.push_bytes(device, bytemuck::bytes_of(data))
+    }
+
+    pub fn push_bytes(&mut self, device: &Device, bytes: &[u8]) -> VulkanResult<u32> {
+
+        let len = bytes.len() as vk::DeviceSize;
+
+        if self.head + len > self.frame_size {
+            return Err(VulkanError::GpuBuffer(BufferError::RingFull {
+                capacity: self.frame_size,
+                requested: len,
+            }));
+        }
+
+        let offset = self.slot as vk::DeviceSize * self.frame_size + self.head;
+        self.buffer.write(device, offset, bytes)?;
+        self.head = align_up(self.head + len, self.alignment);
+
+        Ok(offset as u32)
+    }
+
+    /// Bytes handed out from the current region
+    pub fn used(&self) -> vk::DeviceSize {
+        self.head
+    }
+}
+
+fn align_up(value: vk::DeviceSize, alignment: vk::DeviceSize) -> vk::DeviceSize {
+    value.div_ceil(alignment) * alignment
+}
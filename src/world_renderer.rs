@@ -2,12 +2,26 @@ use std::sync::Arc;
 
 use winit::window;
 use ash::vk;
-use crate::{AABB, AttributeDescriptions, BindingDescriptions, Bindless, DescriptorManager, FinalRenderer, FinalRendererBuilder, GraphicsPipelineBuilder, GridRenderer, LayoutHandle, PassBuilder, PassContext, Pipeline, PipelineLayoutBuilder, RenderContext, RenderGraph, RenderGraphBuilder, RenderTarget, ResourceManager, Scene, SimpleRenderer, Transforms, UiRenderer, Vertex};
-
-
+use log::{info, warn};
+use crate::{AABB, AttributeDescriptions, BindingDescriptions, Bindless, DescriptorManager, FinalRenderer, FinalRendererBuilder, GraphicsPipelineBuilder, GridRenderer, LayoutHandle, PassBuilder, PassContext, Pipeline, PipelineLayoutBuilder, RenderContext, RenderGraph, RenderGraphBuilder, PassStats, RenderTarget, ResourceManager, Scene, ShaderWatcher, SimpleRenderer, Transforms, UiRenderer, UniformRing, Vertex, CAMERA_BINDING, DEFAULT_FRAME_SIZE, DescriptorError, MAX_FRAMES_IN_FLIGHT, VulkanError};
+
+const IDENTITY: [[f32; 4]; 4] = [
+    [1.0, 0.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0, 0.0],
+    [0.0, 0.0, 1.0, 0.0],
+    [0.0, 0.0, 0.0, 1.0],
+];
+
+/// Matches `GlobalCamera` in `common.glsl` (std140)
+#[repr(C)]
+#[derive(Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct GlobalUniforms {
-    resolution: [u32; 2],
-    mouse_pos: [f32; 2]
+    pub model: [[f32; 4]; 4],
+    pub view: [[f32; 4]; 4],
+    pub proj: [[f32; 4]; 4],
+    pub camera_pos: [f32; 4],
+    pub resolution: [u32; 2],
+    pub mouse_pos: [f32; 2]
 }
 
 
@@ -18,11 +32,15 @@ pub struct WorldRenderer {
     grid: GridRenderer,
     finall: FinalRenderer,
     ui: UiRenderer,
-    bindless: Bindless,
+    /// Missing without descriptor indexing
+    bindless: Option<Bindless>,
+    uniforms: UniformRing,
+    mouse_pos: [f32; 2],
     transforms: Transforms,
     aabb: AABB,
     graph: RenderGraph,
     scene: Scene,
+    shaders: ShaderWatcher,
     ctx: RenderContext,
 }
 
@@ -36,7 +54,24 @@ impl WorldRenderer {
         let mut res = ResourceManager::new();
         let mut builder = RenderGraphBuilder::new();
 
-        let bindless = Bindless {};
+        let uniforms = UniformRing::new(&ctx.device, ctx.device.phys_dev.limits(), MAX_FRAMES_IN_FLIGHT, DEFAULT_FRAME_SIZE).unwrap();
+
+        let bindless = match Bindless::new(&ctx.device, MAX_FRAMES_IN_FLIGHT) {
+            Ok(mut bindless) => {
+                bindless.set_uniform_buffer(
+                    CAMERA_BINDING,
+                    uniforms.buffer().raw(),
+                    0,
+                    std::mem::size_of::<GlobalUniforms>() as vk::DeviceSize
+                );
+                Some(bindless)
+            }
+            Err(VulkanError::Descriptor(DescriptorError::IndexingUnsupported)) => {
+                warn!("Descriptor indexing is unsupported, running without the bindless set");
+                None
+            }
+            Err(e) => panic!("Error create bindless set: {}", e),
+        };
         let transforms = Transforms {};
         let aabb = AABB {};
 
@@ -50,8 +85,13 @@ impl WorldRenderer {
             .build()
             .unwrap();
 
-        let desc = DescriptorManager::new(&ctx.device).unwrap();
-        let graph = builder.compile(&ctx, &desc);
+        let mut desc = DescriptorManager::new(&ctx.device).unwrap();
+        let graph = builder.compile(&ctx, &mut res, &mut desc);
+
+        if bindless.is_none() || !graph.uses_bindless() {
+            info!("No pass binds the global set, GlobalUniforms are not uploaded");
+        }
+        let shaders = ShaderWatcher::new("src/shared/shaders", "src/shared/shaders/spv");
 
         WorldRenderer { 
             descriptors: desc,
@@ -61,10 +101,13 @@ impl WorldRenderer {
             simple, 
             ui, 
             bindless, 
+            uniforms,
+            mouse_pos: [0.0, 0.0],
             transforms, 
             aabb, 
             graph, 
             scene, 
+            shaders,
             ctx
         }
     }
@@ -85,11 +128,64 @@ impl WorldRenderer {
         // self.simple = simple;
         // self.finall = Some(finall);
         // self.resources = res.into();
-        // self.graph = builder.compile(&self.ctx, &self.descriptors);
+        // self.graph = builder.compile(&self.ctx, &mut self.descriptors);
 
     }
 
     pub fn draw_frame(&mut self) {
-       self.graph.execute(&mut self.ctx, &self.scene, self.resources.clone());
+       let changed = self.shaders.poll();
+
+       if !changed.is_empty() {
+           match Arc::get_mut(&mut self.resources) {
+               Some(res) => self.graph.reload_pipelines(&self.ctx, res, &changed),
+               None => warn!("Skipping pipeline reload: resources are still shared"),
+           }
+       }
+
+       let slot = self.ctx.begin_frame();
+       self.descriptors.begin_frame(&self.ctx.device, slot).expect("Error reset frame descriptor pools");
+       if let Some(bindless) = &mut self.bindless {
+           bindless.begin_frame(&self.ctx.device, slot);
+       }
+       self.uniforms.begin_frame(slot);
+
+       let camera = &self.scene.camera;
+       let resolution = self.ctx.window.resolution;
+
+       let globals = GlobalUniforms {
+           model: IDENTITY,
+           view: camera.view,
+           proj: camera.proj,
+           camera_pos: camera.pos,
+           resolution: [resolution.width, resolution.height],
+           mouse_pos: self.mouse_pos,
+       };
+
+       // Only read through `CAMERA_BINDING` of the bindless set
+       let globals = match &self.bindless {
+           Some(_) if self.graph.uses_bindless() => {
+               self.uniforms.push(&self.ctx.device, &globals).expect("Error upload GlobalUniforms")
+           }
+           _ => 0,
+       };
+
+       self.graph.execute(&mut self.ctx, slot, self.bindless.as_ref(), globals, &self.scene, self.resources.clone());
+    }
+
+    pub fn pass_stats(&self) -> &[PassStats] {
+        self.graph.pass_stats()
+    }
+
+    pub fn toggle_vsync(&mut self) {
+        let vsync = !self.ctx.vsync();
+
+        if let Err(e) = self.ctx.set_vsync(vsync) {
+            log::warn!("Failed to switch vsync to {}: {}", vsync, e);
+        }
+    }
+
+    /// Cursor position in pixels, uploaded with the next frame's `GlobalUniforms`
+    pub fn set_mouse_pos(&mut self, x: f32, y: f32) {
+        self.mouse_pos = [x, y];
     }
 }
\ No newline at end of file